@@ -0,0 +1,87 @@
+//! The current time, behind a trait so tests can pick what "now" is.
+
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use axum::{
+    extract::{Extension, FromRequest, Query, RequestParts},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+
+use crate::State;
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The real time.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when told to.
+pub struct ManualClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl ManualClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now = *now + by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// The time a request should be answered as of. That's the state's clock,
+/// unless time overrides are allowed and the request has a `?now=` parameter.
+#[derive(Debug, Clone, Copy)]
+pub struct Now(pub DateTime<Utc>);
+
+#[derive(Deserialize)]
+struct NowQuery {
+    now: Option<DateTime<Utc>>,
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Now {
+    type Rejection = Response;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Extension(state) = Extension::<Arc<State>>::from_request(req)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        if state.allow_time_override {
+            let Query(query) = Query::<NowQuery>::from_request(req)
+                .await
+                .map_err(IntoResponse::into_response)?;
+
+            if let Some(now) = query.now {
+                return Ok(Self(now));
+            }
+        }
+
+        Ok(Self(state.clock.now()))
+    }
+}
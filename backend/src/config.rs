@@ -0,0 +1,555 @@
+//! Backend configuration, layered from built-in defaults, a TOML file,
+//! environment variables and command line flags, each overriding the last.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use clap::{Arg, Command, ErrorKind};
+use eyre::{bail, eyre, Context, Result};
+use reqwest::Url;
+
+use crate::telemetry::{TelemetryConfig, TraceExporter};
+
+/// Used when neither `--config` nor `CONFIG_PATH` name a config file. It's
+/// fine for this one not to exist.
+const DEFAULT_CONFIG_PATH: &str = "/etc/train-backend.toml";
+
+struct Setting {
+    /// Name in the config file, with `.` separating tables
+    key: &'static str,
+    env: &'static str,
+    default: Option<&'static str>,
+    /// Hidden when printing the config
+    secret: bool,
+    help: &'static str,
+}
+
+const SETTINGS: &[Setting] = &[
+    Setting {
+        key: "bind_address",
+        env: "BIND_ADDRESS",
+        default: Some("0.0.0.0:8088"),
+        secret: false,
+        help: "Address to serve HTTP on",
+    },
+    Setting {
+        key: "db_path",
+        env: "DB_PATH",
+        default: Some("/var/schedules.db"),
+        secret: false,
+        help: "GTFS schedule database",
+    },
+    Setting {
+        key: "api_key",
+        env: "API_KEY",
+        default: None,
+        secret: true,
+        help: "511.org API key",
+    },
+    Setting {
+        key: "realtime.provider",
+        env: "REALTIME_PROVIDER",
+        default: None,
+        secret: false,
+        help: "Live status source: none, 511 or replay. Defaults to 511 when api_key is set",
+    },
+    Setting {
+        key: "realtime.replay_dir",
+        env: "REALTIME_REPLAY_DIR",
+        default: None,
+        secret: false,
+        help: "Directory of recorded 511.org responses to replay",
+    },
+    Setting {
+        key: "realtime.replay_start",
+        env: "REALTIME_REPLAY_START",
+        default: None,
+        secret: false,
+        help: "Recorded time to start replaying from, defaulting to the first recording",
+    },
+    Setting {
+        key: "realtime.record_dir",
+        env: "REALTIME_RECORD_DIR",
+        default: None,
+        secret: false,
+        help: "Archive every 511.org response in this directory, for replay and test fixtures",
+    },
+    Setting {
+        key: "realtime.record_retention_days",
+        env: "REALTIME_RECORD_RETENTION_DAYS",
+        default: Some("7"),
+        secret: false,
+        help: "Days of recorded 511.org responses to keep",
+    },
+    Setting {
+        key: "static_dir",
+        env: "STATIC_FILE_PATH",
+        default: Some("/var/www/"),
+        secret: false,
+        help: "Directory holding the built frontend",
+    },
+    Setting {
+        key: "public_url",
+        env: "PUBLIC_URL",
+        default: None,
+        secret: false,
+        help: "Address the app is served from, like https://trains.example.com. Defaults to the Host of each request",
+    },
+    Setting {
+        key: "live_cache.ttl_seconds",
+        env: "LIVE_CACHE_TTL_SECONDS",
+        default: Some("120"),
+        secret: false,
+        help: "How long live status from 511.org is reused",
+    },
+    Setting {
+        key: "live_cache.rate_limit_backoff_seconds",
+        env: "LIVE_CACHE_RATE_LIMIT_BACKOFF_SECONDS",
+        default: Some("60"),
+        secret: false,
+        help: "How long to stop asking 511.org for live status after being rate limited",
+    },
+    Setting {
+        key: "push.vapid_key_path",
+        env: "VAPID_KEY_PATH",
+        default: Some("/var/vapid.key"),
+        secret: false,
+        help: "Key used to sign push notifications, generated if missing",
+    },
+    Setting {
+        key: "push.vapid_subject",
+        env: "VAPID_SUBJECT",
+        default: Some("mailto:trains@localhost"),
+        secret: false,
+        help: "Contact address given to push services",
+    },
+    Setting {
+        key: "push.reminders_path",
+        env: "REMINDERS_PATH",
+        default: Some("/var/reminders.json"),
+        secret: false,
+        help: "Where pending reminders are saved",
+    },
+    Setting {
+        key: "webhooks_path",
+        env: "WEBHOOKS_PATH",
+        default: Some("/var/webhooks.json"),
+        secret: false,
+        help: "Where webhook subscriptions are saved",
+    },
+    Setting {
+        key: "webhooks_admin_token",
+        env: "WEBHOOKS_ADMIN_TOKEN",
+        default: None,
+        secret: true,
+        help: "Bearer token needed to manage webhooks, which can't be managed without one",
+    },
+    Setting {
+        key: "telemetry.exporter",
+        env: "OTEL_TRACES_EXPORTER",
+        default: Some("none"),
+        secret: false,
+        help: "Trace exporter: none, stdout, otlp-grpc or otlp-http",
+    },
+    Setting {
+        key: "telemetry.endpoint",
+        env: "OTEL_EXPORTER_OTLP_ENDPOINT",
+        default: None,
+        secret: false,
+        help: "OTLP collector endpoint",
+    },
+    Setting {
+        key: "telemetry.sample_ratio",
+        env: "OTEL_TRACES_SAMPLER_ARG",
+        default: Some("1.0"),
+        secret: false,
+        help: "Fraction of traces to record",
+    },
+    Setting {
+        key: "telemetry.service_name",
+        env: "OTEL_SERVICE_NAME",
+        default: Some("train-backend"),
+        secret: false,
+        help: "Service name traces are reported under",
+    },
+    Setting {
+        key: "debug.allow_time_override",
+        env: "ALLOW_TIME_OVERRIDE",
+        default: Some("false"),
+        secret: false,
+        help: "Let API requests pick the current time with a ?now= parameter, for testing",
+    },
+    Setting {
+        key: "debug.allow_private_targets",
+        env: "ALLOW_PRIVATE_TARGETS",
+        default: Some("false"),
+        secret: false,
+        help: "Send push notifications and webhooks to any address, including private ones, for testing",
+    },
+];
+
+impl Setting {
+    /// Name of the command line flag, without the leading dashes.
+    fn flag(&self) -> String {
+        self.key.replace(&['.', '_'][..], "-")
+    }
+}
+
+/// Where a setting's value came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    File(PathBuf),
+    Env(&'static str),
+    Flag(String),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File(path) => write!(f, "config file {}", path.display()),
+            Source::Env(name) => write!(f, "env var {name}"),
+            Source::Flag(flag) => write!(f, "flag --{flag}"),
+        }
+    }
+}
+
+/// Where live status comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RealtimeConfig {
+    /// Schedule-only mode
+    None,
+    Api511 {
+        api_key: String,
+        /// Where to archive responses, if anywhere
+        record_dir: Option<PathBuf>,
+        record_retention_days: u32,
+    },
+    Replay {
+        dir: PathBuf,
+        start: Option<DateTime<Utc>>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub bind_address: SocketAddr,
+    pub db_path: PathBuf,
+    pub realtime: RealtimeConfig,
+    pub static_dir: PathBuf,
+    pub public_url: Option<Url>,
+    pub live_cache_ttl: Duration,
+    pub rate_limit_backoff: Duration,
+    pub vapid_key_path: PathBuf,
+    pub vapid_subject: String,
+    pub reminders_path: PathBuf,
+    pub webhooks_path: PathBuf,
+    pub webhooks_admin_token: Option<String>,
+    pub telemetry: TelemetryConfig,
+    pub allow_time_override: bool,
+    pub allow_private_targets: bool,
+}
+
+/// Raw setting values after layering, along with where each came from.
+pub struct Layers {
+    values: BTreeMap<&'static str, (String, Source)>,
+    pub print_config: bool,
+}
+
+impl Layers {
+    /// Layer the process's arguments and environment over the config file
+    /// and defaults.
+    pub fn from_process() -> Result<Self> {
+        Self::load(std::env::args(), &std::env::vars().collect())
+    }
+
+    pub fn load(
+        args: impl IntoIterator<Item = String>,
+        env: &HashMap<String, String>,
+    ) -> Result<Self> {
+        let flags: Vec<String> = SETTINGS.iter().map(Setting::flag).collect();
+
+        let matches = SETTINGS
+            .iter()
+            .zip(&flags)
+            .fold(command(), |command, (setting, flag)| {
+                command.arg(
+                    Arg::new(setting.key)
+                        .long(flag)
+                        .takes_value(true)
+                        .help(setting.help),
+                )
+            })
+            .try_get_matches_from(args)
+            .or_else(|e| match e.kind() {
+                ErrorKind::DisplayHelp | ErrorKind::DisplayVersion => e.exit(),
+                _ => Err(eyre!("{e}")),
+            })?;
+
+        let mut values = BTreeMap::new();
+
+        for setting in SETTINGS {
+            if let Some(default) = setting.default {
+                values.insert(setting.key, (default.to_owned(), Source::Default));
+            }
+        }
+
+        let config_path = match (matches.value_of("config"), env.get("CONFIG_PATH")) {
+            (Some(path), _) => Some(PathBuf::from(path)),
+            (None, Some(path)) => Some(PathBuf::from(path)),
+            (None, None) => None,
+        };
+
+        match config_path {
+            Some(path) => values.extend(read_file(&path)?),
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                values.extend(read_file(Path::new(DEFAULT_CONFIG_PATH))?)
+            }
+            None => {}
+        }
+
+        for setting in SETTINGS {
+            if let Some(value) = env.get(setting.env) {
+                values.insert(setting.key, (value.clone(), Source::Env(setting.env)));
+            }
+        }
+
+        for (setting, flag) in SETTINGS.iter().zip(flags) {
+            if let Some(value) = matches.value_of(setting.key) {
+                values.insert(setting.key, (value.to_owned(), Source::Flag(flag)));
+            }
+        }
+
+        Ok(Self {
+            values,
+            print_config: matches.is_present("print-config"),
+        })
+    }
+
+    /// A table of every setting's effective value and where it came from.
+    pub fn describe(&self) -> String {
+        let width = SETTINGS.iter().map(|s| s.key.len()).max().unwrap_or(0);
+
+        SETTINGS
+            .iter()
+            .map(|setting| match self.values.get(setting.key) {
+                Some((_, source)) if setting.secret => {
+                    format!("{:width$}  {:24}  ({source})\n", setting.key, "<hidden>")
+                }
+                Some((value, source)) => {
+                    format!("{:width$}  {value:24}  ({source})\n", setting.key)
+                }
+                None => format!("{:width$}  <unset>\n", setting.key),
+            })
+            .collect()
+    }
+
+    /// Parse and validate the layered values.
+    pub fn config(&self) -> Result<Config> {
+        let config = Config {
+            bind_address: self.parse("bind_address")?,
+            db_path: self.parse("db_path")?,
+            realtime: self.realtime()?,
+            static_dir: self.parse("static_dir")?,
+            public_url: self.parse_optional("public_url")?,
+            live_cache_ttl: Duration::from_secs(self.parse("live_cache.ttl_seconds")?),
+            rate_limit_backoff: Duration::from_secs(
+                self.parse("live_cache.rate_limit_backoff_seconds")?,
+            ),
+            vapid_key_path: self.parse("push.vapid_key_path")?,
+            vapid_subject: self.parse("push.vapid_subject")?,
+            reminders_path: self.parse("push.reminders_path")?,
+            webhooks_path: self.parse("webhooks_path")?,
+            webhooks_admin_token: self.parse_optional("webhooks_admin_token")?,
+            telemetry: TelemetryConfig {
+                exporter: self.parse::<TraceExporter>("telemetry.exporter")?,
+                endpoint: self.parse_optional("telemetry.endpoint")?,
+                sample_ratio: self.parse("telemetry.sample_ratio")?,
+                service_name: self.parse("telemetry.service_name")?,
+            },
+            allow_time_override: self.parse("debug.allow_time_override")?,
+            allow_private_targets: self.parse("debug.allow_private_targets")?,
+        };
+
+        for (key, value) in [
+            ("live_cache.ttl_seconds", config.live_cache_ttl.as_secs()),
+            (
+                "live_cache.rate_limit_backoff_seconds",
+                config.rate_limit_backoff.as_secs(),
+            ),
+        ] {
+            if value == 0 {
+                bail!(
+                    "{key} must be greater than 0 (set by {})",
+                    self.values[key].1
+                );
+            }
+        }
+
+        if !(0.0..=1.0).contains(&config.telemetry.sample_ratio) {
+            bail!(
+                "telemetry.sample_ratio must be between 0 and 1, got {} (set by {})",
+                config.telemetry.sample_ratio,
+                self.values["telemetry.sample_ratio"].1
+            );
+        }
+
+        Ok(config)
+    }
+
+    fn realtime(&self) -> Result<RealtimeConfig> {
+        let api_key: Option<String> = self.parse_optional("api_key")?;
+        let provider: Option<String> = self.parse_optional("realtime.provider")?;
+
+        let provider = match provider {
+            Some(provider) => provider,
+            None if api_key.is_some() => String::from("511"),
+            None => String::from("none"),
+        };
+
+        match provider.as_str() {
+            "none" => Ok(RealtimeConfig::None),
+            "511" => {
+                let api_key: String = self.parse("api_key")?;
+
+                if api_key.is_empty() {
+                    bail!("api_key must not be empty");
+                }
+
+                let record_retention_days = self.parse("realtime.record_retention_days")?;
+
+                if record_retention_days == 0 {
+                    bail!(
+                        "realtime.record_retention_days must be greater than 0 (set by {})",
+                        self.values["realtime.record_retention_days"].1
+                    );
+                }
+
+                Ok(RealtimeConfig::Api511 {
+                    api_key,
+                    record_dir: self.parse_optional("realtime.record_dir")?,
+                    record_retention_days,
+                })
+            }
+            "replay" => Ok(RealtimeConfig::Replay {
+                dir: self.parse("realtime.replay_dir")?,
+                start: self.parse_optional("realtime.replay_start")?,
+            }),
+            _ => bail!(
+                "unknown realtime provider {provider:?} from {}, expected one of none, 511, replay",
+                self.values["realtime.provider"].1
+            ),
+        }
+    }
+
+    fn parse<T>(&self, key: &str) -> Result<T>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        self.parse_optional(key)?.ok_or_else(|| {
+            let setting = SETTINGS.iter().find(|s| s.key == key).unwrap();
+
+            eyre!(
+                "{key} is required, set it in the config file, with the {} env var or with --{}",
+                setting.env,
+                setting.flag()
+            )
+        })
+    }
+
+    fn parse_optional<T>(&self, key: &str) -> Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        self.values
+            .get(key)
+            .map(|(value, source)| {
+                value
+                    .parse()
+                    .map_err(|e| eyre!("invalid {key} {value:?} from {source}: {e}"))
+            })
+            .transpose()
+    }
+}
+
+fn command<'help>() -> Command<'help> {
+    Command::new("train-backend")
+        .about("Caltrain schedules and live status")
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .takes_value(true)
+                .help("TOML config file, overriding the defaults. Defaults to $CONFIG_PATH"),
+        )
+        .arg(
+            Arg::new("print-config")
+                .long("print-config")
+                .help("Show the effective configuration and where each value came from, then exit"),
+        )
+}
+
+fn read_file(path: &Path) -> Result<Vec<(&'static str, (String, Source))>> {
+    let contents = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("failed to read config file {}", path.display()))?;
+    let table: toml::Value = toml::from_str(&contents)
+        .wrap_err_with(|| format!("failed to parse config file {}", path.display()))?;
+
+    let mut found = Vec::new();
+    flatten(path, "", &table, &mut found)?;
+
+    Ok(found)
+}
+
+/// Collect the settings in `value`, rejecting keys that aren't settings so
+/// typos don't go unnoticed.
+fn flatten(
+    path: &Path,
+    prefix: &str,
+    value: &toml::Value,
+    found: &mut Vec<(&'static str, (String, Source))>,
+) -> Result<()> {
+    let table = match value {
+        toml::Value::Table(table) => table,
+        _ => bail!("expected a table for {prefix} in {}", path.display()),
+    };
+
+    for (name, value) in table {
+        let key = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{prefix}.{name}")
+        };
+
+        if let toml::Value::Table(_) = value {
+            flatten(path, &key, value, found)?;
+            continue;
+        }
+
+        let setting = SETTINGS
+            .iter()
+            .find(|s| s.key == key)
+            .ok_or_else(|| eyre!("unknown setting {key} in {}", path.display()))?;
+
+        let value = match value {
+            toml::Value::String(s) => s.clone(),
+            toml::Value::Integer(i) => i.to_string(),
+            toml::Value::Float(f) => f.to_string(),
+            toml::Value::Boolean(b) => b.to_string(),
+            _ => bail!("{key} in {} must be a single value", path.display()),
+        };
+
+        found.push((setting.key, (value, Source::File(path.to_owned()))));
+    }
+
+    Ok(())
+}
@@ -1,22 +1,93 @@
 use std::collections::HashMap;
 
-use chrono::prelude::*;
-use chrono_tz::US::Pacific;
-use eyre::{Context, Result};
-use train_schedules_common::{Station, Stop};
+use chrono::{prelude::*, Duration};
+use chrono_tz::Tz;
+use eyre::{bail, eyre, Context, Result};
+use train_schedules_common::{Boarding, Station, Stop};
 
 #[derive(Clone, Debug)]
 pub struct Service {
-    pub start_date: Date<FixedOffset>,
-    pub end_date: Date<FixedOffset>,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
     pub id: String,
     pub weekdays: Vec<Weekday>,
+    /// Extra days the service runs, from calendar_dates.txt
+    pub added_dates: Vec<NaiveDate>,
+    /// Days the service doesn't run despite its calendar, like holidays
+    pub removed_dates: Vec<NaiveDate>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TripInfo {
+    pub trip_id: i64,
+    pub service_id: String,
+    pub route_name: String,
+    pub direction_id: i64,
+    pub headsign: String,
+}
+
+impl Service {
+    /// Whether trips on this service run on `date`, start and end dates
+    /// included.
+    pub fn runs_on(&self, date: NaiveDate) -> bool {
+        if self.removed_dates.contains(&date) {
+            return false;
+        }
+
+        self.added_dates.contains(&date)
+            || (self.start_date <= date
+                && date <= self.end_date
+                && self.weekdays.contains(&date.weekday()))
+    }
+}
+
+/// The day whose schedule is running at `now` in `timezone`, ignoring trips
+/// from the previous day that run past midnight.
+pub fn service_day(now: DateTime<Utc>, timezone: Tz) -> Date<Tz> {
+    now.with_timezone(&timezone).date()
+}
+
+/// The time GTFS stop times on `day` are counted from: noon minus 12 hours.
+/// That's midnight, except on days the clocks change.
+pub fn service_day_start(day: NaiveDate, timezone: Tz) -> DateTime<Tz> {
+    timezone
+        .ymd(day.year(), day.month(), day.day())
+        .and_hms(12, 0, 0)
+        - Duration::hours(12)
+}
+
+/// `time` with the UTC offset `timezone` has at that instant.
+pub fn localize<T: TimeZone>(time: DateTime<T>, timezone: Tz) -> DateTime<FixedOffset> {
+    let time = time.with_timezone(&timezone);
+
+    time.with_timezone(&time.offset().fix())
+}
+
+/// The time zone from agency.txt, which every agency in a feed has to share.
+pub fn agency_timezone(connection: &sqlite::Connection) -> Result<Tz> {
+    let mut stmt = connection
+        .prepare("select distinct agency_timezone from agency")
+        .wrap_err("prepare agency query")?;
+
+    let mut timezones = Vec::new();
+
+    while let sqlite::State::Row = stmt.next()? {
+        timezones.push(stmt.read::<String>(0)?);
+    }
+
+    match timezones.as_slice() {
+        [timezone] => timezone
+            .parse()
+            .map_err(|e| eyre!("invalid agency_timezone {timezone:?}: {e}")),
+        [] => bail!("no agency found in agency.txt"),
+        _ => bail!("agencies have different time zones: {timezones:?}"),
+    }
 }
 
 pub fn all_stations(connection: &sqlite::Connection) -> Result<Vec<Station>> {
     let mut stmt = connection.prepare(
         "
-            select station_id, stop_name, stop_code
+            select station_id, stop_name, stop_code, stop_lat, stop_lon
             from stops
         ",
     )?;
@@ -27,6 +98,8 @@ pub fn all_stations(connection: &sqlite::Connection) -> Result<Vec<Station>> {
         let station_id = stmt.read(0)?;
         let name = stmt.read(1)?;
         let stop_code = stmt.read(2)?;
+        let lat = stmt.read(3)?;
+        let lon = stmt.read(4)?;
 
         stations
             .entry(station_id)
@@ -34,6 +107,8 @@ pub fn all_stations(connection: &sqlite::Connection) -> Result<Vec<Station>> {
                 name,
                 station_id,
                 stop_codes: Vec::new(),
+                lat,
+                lon,
             })
             .stop_codes
             .push(stop_code);
@@ -46,15 +121,29 @@ pub fn all_stations(connection: &sqlite::Connection) -> Result<Vec<Station>> {
     Ok(stations)
 }
 
-pub fn all_stops(connection: &sqlite::Connection) -> Result<Vec<Stop>> {
-    let mut stmt = connection.prepare(
+/// Load every scheduled stop, with times anchored to `service_day`.
+pub fn all_stops(connection: &sqlite::Connection, service_day: Date<Tz>) -> Result<Vec<Stop>> {
+    // Both columns are optional in stop_times.txt, and empty means regular
+    let boarding = |column| -> Result<String> {
+        Ok(if has_column(connection, "stop_times", column)? {
+            format!("cast(coalesce(nullif({column}, ''), 0) as integer)")
+        } else {
+            String::from("0")
+        })
+    };
+
+    let mut stmt = connection.prepare(format!(
         "
-        select distinct stop_name, station_id, departure_time, arrival_time, stop_times.trip_id, service_id
+        select distinct stop_name, station_id, departure_time, arrival_time, stop_times.trip_id, service_id, stop_sequence, {}, {}, {}, direction_id, {}
         from stop_times
         join trips on trips.trip_id=stop_times.trip_id
         join stops on stop_times.stop_id = stops.stop_id
         ",
-    )?;
+        boarding("pickup_type")?,
+        boarding("drop_off_type")?,
+        platform_code(connection)?,
+        trip_headsign(connection)?,
+    ))?;
 
     let mut stops = Vec::new();
 
@@ -65,16 +154,28 @@ pub fn all_stops(connection: &sqlite::Connection) -> Result<Vec<Stop>> {
 
         let departure_str: String = stmt.read(2)?;
 
-        let departure = parse_time(&departure_str)?;
+        let departure = parse_time(&departure_str, service_day)?;
 
         let arrival_str: String = stmt.read(3)?;
 
-        let arrival = parse_time(&arrival_str)?;
+        let arrival = parse_time(&arrival_str, service_day)?;
 
         let trip_id = stmt.read(4)?;
 
         let service_id = stmt.read(5)?;
 
+        let stop_sequence = stmt.read(6)?;
+
+        let pickup_type = boarding_from_num(stmt.read(7)?)?;
+
+        let drop_off_type = boarding_from_num(stmt.read(8)?)?;
+
+        let platform: String = stmt.read(9)?;
+
+        let direction_id = stmt.read(10)?;
+
+        let headsign = stmt.read(11)?;
+
         stops.push(Stop {
             trip_id,
             station_id,
@@ -82,27 +183,96 @@ pub fn all_stops(connection: &sqlite::Connection) -> Result<Vec<Stop>> {
             arrival,
             departure,
             service_id,
+            stop_sequence,
+            pickup_type,
+            drop_off_type,
+            platform: Some(platform).filter(|p| !p.is_empty()),
+            direction_id,
+            headsign,
         });
     }
 
     Ok(stops)
 }
 
-fn parse_time(time: &str) -> Result<DateTime<FixedOffset>> {
+/// The platform of each stop code that has one.
+pub fn platforms(connection: &sqlite::Connection) -> Result<HashMap<i64, String>> {
+    let mut stmt = connection.prepare(format!(
+        "
+        select stop_code, {}
+        from stops
+        ",
+        platform_code(connection)?,
+    ))?;
+
+    let mut platforms = HashMap::new();
+
+    while let sqlite::State::Row = stmt.next()? {
+        let stop_code = stmt.read(0)?;
+        let platform: String = stmt.read(1)?;
+
+        if !platform.is_empty() {
+            platforms.insert(stop_code, platform);
+        }
+    }
+
+    Ok(platforms)
+}
+
+/// platform_code is optional in stops.txt
+fn platform_code(connection: &sqlite::Connection) -> Result<&'static str> {
+    Ok(if has_column(connection, "stops", "platform_code")? {
+        "coalesce(platform_code, '')"
+    } else {
+        "''"
+    })
+}
+
+/// trip_headsign is optional in trips.txt
+fn trip_headsign(connection: &sqlite::Connection) -> Result<&'static str> {
+    Ok(if has_column(connection, "trips", "trip_headsign")? {
+        "coalesce(trip_headsign, '')"
+    } else {
+        "''"
+    })
+}
+
+pub fn trips(connection: &sqlite::Connection) -> Result<Vec<TripInfo>> {
+    let mut stmt = connection
+        .prepare(format!(
+            "
+        select trip_id, service_id, route_long_name, direction_id, {}
+        from trips
+        join routes on routes.route_id = trips.route_id
+        ",
+            trip_headsign(connection)?,
+        ))
+        .wrap_err("prepare trip query")?;
+
+    let mut trips = Vec::new();
+
+    while let sqlite::State::Row = stmt.next()? {
+        trips.push(TripInfo {
+            trip_id: stmt.read(0)?,
+            service_id: stmt.read(1)?,
+            route_name: stmt.read(2)?,
+            direction_id: stmt.read(3)?,
+            headsign: stmt.read(4)?,
+        });
+    }
+
+    Ok(trips)
+}
+
+fn parse_time(time: &str, service_day: Date<Tz>) -> Result<DateTime<FixedOffset>> {
     let mut parts = time.split(':');
 
-    let mut add_days = 0;
-    let mut hour = parts
+    // Hours go past 24 for trips running after midnight
+    let hour = parts
         .next()
         .unwrap()
         .parse()
         .wrap_err_with(|| format!("failed to parse hour part from time value {time}"))?;
-
-    while hour >= 24 {
-        hour -= 24;
-        add_days += 1;
-    }
-
     let minute = parts
         .next()
         .unwrap()
@@ -114,13 +284,12 @@ fn parse_time(time: &str) -> Result<DateTime<FixedOffset>> {
         .parse()
         .wrap_err_with(|| format!("failed to parse second part from time value {time}"))?;
 
-    let time = Pacific
-        .from_utc_datetime(&Utc::now().naive_utc())
-        .date()
-        .and_hms(hour, minute, second)
-        + chrono::Duration::days(add_days);
+    let time = service_day_start(service_day.naive_local(), service_day.timezone())
+        + Duration::hours(hour)
+        + Duration::minutes(minute)
+        + Duration::seconds(second);
 
-    Ok(time.with_timezone(&FixedOffset::west(0)))
+    Ok(localize(time, service_day.timezone()))
 }
 
 pub fn services(connection: &sqlite::Connection) -> Result<Vec<Service>> {
@@ -171,18 +340,100 @@ pub fn services(connection: &sqlite::Connection) -> Result<Vec<Service>> {
             start_date,
             end_date,
             id,
+            added_dates: Vec::new(),
+            removed_dates: Vec::new(),
         })
     }
 
+    if has_table(connection, "calendar_dates")? {
+        add_calendar_dates(connection, &mut services)?;
+    }
+
     Ok(services)
 }
 
-fn date_from_num(x: i64) -> Date<FixedOffset> {
+/// Apply the exceptions in calendar_dates.txt to `services`, adding services
+/// that only run on the dates listed there.
+fn add_calendar_dates(connection: &sqlite::Connection, services: &mut Vec<Service>) -> Result<()> {
+    let mut stmt = connection
+        .prepare(
+            "
+        select service_id, date, exception_type
+        from calendar_dates
+        ",
+        )
+        .wrap_err("prepare calendar dates query")?;
+
+    while let sqlite::State::Row = stmt.next()? {
+        let id: String = stmt.read(0)?;
+        let date = date_from_num(stmt.read(1)?);
+        let exception_type: i64 = stmt.read(2)?;
+
+        let index = match services.iter().position(|s| s.id == id) {
+            Some(index) => index,
+            None => {
+                services.push(Service {
+                    start_date: date,
+                    end_date: date,
+                    id,
+                    weekdays: Vec::new(),
+                    added_dates: Vec::new(),
+                    removed_dates: Vec::new(),
+                });
+                services.len() - 1
+            }
+        };
+        let service = &mut services[index];
+
+        match exception_type {
+            1 => {
+                service.added_dates.push(date);
+                service.start_date = service.start_date.min(date);
+                service.end_date = service.end_date.max(date);
+            }
+            2 => service.removed_dates.push(date),
+            _ => bail!(
+                "unknown exception_type {exception_type} for service {}",
+                service.id
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+fn has_table(connection: &sqlite::Connection, name: &str) -> Result<bool> {
+    let mut stmt =
+        connection.prepare("select count(*) from sqlite_master where type='table' and name=?")?;
+    stmt.bind(1, name)?;
+    stmt.next()?;
+
+    Ok(stmt.read::<i64>(0)? > 0)
+}
+
+fn has_column(connection: &sqlite::Connection, table: &str, column: &str) -> Result<bool> {
+    let mut stmt = connection.prepare("select count(*) from pragma_table_info(?) where name=?")?;
+    stmt.bind(1, table)?;
+    stmt.bind(2, column)?;
+    stmt.next()?;
+
+    Ok(stmt.read::<i64>(0)? > 0)
+}
+
+fn boarding_from_num(x: i64) -> Result<Boarding> {
+    Ok(match x {
+        0 => Boarding::Regular,
+        1 => Boarding::None,
+        2 => Boarding::PhoneAgency,
+        3 => Boarding::CoordinateWithDriver,
+        _ => bail!("unknown pickup or drop off type {x}"),
+    })
+}
+
+fn date_from_num(x: i64) -> NaiveDate {
     let year = x / 10_000;
     let month = (x / 100) % 100;
     let day = x % 100;
 
-    Pacific
-        .ymd(year as i32, month as u32, day as u32)
-        .with_timezone(&FixedOffset::west(0))
+    NaiveDate::from_ymd(year as i32, month as u32, day as u32)
 }
@@ -16,13 +16,18 @@ impl From<eyre::Report> for Error {
 }
 
 pub fn eyre_into_response(e: impl Into<eyre::Report>) -> Response {
+    error_response(StatusCode::INTERNAL_SERVER_ERROR, e)
+}
+
+/// The JSON error body with some other status than a 500.
+pub fn error_response(status: StatusCode, e: impl Into<eyre::Report>) -> Response {
     let error = e.into();
     let json = json!({
         "error": format!("{error}"),
         "chain": error.chain().map(|e| format!("{e}")).collect::<Vec<String>>(),
     });
 
-    (StatusCode::INTERNAL_SERVER_ERROR, Json(json)).into_response()
+    (status, Json(json)).into_response()
 }
 
 impl IntoResponse for Error {
@@ -0,0 +1,18 @@
+//! Helpers for the pages the backend renders itself, rather than leaving to
+//! the frontend.
+
+use chrono::{DateTime, FixedOffset};
+
+/// `text` made safe to put in an HTML element or attribute.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// A time the way the frontend shows it, like `5:04 PM`.
+pub fn time(time: DateTime<FixedOffset>) -> String {
+    time.format("%l:%M %p").to_string().trim().to_owned()
+}
@@ -0,0 +1,267 @@
+//! Minimal RFC 5545 (iCalendar) writer used for the calendar exports.
+
+use chrono::{prelude::*, Duration};
+use chrono_tz::Tz;
+
+const PRODUCT_ID: &str = "-//lily-mara//train-schedules//EN";
+
+pub struct Calendar {
+    timezone: Tz,
+    events: Vec<Event>,
+}
+
+pub struct Event {
+    pub uid: String,
+    pub summary: String,
+    pub description: String,
+    pub location: String,
+    pub geo: Option<(f64, f64)>,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub recurrence: Option<Recurrence>,
+}
+
+/// Repeat an event every week on the given weekdays, up to and including the
+/// `until` date.
+pub struct Recurrence {
+    pub weekdays: Vec<Weekday>,
+    pub until: NaiveDate,
+    /// Days the event would repeat on but doesn't, like holidays
+    pub except: Vec<NaiveDate>,
+}
+
+impl Calendar {
+    /// Create an empty calendar whose event times are all local times in
+    /// `timezone`.
+    pub fn new(timezone: Tz) -> Self {
+        Self {
+            timezone,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    pub fn render(&self, now: DateTime<Utc>) -> String {
+        let mut lines = vec![
+            String::from("BEGIN:VCALENDAR"),
+            String::from("VERSION:2.0"),
+            format!("PRODID:{PRODUCT_ID}"),
+            String::from("CALSCALE:GREGORIAN"),
+            String::from("METHOD:PUBLISH"),
+        ];
+
+        if let Some((first, last)) = self.year_range() {
+            self.render_timezone(first, last, &mut lines);
+        }
+
+        for event in &self.events {
+            self.render_event(event, now, &mut lines);
+        }
+
+        lines.push(String::from("END:VCALENDAR"));
+
+        let mut out = String::new();
+        for line in lines {
+            fold_line(&line, &mut out);
+        }
+
+        out
+    }
+
+    fn year_range(&self) -> Option<(i32, i32)> {
+        let first = self.events.iter().map(|e| e.start.year()).min()?;
+        let last = self
+            .events
+            .iter()
+            .map(|e| match &e.recurrence {
+                Some(recurrence) => recurrence.until.year(),
+                None => e.end.year(),
+            })
+            .max()?;
+
+        Some((first, last))
+    }
+
+    fn render_event(&self, event: &Event, now: DateTime<Utc>, lines: &mut Vec<String>) {
+        let tzid = self.timezone.name();
+
+        lines.push(String::from("BEGIN:VEVENT"));
+        lines.push(format!("UID:{}", escape_text(&event.uid)));
+        lines.push(format!("DTSTAMP:{}", now.format("%Y%m%dT%H%M%SZ")));
+        lines.push(format!(
+            "DTSTART;TZID={tzid}:{}",
+            event.start.format("%Y%m%dT%H%M%S")
+        ));
+        lines.push(format!(
+            "DTEND;TZID={tzid}:{}",
+            event.end.format("%Y%m%dT%H%M%S")
+        ));
+
+        if let Some(recurrence) = &event.recurrence {
+            // UNTIL has to be given in UTC when DTSTART carries a TZID
+            let until = self
+                .timezone
+                .from_local_datetime(&recurrence.until.and_hms(23, 59, 59))
+                .earliest()
+                .map(|until| until.with_timezone(&Utc))
+                .unwrap_or_else(|| Utc.from_utc_datetime(&recurrence.until.and_hms(23, 59, 59)));
+
+            let days = recurrence
+                .weekdays
+                .iter()
+                .map(|day| weekday_code(*day))
+                .collect::<Vec<_>>()
+                .join(",");
+
+            lines.push(format!(
+                "RRULE:FREQ=WEEKLY;BYDAY={days};UNTIL={}",
+                until.format("%Y%m%dT%H%M%SZ")
+            ));
+
+            for day in &recurrence.except {
+                lines.push(format!(
+                    "EXDATE;TZID={tzid}:{}",
+                    day.and_time(event.start.time()).format("%Y%m%dT%H%M%S")
+                ));
+            }
+        }
+
+        lines.push(format!("SUMMARY:{}", escape_text(&event.summary)));
+        lines.push(format!("DESCRIPTION:{}", escape_text(&event.description)));
+        lines.push(format!("LOCATION:{}", escape_text(&event.location)));
+
+        if let Some((lat, lon)) = event.geo {
+            lines.push(format!("GEO:{lat:.6};{lon:.6}"));
+        }
+
+        lines.push(String::from("END:VEVENT"));
+    }
+
+    /// Describe the calendar's timezone with one STANDARD/DAYLIGHT component
+    /// per offset transition between the start of `first_year` and the end of
+    /// `last_year`.
+    fn render_timezone(&self, first_year: i32, last_year: i32, lines: &mut Vec<String>) {
+        let tz = self.timezone;
+
+        lines.push(String::from("BEGIN:VTIMEZONE"));
+        lines.push(format!("TZID:{}", tz.name()));
+
+        let mut time = NaiveDate::from_ymd(first_year, 1, 1).and_hms(0, 0, 0);
+        let end = NaiveDate::from_ymd(last_year + 1, 1, 1).and_hms(0, 0, 0);
+
+        let initial = tz.offset_from_utc_datetime(&time);
+        let mut offset = initial.fix().local_minus_utc();
+        let mut transitions = 0;
+
+        while time < end {
+            let next = next_transition(tz, time, std::cmp::min(time + Duration::days(1), end));
+            let next_offset = tz.offset_from_utc_datetime(&next);
+            let next_seconds = next_offset.fix().local_minus_utc();
+
+            if next_seconds != offset {
+                let kind = if next_seconds > offset {
+                    "DAYLIGHT"
+                } else {
+                    "STANDARD"
+                };
+                // DTSTART is the wall-clock time the transition happens at,
+                // expressed in the offset in effect before it
+                let onset = next + Duration::seconds(offset as i64);
+
+                lines.push(format!("BEGIN:{kind}"));
+                lines.push(format!("DTSTART:{}", onset.format("%Y%m%dT%H%M%S")));
+                lines.push(format!("TZOFFSETFROM:{}", format_offset(offset)));
+                lines.push(format!("TZOFFSETTO:{}", format_offset(next_seconds)));
+                lines.push(format!("TZNAME:{next_offset}"));
+                lines.push(format!("END:{kind}"));
+
+                offset = next_seconds;
+                transitions += 1;
+            }
+
+            time = next;
+        }
+
+        if transitions == 0 {
+            lines.push(String::from("BEGIN:STANDARD"));
+            lines.push(String::from("DTSTART:19700101T000000"));
+            lines.push(format!("TZOFFSETFROM:{}", format_offset(offset)));
+            lines.push(format!("TZOFFSETTO:{}", format_offset(offset)));
+            lines.push(format!("TZNAME:{initial}"));
+            lines.push(String::from("END:STANDARD"));
+        }
+
+        lines.push(String::from("END:VTIMEZONE"));
+    }
+}
+
+/// The first UTC time after `from` and up to `to` whose offset in `tz` differs
+/// from the one at `from`, or `to` if the offset doesn't change in between.
+/// Offsets change at most once a day, so halving the range finds the change
+/// without checking every hour of it.
+fn next_transition(tz: Tz, from: NaiveDateTime, to: NaiveDateTime) -> NaiveDateTime {
+    let offset = tz.offset_from_utc_datetime(&from).fix();
+    if tz.offset_from_utc_datetime(&to).fix() == offset {
+        return to;
+    }
+
+    let (mut before, mut after) = (from, to);
+    while after - before > Duration::seconds(1) {
+        let middle = before + (after - before) / 2;
+
+        if tz.offset_from_utc_datetime(&middle).fix() == offset {
+            before = middle;
+        } else {
+            after = middle;
+        }
+    }
+
+    after
+}
+
+fn weekday_code(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+fn format_offset(seconds: i32) -> String {
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let minutes = seconds.abs() / 60;
+
+    format!("{sign}{:02}{:02}", minutes / 60, minutes % 60)
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Write `line` terminated by CRLF, folding it so that no physical line is
+/// longer than 75 octets.
+fn fold_line(line: &str, out: &mut String) {
+    let mut width = 0;
+
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+
+        width += c.len_utf8();
+        out.push(c);
+    }
+
+    out.push_str("\r\n");
+}
@@ -0,0 +1,243 @@
+use std::{collections::HashMap, path::Path, sync::Arc, time::Duration};
+
+use axum::{
+    body::Body,
+    extract::Extension,
+    http::Request,
+    response::Response,
+    routing::{delete, get, get_service, post},
+    AddExtensionLayer, Json, Router,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use clock::Clock;
+use db::{Service, TripInfo};
+use metrics::{Metrics, MetricsLayer};
+use opentelemetry::trace::SpanKind;
+use push::VapidKey;
+use realtime::{RealtimeProvider, UnmatchedVisit};
+use reminders::Reminders;
+use reqwest::{Client, Url};
+use tokio::sync::RwLock;
+use tower_http::{
+    services::{ServeDir, ServeFile},
+    trace::TraceLayer,
+};
+use tracing::{info_span, Span};
+use train_schedules_common::*;
+use ttl_cache::TtlCache;
+use webhooks::Webhooks;
+
+pub mod clock;
+pub mod config;
+pub mod db;
+pub mod error;
+pub mod html;
+pub mod ical;
+pub mod metrics;
+pub mod outbound;
+pub mod push;
+pub mod realtime;
+pub mod reminders;
+pub mod routes;
+pub mod telemetry;
+pub mod text;
+pub mod types;
+pub mod webhooks;
+
+/// The whole web app, serving the API, metrics and the frontend's files
+/// from `static_dir`.
+pub fn app(state: Arc<State>, static_dir: &Path) -> Router {
+    let metrics = state.metrics.clone();
+
+    let api_routes =
+        Router::new()
+            .route(
+                "/stations",
+                get(|Extension(state): Extension<Arc<State>>| async move {
+                    Json(state.stations.clone())
+                }),
+            )
+            .route("/upcoming-trips", get(routes::upcoming::upcoming_trips))
+            .route("/stations/nearby", get(routes::stations::nearby))
+            .route("/schedule-bundle", get(routes::bundle::schedule_bundle))
+            .route(
+                "/upcoming-trips.ics",
+                get(routes::calendar::upcoming_calendar),
+            )
+            .route("/timetable", get(routes::timetable::timetable))
+            .route("/oembed", get(routes::widget::oembed))
+            .route("/trip", get(routes::trip::trip))
+            .route("/trip/:id", get(routes::calendar::trip_calendar))
+            .route("/stations/live", get(routes::live::live_station))
+            .route("/stations/:id/live", get(routes::live::station_live))
+            .route("/stations/:id/board", get(routes::board::board))
+            .route(
+                "/stations/:id/board/events",
+                get(routes::board::board_events),
+            )
+            .route("/trip/:id/live", get(routes::live::trip_live))
+            .route(
+                "/debug/unmatched-visits",
+                get(routes::live::unmatched_visits),
+            )
+            .route("/push/public-key", get(routes::reminders::public_key))
+            .route("/reminders", post(routes::reminders::create_reminder))
+            .route("/reminders/:id", delete(routes::reminders::delete_reminder))
+            .route(
+                "/webhooks",
+                get(routes::webhooks::list_webhooks).post(routes::webhooks::create_webhook),
+            )
+            .route("/webhooks/:id", delete(routes::webhooks::delete_webhook))
+            .route(
+                "/webhooks/:id/deliveries",
+                get(routes::webhooks::webhook_deliveries),
+            );
+
+    Router::new()
+        .nest("/api", api_routes)
+        .route("/metrics", get(routes::metrics::metrics))
+        .route("/embed/station/:start", get(routes::widget::station_widget))
+        .route(
+            "/embed/station/:start/:end",
+            get(routes::widget::twostop_widget),
+        )
+        .route("/c/station/:start", get(routes::pages::station_page))
+        .route("/c/station/:start/:end", get(routes::pages::twostop_page))
+        .route("/c/trip/:id", get(routes::pages::trip_page))
+        .route(
+            "/c/",
+            get_service(ServeFile::new(static_dir.join("index.html")))
+                .handle_error(|e: std::io::Error| async move { error::eyre_into_response(e) }),
+        )
+        .route(
+            "/sw.js",
+            get_service(ServeFile::new(static_dir.join("sw.js")))
+                .handle_error(|e: std::io::Error| async move { error::eyre_into_response(e) }),
+        )
+        .route(
+            "/",
+            get_service(ServeDir::new(static_dir))
+                .handle_error(|e: std::io::Error| async move { error::eyre_into_response(e) }),
+        )
+        .layer(AddExtensionLayer::new(state))
+        .layer(AddExtensionLayer::new(routes::pages::AppShell::new(
+            static_dir,
+        )))
+        .layer(MetricsLayer::new(metrics))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(|request: &Request<Body>| http_span(request, SpanKind::Server))
+                .on_response(|response: &Response, _duration: Duration, span: &Span| {
+                    http_span_response(response, span)
+                }),
+        )
+}
+
+fn http_span<B>(request: &Request<B>, kind: SpanKind) -> Span {
+    let span = info_span!(
+        "http request",
+        name = &format!("{} {}", request.method(), request.uri().path())[..],
+        http.target = request.uri().path(),
+        http.url = tracing::field::display(&request.uri()),
+        http.method = request.method().as_str(),
+        http.status_code = tracing::field::Empty,
+        otel.kind = %kind,
+    );
+
+    telemetry::set_remote_parent(&span, request.headers());
+
+    span
+}
+
+fn http_span_response<B>(response: &axum::http::Response<B>, span: &Span) {
+    span.record("http.status_code", &response.status().as_u16());
+}
+
+pub type LiveStatusCache = Arc<RwLock<TtlCache<(), Vec<Stop>>>>;
+
+pub struct State {
+    pub stations: Vec<Station>,
+    /// Platform of each stop code, from platform_code in stops.txt
+    pub platforms: HashMap<i64, String>,
+    pub stops: Vec<Stop>,
+    pub client: Client,
+    pub realtime: Box<dyn RealtimeProvider>,
+    pub live_status_cache: LiveStatusCache,
+    /// Visits from the last live status update that didn't match a trip
+    pub unmatched_visits: RwLock<Vec<UnmatchedVisit>>,
+    /// How long live status is cached for
+    pub live_cache_ttl: Duration,
+    /// How long to stop asking for live status after being rate limited
+    pub rate_limit_backoff: Duration,
+    pub services: Vec<Service>,
+    pub trips: Vec<TripInfo>,
+    /// The day the times in `stops` are anchored to
+    pub service_day: NaiveDate,
+    /// The agency's time zone, from agency.txt. Schedule dates and times are
+    /// local to it
+    pub timezone: Tz,
+    pub reminders: Reminders,
+    /// Key used to sign push notifications
+    pub vapid: VapidKey,
+    pub webhooks: Webhooks,
+    pub metrics: Arc<Metrics>,
+    pub clock: Arc<dyn Clock>,
+    /// Whether requests may pick the current time, see [`clock::Now`]
+    pub allow_time_override: bool,
+    /// Whether push notifications and webhooks may be sent to private
+    /// addresses, and push notifications to services other than the known
+    /// push services
+    pub allow_private_targets: bool,
+    /// Where the app is served from, if it's configured
+    pub public_url: Option<Url>,
+}
+
+impl State {
+    /// The service day running at `now`, in the agency's time zone.
+    pub fn today(&self, now: DateTime<Utc>) -> NaiveDate {
+        db::service_day(now, self.timezone).naive_local()
+    }
+
+    /// Whether service `service_id` runs on `day`.
+    pub fn runs_on(&self, service_id: &str, day: NaiveDate) -> bool {
+        self.services
+            .iter()
+            .any(|s| s.id == service_id && s.runs_on(day))
+    }
+
+    /// Stops of trip `trip_id` around `now`: yesterday's run while it's
+    /// still going after midnight, otherwise today's. Empty when it runs on
+    /// neither.
+    pub fn trip_stops(&self, trip_id: i64, now: DateTime<Utc>) -> Vec<Stop> {
+        let today = self.today(now);
+        let stops_on = |day: NaiveDate| {
+            self.stops_on(day, |s| {
+                s.trip_id == trip_id && self.runs_on(&s.service_id, day)
+            })
+        };
+
+        let yesterday = stops_on(today.pred());
+        if yesterday.iter().any(|s| s.arrival > now) {
+            return yesterday;
+        }
+
+        stops_on(today)
+    }
+
+    /// Scheduled stops matching `filter`, moved from `service_day` to `day`.
+    pub fn stops_on(&self, day: NaiveDate, filter: impl Fn(&Stop) -> bool) -> Vec<Stop> {
+        let shift = db::service_day_start(day, self.timezone)
+            - db::service_day_start(self.service_day, self.timezone);
+
+        self.stops
+            .iter()
+            .filter(|s| filter(s))
+            .map(|s| Stop {
+                arrival: db::localize(s.arrival + shift, self.timezone),
+                departure: db::localize(s.departure + shift, self.timezone),
+                ..s.clone()
+            })
+            .collect()
+    }
+}
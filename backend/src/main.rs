@@ -1,135 +1,117 @@
-use axum::{
-    body::Body,
-    extract::Extension,
-    http::Request,
-    response::Response,
-    routing::{get, get_service},
-    AddExtensionLayer, Json, Router,
-};
-use db::Service;
 use eyre::{Context, Result};
-use opentelemetry::trace::SpanKind;
 use reqwest::Client;
-use std::{env, sync::Arc, time::Duration};
+use std::sync::Arc;
 use tokio::sync::RwLock;
 
-use tower_http::{
-    services::{ServeDir, ServeFile},
-    trace::TraceLayer,
+use train_backend::{
+    clock::{Clock, SystemClock},
+    config::{Layers, RealtimeConfig},
+    db,
+    metrics::Metrics,
+    push::VapidKey,
+    realtime::{Api511Provider, NoopProvider, RealtimeProvider, Recorder, ReplayProvider},
+    reminders::Reminders,
+    telemetry,
+    webhooks::{RetryPolicy, Webhooks},
+    State,
 };
-use tracing::{info_span, Span};
-use tracing_subscriber::{fmt::format::FmtSpan, prelude::*, EnvFilter, Registry};
-use train_schedules_common::*;
 use ttl_cache::TtlCache;
 
-mod db;
-mod error;
-mod routes;
-mod types;
-
-type LiveStatusCache = Arc<RwLock<TtlCache<(), Vec<Stop>>>>;
-
-pub struct State {
-    pub stations: Vec<Station>,
-    pub stops: Vec<Stop>,
-    pub client: Client,
-    pub api_key: String,
-    pub live_status_cache: LiveStatusCache,
-    pub services: Vec<Service>,
-}
-
 #[tokio::main]
 async fn main() -> Result<()> {
     let _ = dotenv::dotenv();
 
     color_backtrace::install();
 
-    let tracer = opentelemetry_otlp::new_pipeline()
-        .tracing()
-        .with_exporter(opentelemetry_otlp::new_exporter().tonic())
-        .install_batch(opentelemetry::runtime::Tokio)?;
+    let layers = Layers::from_process()?;
 
-    let telemetry = tracing_opentelemetry::layer().with_tracer(tracer);
+    if layers.print_config {
+        print!("{}", layers.describe());
 
-    Registry::default()
-        .with(EnvFilter::from_default_env())
-        .with(telemetry)
-        .with(tracing_subscriber::fmt::layer().with_span_events(FmtSpan::EXIT))
-        .init();
+        return match layers.config() {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.wrap_err("configuration is invalid")),
+        };
+    }
 
-    let db_path = env::var("DB_PATH").unwrap_or_else(|_| "/var/schedules.db".to_owned());
-    let api_key = env::var("API_KEY").wrap_err("API_KEY environment variable is required")?;
-    // let static_file_path = env::var("STATIC_FILE_PATH");
+    let config = layers.config()?;
+
+    telemetry::init(&config.telemetry)?;
 
     let connection =
-        sqlite::Connection::open(&db_path).wrap_err("failed to open sqlite connection")?;
+        sqlite::Connection::open(&config.db_path).wrap_err("failed to open sqlite connection")?;
+
+    let live_status_cache = Arc::new(RwLock::new(TtlCache::new(1)));
+
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+    let timezone = db::agency_timezone(&connection)?;
+    let service_day = db::service_day(clock.now(), timezone);
+
+    let stops = db::all_stops(&connection, service_day)?;
+    let services = db::services(&connection)?;
+    let trips = db::trips(&connection)?;
+
+    let metrics = Arc::new(Metrics::new());
+    metrics.set_schedule(&stops, &trips, &services, timezone);
 
-    let live_status_cache = Arc::new(RwLock::new(TtlCache::new(50)));
+    let client = Client::new();
+
+    let realtime: Box<dyn RealtimeProvider> = match &config.realtime {
+        RealtimeConfig::None => Box::new(NoopProvider),
+        RealtimeConfig::Api511 {
+            api_key,
+            record_dir,
+            record_retention_days,
+        } => {
+            let mut provider =
+                Api511Provider::new(client.clone(), api_key.clone(), metrics.clone());
+
+            if let Some(dir) = record_dir {
+                provider =
+                    provider.with_recorder(Recorder::new(dir.clone(), *record_retention_days));
+            }
+
+            Box::new(provider)
+        }
+        RealtimeConfig::Replay { dir, start } => {
+            Box::new(ReplayProvider::load(dir, *start, clock.clone())?)
+        }
+    };
 
     let state = Arc::new(State {
-        api_key: api_key.clone(),
-        client: Client::new(),
+        client,
+        realtime,
         live_status_cache: live_status_cache.clone(),
+        unmatched_visits: RwLock::new(Vec::new()),
+        live_cache_ttl: config.live_cache_ttl,
+        rate_limit_backoff: config.rate_limit_backoff,
         stations: db::all_stations(&connection)?,
-        stops: db::all_stops(&connection)?,
-        services: db::services(&connection)?,
+        platforms: db::platforms(&connection)?,
+        stops,
+        services,
+        trips,
+        service_day: service_day.naive_local(),
+        timezone,
+        reminders: Reminders::load(config.reminders_path.clone())?,
+        vapid: VapidKey::load_or_generate(&config.vapid_key_path, config.vapid_subject.clone())?,
+        webhooks: Webhooks::load(config.webhooks_path.clone(), RetryPolicy::default())?
+            .with_admin_token(config.webhooks_admin_token.clone())
+            .with_private_targets(config.allow_private_targets),
+        metrics: metrics.clone(),
+        clock,
+        allow_time_override: config.allow_time_override,
+        allow_private_targets: config.allow_private_targets,
+        public_url: config.public_url.clone(),
     });
 
-    let api_routes =
-        Router::new()
-            .route(
-                "/stations",
-                get(|Extension(state): Extension<Arc<State>>| async move {
-                    Json(state.stations.clone())
-                }),
-            )
-            .route(
-                "/upcoming-trips",
-                get(crate::routes::upcoming::upcoming_trips),
-            )
-            .route("/trip", get(crate::routes::trip::trip))
-            .route("/stations/live", get(crate::routes::live::live_station));
-
-    let app = Router::new()
-        .nest("/api", api_routes)
-        .route(
-            "/c/",
-            get_service(ServeFile::new("/var/www/index.html"))
-                .handle_error(|e: std::io::Error| async move { error::eyre_into_response(e) }),
-        )
-        .route(
-            "/",
-            get_service(ServeDir::new("/var/www/"))
-                .handle_error(|e: std::io::Error| async move { error::eyre_into_response(e) }),
-        )
-        .layer(AddExtensionLayer::new(state))
-        .layer(
-            TraceLayer::new_for_http()
-                .make_span_with(|request: &Request<Body>| http_span(request, SpanKind::Server))
-                .on_response(|response: &Response, _duration: Duration, span: &Span| {
-                    http_span_response(response, span)
-                }),
-        );
-
-    axum::Server::bind(&"0.0.0.0:8088".parse()?)
+    tokio::spawn(train_backend::reminders::run_scheduler(state.clone()));
+    tokio::spawn(train_backend::webhooks::run_poller(state.clone()));
+
+    let app = train_backend::app(state, &config.static_dir);
+
+    axum::Server::bind(&config.bind_address)
         .serve(app.into_make_service())
         .await?;
 
     Ok(())
 }
-
-fn http_span<B>(request: &axum::http::Request<B>, kind: SpanKind) -> Span {
-    info_span!(
-        "http request",
-        name = &format!("{} {}", request.method(), request.uri().path())[..],
-        http.target = request.uri().path(),
-        http.url = tracing::field::display(&request.uri()),
-        http.method = request.method().as_str(),
-        http.status_code = tracing::field::Empty,
-        otel.kind = %kind,
-    )
-}
-
-fn http_span_response<B>(response: &axum::http::Response<B>, span: &Span) {
-    span.record("http.status_code", &response.status().as_u16());
-}
@@ -0,0 +1,352 @@
+//! Prometheus metrics, served from `/metrics`.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use axum::http::{Request, Response};
+use chrono::{prelude::*, Duration};
+use chrono_tz::Tz;
+use eyre::Result;
+use prometheus::{
+    Encoder, Gauge, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    Opts, Registry, TextEncoder,
+};
+use tower::{Layer, Service as TowerService};
+use train_schedules_common::Stop;
+
+use crate::{
+    db::{Service, TripInfo},
+    realtime::Unmatched,
+};
+
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    upstream_requests: IntCounterVec,
+    upstream_request_duration: Histogram,
+    live_cache_hits: IntCounter,
+    live_cache_misses: IntCounter,
+    live_cache_refreshed: Gauge,
+    live_cache_age: Gauge,
+    unmatched_visits: IntCounterVec,
+    stops: IntGauge,
+    trips: IntGauge,
+    services: IntGauge,
+    feed_expiry: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests served"),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time taken to serve HTTP requests",
+            ),
+            &["method", "route"],
+        )
+        .unwrap();
+        let upstream_requests = IntCounterVec::new(
+            Opts::new(
+                "upstream_511_requests_total",
+                "Requests made to the 511.org API, by HTTP status",
+            ),
+            &["status"],
+        )
+        .unwrap();
+        let upstream_request_duration = Histogram::with_opts(HistogramOpts::new(
+            "upstream_511_request_duration_seconds",
+            "Time taken by requests to the 511.org API",
+        ))
+        .unwrap();
+        let live_cache_hits = IntCounter::new(
+            "live_cache_hits_total",
+            "Live status requests answered from the cache",
+        )
+        .unwrap();
+        let live_cache_misses = IntCounter::new(
+            "live_cache_misses_total",
+            "Live status requests that had to go to the 511.org API",
+        )
+        .unwrap();
+        let live_cache_refreshed = Gauge::new(
+            "live_cache_refreshed_timestamp_seconds",
+            "When live status was last fetched from the 511.org API",
+        )
+        .unwrap();
+        let live_cache_age = Gauge::new(
+            "live_cache_age_seconds",
+            "Time since live status was last fetched from the 511.org API",
+        )
+        .unwrap();
+        let unmatched_visits = IntCounterVec::new(
+            Opts::new(
+                "realtime_unmatched_visits_total",
+                "Realtime visits that couldn't be matched to a scheduled trip, by reason",
+            ),
+            &["reason"],
+        )
+        .unwrap();
+        let stops = IntGauge::new("schedule_stops", "Scheduled stops loaded").unwrap();
+        let trips = IntGauge::new("schedule_trips", "Scheduled trips loaded").unwrap();
+        let services = IntGauge::new("schedule_services", "Service calendars loaded").unwrap();
+        let feed_expiry = IntGauge::new(
+            "schedule_feed_expiry_timestamp_seconds",
+            "When the last service calendar in the loaded schedule ends",
+        )
+        .unwrap();
+
+        for collector in [
+            Box::new(http_requests.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(http_request_duration.clone()),
+            Box::new(upstream_requests.clone()),
+            Box::new(upstream_request_duration.clone()),
+            Box::new(live_cache_hits.clone()),
+            Box::new(live_cache_misses.clone()),
+            Box::new(live_cache_refreshed.clone()),
+            Box::new(live_cache_age.clone()),
+            Box::new(unmatched_visits.clone()),
+            Box::new(stops.clone()),
+            Box::new(trips.clone()),
+            Box::new(services.clone()),
+            Box::new(feed_expiry.clone()),
+        ] {
+            registry
+                .register(collector)
+                .expect("metric names are unique");
+        }
+
+        Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            upstream_requests,
+            upstream_request_duration,
+            live_cache_hits,
+            live_cache_misses,
+            live_cache_refreshed,
+            live_cache_age,
+            unmatched_visits,
+            stops,
+            trips,
+            services,
+            feed_expiry,
+        }
+    }
+
+    /// Record the size of the loaded schedule and when it runs out, which is
+    /// midnight in `timezone` after the last day of service.
+    pub fn set_schedule(
+        &self,
+        stops: &[Stop],
+        trips: &[TripInfo],
+        services: &[Service],
+        timezone: Tz,
+    ) {
+        self.stops.set(stops.len() as i64);
+        self.trips.set(trips.len() as i64);
+        self.services.set(services.len() as i64);
+
+        let last_day = services.iter().map(|s| s.end_date).max();
+
+        if let Some(last_day) = last_day {
+            let expiry = (last_day + Duration::days(1)).and_hms(0, 0, 0);
+
+            if let Some(expiry) = timezone.from_local_datetime(&expiry).earliest() {
+                self.feed_expiry.set(expiry.timestamp());
+            }
+        }
+    }
+
+    pub fn observe_request(&self, method: &str, route: &str, status: u16, elapsed: f64) {
+        self.http_requests
+            .with_label_values(&[method, route, &status.to_string()])
+            .inc();
+        self.http_request_duration
+            .with_label_values(&[method, route])
+            .observe(elapsed);
+    }
+
+    /// Record a request to 511.org, with `status` as `None` if no response was
+    /// received.
+    pub fn observe_upstream(&self, status: Option<u16>, elapsed: f64) {
+        let status = status.map_or_else(|| String::from("error"), |s| s.to_string());
+
+        self.upstream_requests.with_label_values(&[&status]).inc();
+        self.upstream_request_duration.observe(elapsed);
+    }
+
+    pub fn unmatched_visit(&self, reason: Unmatched) {
+        self.unmatched_visits
+            .with_label_values(&[reason.as_str()])
+            .inc();
+    }
+
+    pub fn live_cache_hit(&self) {
+        self.live_cache_hits.inc();
+    }
+
+    pub fn live_cache_miss(&self) {
+        self.live_cache_misses.inc();
+    }
+
+    pub fn live_cache_refreshed(&self, at: DateTime<Utc>) {
+        self.live_cache_refreshed.set(at.timestamp() as f64);
+    }
+
+    /// Render every metric in the Prometheus text format.
+    pub fn render(&self, now: DateTime<Utc>) -> Result<String> {
+        let refreshed = self.live_cache_refreshed.get();
+
+        if refreshed > 0.0 {
+            self.live_cache_age.set(now.timestamp() as f64 - refreshed);
+        }
+
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Every route in [`crate::app`], which requests are counted under
+const ROUTES: &[&str] = &[
+    "/",
+    "/metrics",
+    "/sw.js",
+    "/c/",
+    "/c/station/:start",
+    "/c/station/:start/:end",
+    "/c/trip/:id",
+    "/embed/station/:start",
+    "/embed/station/:start/:end",
+    "/api/stations",
+    "/api/upcoming-trips",
+    "/api/stations/nearby",
+    "/api/schedule-bundle",
+    "/api/upcoming-trips.ics",
+    "/api/timetable",
+    "/api/oembed",
+    "/api/trip",
+    "/api/trip/:id",
+    "/api/stations/live",
+    "/api/stations/:id/live",
+    "/api/stations/:id/board",
+    "/api/stations/:id/board/events",
+    "/api/trip/:id/live",
+    "/api/debug/unmatched-visits",
+    "/api/push/public-key",
+    "/api/reminders",
+    "/api/reminders/:id",
+    "/api/webhooks",
+    "/api/webhooks/:id",
+    "/api/webhooks/:id/deliveries",
+];
+
+/// The route that serves a request path, so ids don't each get their own
+/// time series. Other paths outside the API are files from the static
+/// directory, and the rest share `other`, so there's a fixed number of labels
+/// whatever gets requested.
+pub fn route_label(path: &str) -> &'static str {
+    let segments: Vec<&str> = path.split('/').collect();
+
+    let route = ROUTES.iter().find(|route| {
+        let route: Vec<&str> = route.split('/').collect();
+
+        route.len() == segments.len()
+            && route
+                .iter()
+                .zip(&segments)
+                .all(|(r, s)| r == s || (r.starts_with(':') && !s.is_empty()))
+    });
+
+    match route {
+        Some(route) => route,
+        None if path == "/api" || path.starts_with("/api/") => "other",
+        None => "static",
+    }
+}
+
+/// Records the count and latency of every request.
+#[derive(Clone)]
+pub struct MetricsLayer {
+    metrics: Arc<Metrics>,
+}
+
+impl MetricsLayer {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self { metrics }
+    }
+}
+
+impl<S> Layer<S> for MetricsLayer {
+    type Service = MetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MetricsService {
+            inner,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct MetricsService<S> {
+    inner: S,
+    metrics: Arc<Metrics>,
+}
+
+impl<S, ReqBody, ResBody> TowerService<Request<ReqBody>> for MetricsService<S>
+where
+    S: TowerService<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let method = request.method().to_string();
+        let route = route_label(request.uri().path());
+        let metrics = self.metrics.clone();
+        let start = Instant::now();
+
+        let response = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = response.await?;
+
+            metrics.observe_request(
+                &method,
+                route,
+                response.status().as_u16(),
+                start.elapsed().as_secs_f64(),
+            );
+
+            Ok(response)
+        })
+    }
+}
@@ -0,0 +1,96 @@
+//! Requests to urls that users choose, like webhook targets and push
+//! endpoints. These are only sent to public addresses, so they can't be
+//! pointed at the server itself or the network it's on.
+
+use std::net::{IpAddr, SocketAddr};
+
+use eyre::{bail, eyre, Context, Result};
+use reqwest::{redirect, Client, Url};
+
+/// Resolve `url`'s host, making sure every address it resolves to is public.
+/// Returns the address to connect to.
+pub async fn resolve_public(url: &Url) -> Result<SocketAddr> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| eyre!("url {url} has no host"))?;
+    // IPv6 addresses come in brackets
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    let addresses = tokio::net::lookup_host((host, port))
+        .await
+        .wrap_err_with(|| format!("failed to resolve url {url}"))?
+        .collect::<Vec<_>>();
+
+    if let Some(address) = addresses.iter().find(|a| !is_public(a.ip())) {
+        bail!(
+            "url {url} resolves to {}, which isn't a public address",
+            address.ip()
+        );
+    }
+
+    addresses
+        .into_iter()
+        .next()
+        .ok_or_else(|| eyre!("url {url} doesn't resolve to any address"))
+}
+
+/// A client for sending requests to `url`. It doesn't follow redirects, so
+/// the url can't bounce requests on to addresses it couldn't use itself.
+/// Unless `allow_private` is set, it only connects to a public address,
+/// checked when the client is built rather than looked up again for the
+/// request, so DNS can't be changed to point somewhere else in between.
+pub async fn client_for(url: &Url, allow_private: bool) -> Result<Client> {
+    let builder = Client::builder().redirect(redirect::Policy::none());
+
+    let builder = if allow_private {
+        builder
+    } else {
+        let address = resolve_public(url).await?;
+
+        match url.domain() {
+            Some(domain) => builder.resolve(domain, address),
+            // IP addresses aren't looked up again
+            None => builder,
+        }
+    };
+
+    builder.build().wrap_err("failed to build client")
+}
+
+fn is_public(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                // "This network" and carrier-grade NAT
+                || a == 0
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(ip) => {
+            // IPv4-mapped and compatible addresses are as public as the IPv4 one
+            if let Some(v4) = ip.to_ipv4() {
+                if !ip.is_loopback() && !ip.is_unspecified() {
+                    return is_public(IpAddr::V4(v4));
+                }
+            }
+
+            let first = ip.segments()[0];
+
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local
+                || (first & 0xfe00) == 0xfc00
+                // Link local
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! Web Push delivery (RFC 8030) with message encryption (RFC 8291) and VAPID
+//! authentication (RFC 8292).
+
+use std::{fs, path::Path};
+
+use aes_gcm::{
+    aead::{Aead, NewAead},
+    Aes128Gcm, Key, Nonce,
+};
+use chrono::{DateTime, Duration, Utc};
+use eyre::{bail, eyre, Context, Result};
+use hkdf::Hkdf;
+use p256::{
+    ecdh::EphemeralSecret,
+    ecdsa::{signature::Signer, Signature, SigningKey, VerifyingKey},
+    elliptic_curve::sec1::ToEncodedPoint,
+    PublicKey,
+};
+use rand::{rngs::OsRng, RngCore};
+use reqwest::Url;
+use serde_json::json;
+use sha2::Sha256;
+use tracing::warn;
+use train_schedules_common::PushSubscription;
+
+use crate::outbound;
+
+/// Record size advertised in the message header. Push services also cap the
+/// whole message body at this size.
+const RECORD_SIZE: u32 = 4096;
+
+/// How long VAPID tokens stay valid. Push services reject anything over 24h.
+const TOKEN_LIFETIME_HOURS: i64 = 12;
+
+/// Push services browsers hand out subscriptions for. Endpoints are on one of
+/// these hosts or a subdomain of it
+const PUSH_SERVICES: &[&str] = &[
+    "fcm.googleapis.com",
+    "android.googleapis.com",
+    "push.services.mozilla.com",
+    "notify.windows.com",
+    "push.apple.com",
+];
+
+/// Application server key used to sign requests to push services.
+pub struct VapidKey {
+    signing_key: SigningKey,
+    subject: String,
+}
+
+impl VapidKey {
+    pub fn generate(subject: impl Into<String>) -> Self {
+        Self {
+            signing_key: SigningKey::random(&mut OsRng),
+            subject: subject.into(),
+        }
+    }
+
+    /// Load the key stored at `path`, generating and saving a new one if
+    /// there isn't one yet. Subscriptions are tied to the key, so losing it
+    /// invalidates every saved subscription.
+    pub fn load_or_generate(path: &Path, subject: impl Into<String>) -> Result<Self> {
+        let subject = subject.into();
+
+        if path.exists() {
+            let encoded = fs::read_to_string(path)
+                .wrap_err_with(|| format!("failed to read VAPID key from {}", path.display()))?;
+            let bytes = decode_base64(encoded.trim())?;
+            let signing_key = SigningKey::from_bytes(&bytes)
+                .map_err(|e| eyre!("invalid VAPID key in {}: {e}", path.display()))?;
+
+            return Ok(Self {
+                signing_key,
+                subject,
+            });
+        }
+
+        let key = Self::generate(subject);
+
+        if let Err(e) = fs::write(path, encode_base64(&key.signing_key.to_bytes())) {
+            warn!(
+                "failed to save VAPID key to {} ({e}), reminders will not survive a restart",
+                path.display()
+            );
+        }
+
+        Ok(key)
+    }
+
+    /// The public key in the form browsers expect as `applicationServerKey`.
+    pub fn public_key(&self) -> String {
+        let verifying_key = VerifyingKey::from(&self.signing_key);
+
+        encode_base64(verifying_key.to_encoded_point(false).as_bytes())
+    }
+
+    /// `Authorization` header value for a request to `endpoint`.
+    fn authorization(&self, endpoint: &Url, now: DateTime<Utc>) -> Result<String> {
+        let header = json!({ "typ": "JWT", "alg": "ES256" });
+        let claims = json!({
+            "aud": endpoint.origin().ascii_serialization(),
+            "exp": (now + Duration::hours(TOKEN_LIFETIME_HOURS)).timestamp(),
+            "sub": self.subject,
+        });
+
+        let signing_input = format!(
+            "{}.{}",
+            encode_base64(serde_json::to_string(&header)?.as_bytes()),
+            encode_base64(serde_json::to_string(&claims)?.as_bytes())
+        );
+        let signature: Signature = self.signing_key.sign(signing_input.as_bytes());
+
+        Ok(format!(
+            "vapid t={signing_input}.{}, k={}",
+            encode_base64(signature.as_ref()),
+            self.public_key()
+        ))
+    }
+}
+
+/// Encrypt `payload` for `subscription` using the `aes128gcm` content
+/// encoding.
+pub fn encrypt(subscription: &PushSubscription, payload: &[u8]) -> Result<Vec<u8>> {
+    let ua_public_bytes = decode_base64(&subscription.keys.p256dh)?;
+    let ua_public = PublicKey::from_sec1_bytes(&ua_public_bytes)
+        .map_err(|e| eyre!("invalid subscription public key: {e}"))?;
+    let auth_secret = decode_base64(&subscription.keys.auth)?;
+
+    let as_secret = EphemeralSecret::random(&mut OsRng);
+    let as_public = as_secret.public_key().to_encoded_point(false);
+    let ecdh_secret = as_secret.diffie_hellman(&ua_public);
+
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+
+    let mut key_info = b"WebPush: info\0".to_vec();
+    key_info.extend_from_slice(&ua_public_bytes);
+    key_info.extend_from_slice(as_public.as_bytes());
+
+    let mut ikm = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&auth_secret[..]), ecdh_secret.as_bytes())
+        .expand(&key_info, &mut ikm)
+        .map_err(|e| eyre!("failed to derive push key material: {e}"))?;
+
+    let prk = Hkdf::<Sha256>::new(Some(&salt[..]), &ikm);
+    let mut cek = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    prk.expand(b"Content-Encoding: aes128gcm\0", &mut cek)
+        .and_then(|_| prk.expand(b"Content-Encoding: nonce\0", &mut nonce))
+        .map_err(|e| eyre!("failed to derive push content key: {e}"))?;
+
+    // A single record, terminated by the last-record padding delimiter
+    let mut plaintext = payload.to_vec();
+    plaintext.push(2);
+
+    let ciphertext = Aes128Gcm::new(Key::from_slice(&cek))
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_ref())
+        .map_err(|e| eyre!("failed to encrypt push payload: {e}"))?;
+
+    // salt, record size, key id length and the key id itself
+    let header_size = salt.len() + 4 + 1 + as_public.as_bytes().len();
+
+    if header_size + ciphertext.len() > RECORD_SIZE as usize {
+        bail!("push payload of {} bytes is too large", payload.len());
+    }
+
+    let mut body = Vec::with_capacity(header_size + ciphertext.len());
+    body.extend_from_slice(&salt);
+    body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+    body.push(as_public.as_bytes().len() as u8);
+    body.extend_from_slice(as_public.as_bytes());
+    body.extend_from_slice(&ciphertext);
+
+    Ok(body)
+}
+
+/// Outcome of handing a message to a push service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    Accepted,
+    /// The subscription has expired or was revoked, and should be forgotten
+    Gone,
+}
+
+/// Make sure `subscription` can be sent to: an https endpoint of a known
+/// push service, on a public address. Anything goes with `allow_private`.
+pub async fn check_endpoint(subscription: &PushSubscription, allow_private: bool) -> Result<()> {
+    let endpoint = Url::parse(&subscription.endpoint)
+        .wrap_err_with(|| format!("invalid push endpoint {}", subscription.endpoint))?;
+
+    if allow_private {
+        return Ok(());
+    }
+
+    if endpoint.scheme() != "https" {
+        bail!("push endpoint must be https");
+    }
+
+    let host = endpoint.domain().unwrap_or_default();
+    let known = PUSH_SERVICES.iter().any(|service| {
+        host == *service
+            || host
+                .strip_suffix(service)
+                .is_some_and(|subdomain| subdomain.ends_with('.'))
+    });
+
+    if !known {
+        bail!("push endpoint {endpoint} isn't on a known push service");
+    }
+
+    outbound::resolve_public(&endpoint).await?;
+
+    Ok(())
+}
+
+/// Deliver `payload` to the browser behind `subscription`. Only public
+/// addresses are sent to, unless `allow_private` is set.
+pub async fn send(
+    key: &VapidKey,
+    subscription: &PushSubscription,
+    payload: &[u8],
+    ttl_seconds: u32,
+    allow_private: bool,
+) -> Result<Delivery> {
+    let endpoint = Url::parse(&subscription.endpoint)
+        .wrap_err_with(|| format!("invalid push endpoint {}", subscription.endpoint))?;
+    let client = outbound::client_for(&endpoint, allow_private).await?;
+
+    let body = encrypt(subscription, payload)?;
+    let authorization = key.authorization(&endpoint, Utc::now())?;
+
+    let response = client
+        .post(endpoint)
+        .header("TTL", ttl_seconds.to_string())
+        .header("Urgency", "high")
+        .header("Content-Encoding", "aes128gcm")
+        .header("Content-Type", "application/octet-stream")
+        .header("Authorization", authorization)
+        .body(body)
+        .send()
+        .await
+        .wrap_err("failed to reach push service")?;
+
+    let status = response.status();
+
+    if status.as_u16() == 404 || status.as_u16() == 410 {
+        return Ok(Delivery::Gone);
+    }
+
+    if !status.is_success() {
+        bail!("push service responded with HTTP {status}");
+    }
+
+    Ok(Delivery::Accepted)
+}
+
+pub fn encode_base64(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+/// Decode base64url, with or without padding as browsers produce both.
+pub fn decode_base64(encoded: &str) -> Result<Vec<u8>> {
+    base64::decode_config(encoded.trim_end_matches('='), base64::URL_SAFE_NO_PAD)
+        .wrap_err("invalid base64url value")
+}
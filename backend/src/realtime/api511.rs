@@ -0,0 +1,118 @@
+use std::{sync::Arc, time::Instant};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use eyre::{bail, Result};
+use reqwest::{Client, StatusCode};
+use tracing::{info_span, warn, Instrument};
+
+use super::{parse_stop_monitoring, Realtime, RealtimeProvider, Recorder};
+use crate::{metrics::Metrics, telemetry};
+
+const STOP_MONITORING_URL: &str = "https://api.511.org/transit/StopMonitoring";
+
+/// Live status from the 511.org SIRI StopMonitoring API.
+pub struct Api511Provider {
+    client: Client,
+    api_key: String,
+    agency: String,
+    url: String,
+    metrics: Arc<Metrics>,
+    recorder: Option<Recorder>,
+}
+
+impl Api511Provider {
+    pub fn new(client: Client, api_key: String, metrics: Arc<Metrics>) -> Self {
+        Self {
+            client,
+            api_key,
+            agency: String::from("CT"),
+            url: String::from(STOP_MONITORING_URL),
+            metrics,
+            recorder: None,
+        }
+    }
+
+    /// Send requests to `url` instead of 511.org.
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    /// Archive every response with `recorder`, including errors and rate
+    /// limiting.
+    pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Fetch the raw response body, or `None` when rate limited.
+    pub async fn fetch_body(&self) -> Result<Option<String>> {
+        let span = info_span!(
+            "511 StopMonitoring",
+            otel.kind = "client",
+            http.status_code = tracing::field::Empty,
+        );
+
+        let start = Instant::now();
+        let response = self
+            .client
+            .get(&self.url)
+            .query(&[
+                ("api_key", self.api_key.as_str()),
+                ("agency", self.agency.as_str()),
+                ("format", "json"),
+            ])
+            .headers(span.in_scope(telemetry::trace_headers))
+            .send()
+            .instrument(span.clone())
+            .await;
+        let elapsed = start.elapsed().as_secs_f64();
+
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                self.metrics.observe_upstream(None, elapsed);
+                return Err(e.into());
+            }
+        };
+
+        let status = response.status();
+        span.record("http.status_code", &status.as_u16());
+        self.metrics
+            .observe_upstream(Some(status.as_u16()), elapsed);
+
+        let body = response.text().await?;
+
+        if let Some(recorder) = &self.recorder {
+            // Losing a recording shouldn't cost users their live status
+            if let Err(e) = recorder.record(Utc::now(), status.as_u16(), &body).await {
+                warn!("failed to record 511.org response: {e:?}");
+            }
+        }
+
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Ok(None);
+        }
+
+        if status != StatusCode::OK {
+            bail!("Received HTTP {status} from 511.org API: {body}");
+        }
+
+        Ok(Some(body))
+    }
+}
+
+#[async_trait]
+impl RealtimeProvider for Api511Provider {
+    fn name(&self) -> &'static str {
+        "511"
+    }
+
+    async fn fetch(&self) -> Result<Realtime> {
+        match self.fetch_body().await? {
+            Some(body) => Ok(Realtime::Visits(parse_stop_monitoring(&body)?)),
+            None => Ok(Realtime::RateLimited),
+        }
+    }
+}
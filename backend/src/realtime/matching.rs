@@ -0,0 +1,129 @@
+//! Tying realtime visits to the scheduled stops they're predictions for.
+
+use chrono::Utc;
+use serde::Serialize;
+use train_schedules_common::Stop;
+
+use super::Visit;
+use crate::{db, State};
+
+/// Why a visit couldn't be matched to a scheduled stop.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Unmatched {
+    /// There was no `DatedVehicleJourneyRef` to say which trip it is
+    NoJourneyRef,
+    NoExpectedTime,
+    UnknownTrip,
+    UnknownStop,
+    /// The trip's service doesn't run on the visit's service date
+    NotRunning,
+    /// The trip doesn't stop at the visit's station
+    NotStopping,
+}
+
+impl Unmatched {
+    /// Label for metrics
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Unmatched::NoJourneyRef => "no_journey_ref",
+            Unmatched::NoExpectedTime => "no_expected_time",
+            Unmatched::UnknownTrip => "unknown_trip",
+            Unmatched::UnknownStop => "unknown_stop",
+            Unmatched::NotRunning => "not_running",
+            Unmatched::NotStopping => "not_stopping",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct UnmatchedVisit {
+    pub reason: Unmatched,
+    pub visit: Visit,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct Matched {
+    /// Scheduled stops with the expected times from their visits
+    pub stops: Vec<Stop>,
+    pub unmatched: Vec<UnmatchedVisit>,
+}
+
+/// Match each visit to the stop of the trip named by its journey ref, on the
+/// service date it gives. Visits without a service date are taken to be on
+/// the service day of their expected departure.
+pub fn match_visits(state: &State, visits: Vec<Visit>) -> Matched {
+    let mut matched = Matched::default();
+
+    for visit in visits {
+        match match_visit(state, &visit) {
+            Ok(stop) => matched.stops.push(stop),
+            Err(reason) => matched.unmatched.push(UnmatchedVisit { reason, visit }),
+        }
+    }
+
+    matched
+}
+
+fn match_visit(state: &State, visit: &Visit) -> Result<Stop, Unmatched> {
+    let (arrival, departure) = match (visit.arrival, visit.departure) {
+        (Some(arrival), Some(departure)) => (arrival, departure),
+        _ => return Err(Unmatched::NoExpectedTime),
+    };
+
+    let trip_id: i64 = visit
+        .journey_ref
+        .as_ref()
+        .ok_or(Unmatched::NoJourneyRef)?
+        .parse()
+        .map_err(|_| Unmatched::UnknownTrip)?;
+
+    let trip = state
+        .trips
+        .iter()
+        .find(|t| t.trip_id == trip_id)
+        .ok_or(Unmatched::UnknownTrip)?;
+
+    let stop_code: i64 = visit
+        .stop_point_ref
+        .parse()
+        .map_err(|_| Unmatched::UnknownStop)?;
+
+    let station = state
+        .stations
+        .iter()
+        .find(|s| s.stop_codes.contains(&stop_code))
+        .ok_or(Unmatched::UnknownStop)?;
+
+    let service_date = visit
+        .service_date
+        .unwrap_or_else(|| state.today(departure.with_timezone(&Utc)));
+
+    let service = state.services.iter().find(|s| s.id == trip.service_id);
+
+    if !matches!(service, Some(service) if service.runs_on(service_date)) {
+        return Err(Unmatched::NotRunning);
+    }
+
+    let scheduled = state
+        .stops_on(service_date, |s| {
+            s.trip_id == trip_id && s.station_id == station.station_id
+        })
+        .pop()
+        .ok_or(Unmatched::NotStopping)?;
+
+    // A train calling at another of the station's stops than scheduled is
+    // using that stop's platform
+    let platform = visit
+        .platform
+        .clone()
+        .or_else(|| state.platforms.get(&stop_code).cloned())
+        .or_else(|| scheduled.platform.clone());
+
+    Ok(Stop {
+        arrival: db::localize(arrival, state.timezone),
+        departure: db::localize(departure, state.timezone),
+        platform,
+        ..scheduled
+    })
+}
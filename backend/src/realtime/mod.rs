@@ -0,0 +1,103 @@
+//! Sources of live train positions. Providers report [`Visit`]s, which are
+//! matched to scheduled trips so everything downstream of the live status
+//! cache works in terms of [`Stop`]s. Adding an agency or a test source only
+//! needs a new [`RealtimeProvider`].
+//!
+//! [`Stop`]: train_schedules_common::Stop
+
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use eyre::{Context, Result};
+use serde::Serialize;
+use tracing::debug;
+
+use crate::types::{self, MonitoredStopVisit};
+
+mod api511;
+mod matching;
+mod noop;
+mod recorder;
+mod replay;
+
+pub use api511::Api511Provider;
+pub use matching::{match_visits, Matched, Unmatched, UnmatchedVisit};
+pub use noop::NoopProvider;
+pub use recorder::Recorder;
+pub use replay::{recording_file_name, ReplayProvider};
+
+/// The result of asking a provider for live status.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Realtime {
+    Visits(Vec<Visit>),
+    /// The provider asked us to back off, try again later
+    RateLimited,
+}
+
+/// A prediction for a train calling at a stop, as the provider reported it.
+/// [`match_visits`] ties it to the scheduled trip it's for.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Visit {
+    /// The trip's id in the static schedule, from `DatedVehicleJourneyRef`
+    pub journey_ref: Option<String>,
+    /// The day whose service the trip belongs to, from `DataFrameRef`
+    pub service_date: Option<NaiveDate>,
+    /// The train running the trip, which changes when a train is substituted
+    pub vehicle_ref: Option<String>,
+    pub stop_point_ref: String,
+    pub arrival: Option<DateTime<FixedOffset>>,
+    pub departure: Option<DateTime<FixedOffset>>,
+    /// The platform the train will leave from, or arrive at if it isn't
+    /// leaving
+    pub platform: Option<String>,
+}
+
+#[async_trait]
+pub trait RealtimeProvider: Send + Sync {
+    /// Short name for logs and metrics
+    fn name(&self) -> &'static str;
+
+    /// Current expected arrival and departure times. Times can be in any time
+    /// zone, they're moved to the agency's when matched.
+    async fn fetch(&self) -> Result<Realtime>;
+}
+
+/// Parse a SIRI StopMonitoring response as served by 511.org into visits.
+pub fn parse_stop_monitoring(body: &str) -> Result<Vec<Visit>> {
+    // 511.org prefixes its JSON responses with a byte order mark
+    let json = body.trim_start_matches('\u{feff}');
+
+    let response: types::ApiResponse = serde_json::from_str(json)
+        .wrap_err_with(|| format!("failed to parse 511.org API response as json. body: {body}"))?;
+
+    debug!("Parsed API response: {:?}", response);
+
+    Ok(response
+        .ServiceDelivery
+        .StopMonitoringDelivery
+        .MonitoredStopVisit
+        .into_iter()
+        .map(visit)
+        .collect())
+}
+
+fn visit(visit: MonitoredStopVisit) -> Visit {
+    let journey = visit.MonitoredVehicleJourney;
+    let framed_ref = journey.FramedVehicleJourneyRef;
+    let call = journey.MonitoredCall;
+
+    Visit {
+        journey_ref: framed_ref
+            .as_ref()
+            .map(|r| r.DatedVehicleJourneyRef.clone())
+            .filter(|r| !r.is_empty()),
+        service_date: framed_ref.and_then(|r| r.DataFrameRef.parse().ok()),
+        vehicle_ref: journey.VehicleRef,
+        stop_point_ref: call.StopPointRef,
+        arrival: call.ExpectedArrivalTime,
+        departure: call.ExpectedDepartureTime,
+        platform: call
+            .DeparturePlatformName
+            .or(call.ArrivalPlatformName)
+            .filter(|p| !p.is_empty()),
+    }
+}
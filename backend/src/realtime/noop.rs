@@ -0,0 +1,18 @@
+use async_trait::async_trait;
+use eyre::Result;
+
+use super::{Realtime, RealtimeProvider};
+
+/// Schedule-only mode: there is never any live data.
+pub struct NoopProvider;
+
+#[async_trait]
+impl RealtimeProvider for NoopProvider {
+    fn name(&self) -> &'static str {
+        "none"
+    }
+
+    async fn fetch(&self) -> Result<Realtime> {
+        Ok(Realtime::Visits(Vec::new()))
+    }
+}
@@ -0,0 +1,79 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{prelude::*, Duration};
+use eyre::{Context, Result};
+use tracing::info;
+
+use super::recording_file_name;
+
+const DAY_FORMAT: &str = "%Y-%m-%d";
+
+/// Archives raw upstream responses so they can be replayed with
+/// [`ReplayProvider`](super::ReplayProvider) or turned into test fixtures.
+/// Responses are kept in a directory per UTC day, and days older than the
+/// retention period are deleted as new responses come in.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    dir: PathBuf,
+    retention: Duration,
+}
+
+impl Recorder {
+    pub fn new(dir: PathBuf, retention_days: u32) -> Self {
+        Self {
+            dir,
+            retention: Duration::days(retention_days.into()),
+        }
+    }
+
+    /// Write `body`, received with HTTP `status` at `at`, to the archive,
+    /// returning where it was written.
+    pub async fn record(&self, at: DateTime<Utc>, status: u16, body: &str) -> Result<PathBuf> {
+        let day_dir = self.dir.join(at.format(DAY_FORMAT).to_string());
+
+        tokio::fs::create_dir_all(&day_dir)
+            .await
+            .wrap_err_with(|| format!("failed to create {}", day_dir.display()))?;
+
+        let path = day_dir.join(recording_file_name(at, status));
+
+        tokio::fs::write(&path, body)
+            .await
+            .wrap_err_with(|| format!("failed to write {}", path.display()))?;
+
+        self.rotate(at.date().naive_utc()).await?;
+
+        Ok(path)
+    }
+
+    /// Delete the directories of days that have fallen out of the retention
+    /// period as of `today`.
+    async fn rotate(&self, today: NaiveDate) -> Result<()> {
+        let oldest = today - self.retention;
+        let mut entries = tokio::fs::read_dir(&self.dir)
+            .await
+            .wrap_err_with(|| format!("failed to read {}", self.dir.display()))?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+
+            if matches!(day_of(&path), Some(day) if day < oldest) {
+                info!("removing expired recordings in {}", path.display());
+
+                tokio::fs::remove_dir_all(&path)
+                    .await
+                    .wrap_err_with(|| format!("failed to remove {}", path.display()))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn day_of(path: &Path) -> Option<NaiveDate> {
+    if !path.is_dir() {
+        return None;
+    }
+
+    NaiveDate::parse_from_str(path.file_name()?.to_str()?, DAY_FORMAT).ok()
+}
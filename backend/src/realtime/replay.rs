@@ -0,0 +1,154 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use chrono::{prelude::*, Duration};
+use eyre::{bail, Context, Result};
+use tracing::info;
+
+use super::{parse_stop_monitoring, Realtime, RealtimeProvider};
+use crate::clock::Clock;
+
+const FILE_NAME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// Name for a response with HTTP `status` recorded at `at`, which
+/// [`ReplayProvider`] reads the time and status back out of. Anything but a
+/// 200 has its status after the time, and isn't necessarily JSON.
+pub fn recording_file_name(at: DateTime<Utc>, status: u16) -> String {
+    match status {
+        200 => format!("{}.json", at.format(FILE_NAME_FORMAT)),
+        _ => format!("{}-{status}.txt", at.format(FILE_NAME_FORMAT)),
+    }
+}
+
+struct Recording {
+    recorded_at: DateTime<Utc>,
+    status: u16,
+    path: PathBuf,
+}
+
+/// Plays back recorded 511.org responses as if they were happening now, so
+/// a past day's service can be reproduced locally. Trips only match the
+/// schedule if today runs the same service as the day being replayed.
+pub struct ReplayProvider {
+    /// Oldest first
+    recordings: Vec<Recording>,
+    /// Added to recorded times to line them up with the current time
+    offset: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl ReplayProvider {
+    /// Load the recordings in `dir` and its subdirectories. Replay begins
+    /// from `start`, or the first recording, at the clock's current time.
+    pub fn load(dir: &Path, start: Option<DateTime<Utc>>, clock: Arc<dyn Clock>) -> Result<Self> {
+        let mut recordings = Vec::new();
+        find_recordings(dir, &mut recordings)?;
+
+        recordings.sort_by_key(|r| r.recorded_at);
+
+        let first = match recordings.first() {
+            Some(first) => first.recorded_at,
+            None => bail!("no recordings found in {}", dir.display()),
+        };
+
+        let start = start.unwrap_or(first);
+
+        info!(
+            "replaying {} recordings from {} starting at {start}",
+            recordings.len(),
+            dir.display()
+        );
+
+        Ok(Self {
+            recordings,
+            offset: clock.now() - start,
+            clock,
+        })
+    }
+
+    /// The most recent recording as of `now` in replayed time.
+    fn recording_at(&self, now: DateTime<Utc>) -> &Recording {
+        let replayed = now - self.offset;
+
+        self.recordings
+            .iter()
+            .take_while(|r| r.recorded_at <= replayed)
+            .last()
+            .unwrap_or(&self.recordings[0])
+    }
+}
+
+#[async_trait]
+impl RealtimeProvider for ReplayProvider {
+    fn name(&self) -> &'static str {
+        "replay"
+    }
+
+    async fn fetch(&self) -> Result<Realtime> {
+        let recording = self.recording_at(self.clock.now());
+
+        match recording.status {
+            200 => {}
+            429 => return Ok(Realtime::RateLimited),
+            status => bail!(
+                "replaying HTTP {status} from 511.org API in {}",
+                recording.path.display()
+            ),
+        }
+
+        let body = tokio::fs::read_to_string(&recording.path)
+            .await
+            .wrap_err_with(|| format!("failed to read {}", recording.path.display()))?;
+
+        let mut visits = parse_stop_monitoring(&body)?;
+
+        // Trips stay on the service day they were recorded on, moved by the
+        // nearest whole number of days
+        let days = (self.offset + Duration::hours(12)).num_days();
+
+        for visit in &mut visits {
+            visit.service_date = visit.service_date.map(|d| d + Duration::days(days));
+            visit.arrival = visit.arrival.map(|t| t + self.offset);
+            visit.departure = visit.departure.map(|t| t + self.offset);
+        }
+
+        Ok(Realtime::Visits(visits))
+    }
+}
+
+fn find_recordings(dir: &Path, recordings: &mut Vec<Recording>) -> Result<()> {
+    let entries =
+        std::fs::read_dir(dir).wrap_err_with(|| format!("failed to read {}", dir.display()))?;
+
+    for entry in entries {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            find_recordings(&path, recordings)?;
+            continue;
+        }
+
+        let stem = match path.file_stem().and_then(|stem| stem.to_str()) {
+            Some(stem) => stem,
+            None => continue,
+        };
+        let (time, status) = match stem.split_once('-') {
+            Some((time, status)) => (time, status.parse().ok()),
+            None => (stem, Some(200)),
+        };
+        let recorded_at = NaiveDateTime::parse_from_str(time, FILE_NAME_FORMAT).ok();
+
+        if let (Some(recorded_at), Some(status)) = (recorded_at, status) {
+            recordings.push(Recording {
+                recorded_at: DateTime::from_utc(recorded_at, Utc),
+                status,
+                path,
+            });
+        }
+    }
+
+    Ok(())
+}
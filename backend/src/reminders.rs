@@ -0,0 +1,251 @@
+//! Departure reminders, delivered as Web Push notifications shortly before a
+//! trip leaves a station.
+
+use std::{path::PathBuf, sync::Arc};
+
+use chrono::{prelude::*, Duration};
+use chrono_tz::Tz;
+use eyre::{bail, Context, Result};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::RwLock;
+use tracing::{error, info, warn};
+use train_schedules_common::{PushSubscription, Reminder, ReminderRequest, Stop};
+
+use crate::{
+    push::{self, Delivery},
+    routes::live::get_station_live_status,
+    State,
+};
+
+/// How often the scheduler looks for reminders that are due
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Most reminders a push subscription can have waiting at once
+pub const MAX_PER_SUBSCRIPTION: usize = 20;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StoredReminder {
+    pub id: String,
+    pub subscription: PushSubscription,
+    pub trip_id: i64,
+    pub station_id: i64,
+    pub minutes_before: i64,
+}
+
+/// Pending reminders, optionally persisted to a JSON file so they survive
+/// restarts.
+pub struct Reminders {
+    path: Option<PathBuf>,
+    reminders: RwLock<Vec<StoredReminder>>,
+}
+
+impl Reminders {
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            reminders: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let reminders = if path.exists() {
+            let json = std::fs::read_to_string(&path)
+                .wrap_err_with(|| format!("failed to read reminders from {}", path.display()))?;
+
+            serde_json::from_str(&json)
+                .wrap_err_with(|| format!("failed to parse reminders in {}", path.display()))?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            path: Some(path),
+            reminders: RwLock::new(reminders),
+        })
+    }
+
+    pub async fn add(&self, request: ReminderRequest) -> Result<Reminder> {
+        let mut id = [0u8; 16];
+        OsRng.fill_bytes(&mut id);
+
+        let reminder = StoredReminder {
+            id: push::encode_base64(&id),
+            subscription: request.subscription,
+            trip_id: request.trip_id,
+            station_id: request.station_id,
+            minutes_before: request.minutes_before,
+        };
+
+        let mut reminders = self.reminders.write().await;
+
+        let waiting = reminders
+            .iter()
+            .filter(|r| r.subscription.endpoint == reminder.subscription.endpoint)
+            .count();
+        if waiting >= MAX_PER_SUBSCRIPTION {
+            bail!("a subscription can't have more than {MAX_PER_SUBSCRIPTION} reminders");
+        }
+
+        reminders.push(reminder.clone());
+        self.save(&reminders).await?;
+
+        Ok(Reminder {
+            id: reminder.id,
+            trip_id: reminder.trip_id,
+            station_id: reminder.station_id,
+            minutes_before: reminder.minutes_before,
+        })
+    }
+
+    /// Forget the reminder with the given id, returning whether it existed.
+    pub async fn remove(&self, id: &str) -> Result<bool> {
+        let mut reminders = self.reminders.write().await;
+
+        let before = reminders.len();
+        reminders.retain(|r| r.id != id);
+
+        if reminders.len() == before {
+            return Ok(false);
+        }
+
+        self.save(&reminders).await?;
+
+        Ok(true)
+    }
+
+    pub async fn all(&self) -> Vec<StoredReminder> {
+        self.reminders.read().await.clone()
+    }
+
+    async fn save(&self, reminders: &[StoredReminder]) -> Result<()> {
+        if let Some(path) = &self.path {
+            let json = serde_json::to_string(reminders)?;
+
+            tokio::fs::write(path, json)
+                .await
+                .wrap_err_with(|| format!("failed to save reminders to {}", path.display()))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Check for due reminders forever.
+pub async fn run_scheduler(state: Arc<State>) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if state.reminders.all().await.is_empty() {
+            continue;
+        }
+
+        let live = match get_station_live_status(&state).await {
+            Ok(live) => live,
+            Err(e) => {
+                warn!("reminders falling back to scheduled times: {e:?}");
+                Vec::new()
+            }
+        };
+
+        if let Err(e) = send_due(&state, &live, state.clock.now()).await {
+            error!("failed to send reminders: {e:?}");
+        }
+    }
+}
+
+/// Send every reminder whose time has come, using live departure times where
+/// `live` has them. Sent reminders are forgotten, as are ones for trips that
+/// already left or don't run today. Returns the number of notifications sent.
+pub async fn send_due(state: &State, live: &[Stop], now: DateTime<Utc>) -> Result<usize> {
+    let today = state.today(now);
+    let mut sent = 0;
+
+    for reminder in state.reminders.all().await {
+        let scheduled = state
+            .stops_on(today, |s| {
+                s.trip_id == reminder.trip_id
+                    && s.station_id == reminder.station_id
+                    && state.runs_on(&s.service_id, today)
+            })
+            .pop();
+
+        let scheduled = match &scheduled {
+            Some(stop) => stop,
+            None => {
+                state.reminders.remove(&reminder.id).await?;
+                continue;
+            }
+        };
+
+        let live_stop = scheduled.live_in(live);
+
+        let departure = live_stop
+            .map(|s| s.departure)
+            .unwrap_or(scheduled.departure)
+            .with_timezone(&Utc);
+
+        if now > departure {
+            info!(
+                "dropping reminder {} for a trip that already left",
+                reminder.id
+            );
+            state.reminders.remove(&reminder.id).await?;
+            continue;
+        }
+
+        if now < departure - Duration::minutes(reminder.minutes_before) {
+            continue;
+        }
+
+        let payload = notification(
+            scheduled,
+            departure.with_timezone(&state.timezone),
+            now,
+            live_stop.is_some(),
+        );
+        let ttl = (departure - now).num_seconds().max(60) as u32;
+
+        match push::send(
+            &state.vapid,
+            &reminder.subscription,
+            payload.as_bytes(),
+            ttl,
+            state.allow_private_targets,
+        )
+        .await
+        {
+            Ok(Delivery::Accepted) => {
+                sent += 1;
+                state.reminders.remove(&reminder.id).await?;
+            }
+            Ok(Delivery::Gone) => {
+                info!(
+                    "dropping reminder {} for an expired subscription",
+                    reminder.id
+                );
+                state.reminders.remove(&reminder.id).await?;
+            }
+            // Leave the reminder in place to retry on the next check
+            Err(e) => warn!("failed to send reminder {}: {e:?}", reminder.id),
+        }
+    }
+
+    Ok(sent)
+}
+
+fn notification(stop: &Stop, departure: DateTime<Tz>, now: DateTime<Utc>, live: bool) -> String {
+    let minutes = (departure.with_timezone(&Utc) - now).num_minutes();
+    let time = departure.format("%l:%M %p");
+    let source = if live { "live estimate" } else { "scheduled" };
+
+    json!({
+        "title": format!("Train {} leaves {} in {} min.", stop.trip_id, stop.station_name, minutes),
+        "body": format!("Departing at {} ({source})", time.to_string().trim()),
+        "url": format!("/c/trip/{}", stop.trip_id),
+    })
+    .to_string()
+}
@@ -0,0 +1,145 @@
+use std::sync::Arc;
+
+use crate::{
+    clock::Now,
+    db,
+    error::{Error, HttpResult},
+    routes::{
+        live::get_live_status_at,
+        upcoming::{get_upcoming, station},
+    },
+    State,
+};
+use axum::{
+    extract::{Extension, Path},
+    response::sse::{Event, KeepAlive, Sse},
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use eyre::Result;
+use futures::{stream, Stream};
+use tracing::warn;
+use train_schedules_common::{Departure, DeparturesBoard};
+
+/// How often kiosks are sent a fresh board
+const PUSH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// How many departures a board lists
+const BOARD_DEPARTURES: usize = 20;
+
+/// Trains at least this many minutes late make the ticker
+const ALERT_DELAY_MINUTES: i64 = 5;
+
+/// How far back to look for trains that were due to leave already but are
+/// running late
+const LATE_TRAINS_MINUTES: i64 = 60;
+
+/// `/api/stations/{id}/board` - the departures board for a station.
+pub async fn board(
+    Path(station_id): Path<i64>,
+    Now(now): Now,
+    Extension(data): Extension<Arc<State>>,
+) -> HttpResult<DeparturesBoard> {
+    Ok(Json(build_board(&data, station_id, now).await?))
+}
+
+/// `/api/stations/{id}/board/events` - server-sent `board` events with the
+/// departures board for a station, one straight away and one every
+/// [`PUSH_INTERVAL`] after that.
+pub async fn board_events(
+    Path(station_id): Path<i64>,
+    Now(now): Now,
+    Extension(data): Extension<Arc<State>>,
+) -> Result<Sse<impl Stream<Item = Result<Event, serde_json::Error>>>, Error> {
+    // Fail the request rather than the stream for stations that don't exist
+    station(station_id, &data.stations)?;
+
+    // Keep a `?now=` override moving along with the clock
+    let offset = now - data.clock.now();
+    let interval = tokio::time::interval(PUSH_INTERVAL);
+
+    let events = stream::unfold(interval, move |mut interval| {
+        let data = data.clone();
+
+        async move {
+            interval.tick().await;
+
+            let now = data.clock.now() + offset;
+            let event = match build_board(&data, station_id, now).await {
+                Ok(board) => Event::default().event("board").json_data(board),
+                Err(e) => {
+                    warn!("failed to build departures board for {station_id}: {e:?}");
+                    Ok(Event::default().comment("board unavailable"))
+                }
+            };
+
+            Some((event, interval))
+        }
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Departures from `station_id` at `now`, with live status where there is
+/// any. Without live status the board falls back to the schedule and says so.
+pub async fn build_board(
+    data: &State,
+    station_id: i64,
+    now: DateTime<Utc>,
+) -> Result<DeparturesBoard> {
+    let station = station(station_id, &data.stations)?;
+
+    let mut alerts = Vec::new();
+
+    let live = match get_live_status_at(data, station_id).await {
+        Ok(live) => live,
+        Err(e) => {
+            warn!("departures board for {station_id} falling back to scheduled times: {e:?}");
+            alerts.push(String::from("Live train times are unavailable"));
+            Vec::new()
+        }
+    };
+
+    let departures = get_upcoming(
+        data,
+        station_id,
+        now - Duration::minutes(LATE_TRAINS_MINUTES),
+    )
+    .into_iter()
+    .map(|scheduled| {
+        let live = scheduled.live_in(&live).cloned();
+
+        Departure { scheduled, live }
+    })
+    .filter(|d| d.departure() > now)
+    .take(BOARD_DEPARTURES)
+    .collect::<Vec<_>>();
+
+    alerts.extend(departures.iter().filter_map(alert));
+
+    Ok(DeparturesBoard {
+        station,
+        updated_at: db::localize(now, data.timezone),
+        departures,
+        alerts,
+    })
+}
+
+/// What riders should know about `departure`, if anything.
+fn alert(departure: &Departure) -> Option<String> {
+    let live = departure.live.as_ref()?;
+    let scheduled = &departure.scheduled;
+    let train = format!("Train {} to {}", scheduled.trip_id, scheduled.headsign);
+
+    let delay = (live.departure - scheduled.departure).num_minutes();
+    if delay >= ALERT_DELAY_MINUTES {
+        return Some(format!("{train} is running {delay} min. late"));
+    }
+
+    match (&live.platform, &scheduled.platform) {
+        (Some(live), Some(scheduled)) if live != scheduled => {
+            Some(format!("{train} now leaves from platform {live}"))
+        }
+        _ => None,
+    }
+}
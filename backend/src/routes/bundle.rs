@@ -0,0 +1,138 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    clock::Now,
+    db::{self, Service},
+    State,
+};
+use axum::{
+    extract::{Extension, Query},
+    Json,
+};
+use chrono::{prelude::*, Duration};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use train_schedules_common::{BundleStop, BundleTrip, ScheduleBundle, ServiceCalendar, UtcOffset};
+
+const DEFAULT_DAYS: i64 = 7;
+const MAX_DAYS: i64 = 60;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct BundleQuery {
+    days: Option<i64>,
+}
+
+/// `/api/schedule-bundle` - the static schedule for services running in the
+/// next `days` days, compact enough for the frontend to keep for offline use.
+pub async fn schedule_bundle(
+    Query(query): Query<BundleQuery>,
+    Now(now): Now,
+    Extension(data): Extension<Arc<State>>,
+) -> Json<ScheduleBundle> {
+    let days = query.days.unwrap_or(DEFAULT_DAYS).clamp(1, MAX_DAYS);
+    let today = data.today(now);
+
+    let services = data
+        .services
+        .iter()
+        .map(service_calendar)
+        .filter(|s| (0..days).any(|day| s.runs_on(today + Duration::days(day))))
+        .collect::<Vec<_>>();
+
+    let start = db::localize(
+        db::service_day_start(data.service_day, data.timezone),
+        data.timezone,
+    );
+    let mut trips: HashMap<i64, BundleTrip> = HashMap::new();
+
+    for stop in &data.stops {
+        if !services.iter().any(|s| s.id == stop.service_id) {
+            continue;
+        }
+
+        let minutes = |time: DateTime<FixedOffset>| (time - start).num_minutes();
+
+        trips
+            .entry(stop.trip_id)
+            .or_insert_with(|| BundleTrip {
+                trip_id: stop.trip_id,
+                service_id: stop.service_id.clone(),
+                direction_id: stop.direction_id,
+                headsign: stop.headsign.clone(),
+                stops: Vec::new(),
+            })
+            .stops
+            .push(BundleStop {
+                station_id: stop.station_id,
+                stop_sequence: stop.stop_sequence,
+                arrival: minutes(stop.arrival),
+                departure: minutes(stop.departure),
+                pickup_type: stop.pickup_type,
+                drop_off_type: stop.drop_off_type,
+                platform: stop.platform.clone(),
+            });
+    }
+
+    let mut trips = trips.into_values().collect::<Vec<_>>();
+    trips.sort_by_key(|t| t.trip_id);
+    for trip in &mut trips {
+        trip.stops.sort_by_key(|s| s.stop_sequence);
+    }
+
+    // Yesterday's service runs into today, and the last day's into the next
+    let utc_offsets = utc_offsets(
+        db::service_day_start(today - Duration::days(1), data.timezone).with_timezone(&Utc),
+        db::service_day_start(today + Duration::days(days + 1), data.timezone).with_timezone(&Utc),
+        data.timezone,
+    );
+
+    Json(ScheduleBundle {
+        stations: data.stations.clone(),
+        services,
+        trips,
+        utc_offsets,
+    })
+}
+
+/// The offsets `timezone` has between `start` and `end`, from `start` and
+/// then each time the clocks change.
+fn utc_offsets(start: DateTime<Utc>, end: DateTime<Utc>, timezone: Tz) -> Vec<UtcOffset> {
+    let seconds_east = |time: DateTime<Utc>| {
+        timezone
+            .offset_from_utc_datetime(&time.naive_utc())
+            .fix()
+            .local_minus_utc()
+    };
+
+    let mut offsets = vec![UtcOffset {
+        from: start,
+        seconds_east: seconds_east(start),
+    }];
+
+    // Clocks change on the quarter hour
+    let mut time = start.date().and_hms(0, 0, 0);
+    while time < end {
+        time = time + Duration::minutes(15);
+
+        let offset = seconds_east(time);
+        if time > start && offsets.last().map(|o| o.seconds_east) != Some(offset) {
+            offsets.push(UtcOffset {
+                from: time,
+                seconds_east: offset,
+            });
+        }
+    }
+
+    offsets
+}
+
+fn service_calendar(service: &Service) -> ServiceCalendar {
+    ServiceCalendar {
+        id: service.id.clone(),
+        start_date: service.start_date,
+        end_date: service.end_date,
+        weekdays: service.weekdays.clone(),
+        added_dates: service.added_dates.clone(),
+        removed_dates: service.removed_dates.clone(),
+    }
+}
@@ -0,0 +1,272 @@
+use std::sync::Arc;
+
+use crate::{
+    clock::Now,
+    db::Service,
+    error::Error,
+    ical::{Calendar, Event, Recurrence},
+    routes::upcoming::{get_twostops, station},
+    State,
+};
+use axum::{
+    extract::{Extension, Path, Query},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{prelude::*, Duration};
+use eyre::{eyre, Context, Result};
+use serde::Deserialize;
+use train_schedules_common::Stop;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TripCalendarQuery {
+    start: Option<i64>,
+    end: Option<i64>,
+    #[serde(default)]
+    recurring: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpcomingCalendarQuery {
+    start: i64,
+    end: i64,
+    #[serde(default)]
+    recurring: bool,
+}
+
+/// `/api/trip/{id}.ics` - one event for the trip, optionally limited to the
+/// part of it between the `start` and `end` stations.
+pub async fn trip_calendar(
+    Path(file): Path<String>,
+    Query(query): Query<TripCalendarQuery>,
+    Now(now): Now,
+    Extension(data): Extension<Arc<State>>,
+) -> Result<Response, Error> {
+    // The route matches `/api/trip/{anything}`, only calendar files are here
+    let trip_id = match file.strip_suffix(".ics") {
+        Some(trip_id) => trip_id,
+        None => return Ok(StatusCode::NOT_FOUND.into_response()),
+    };
+    let trip_id: i64 = trip_id
+        .parse()
+        .wrap_err_with(|| format!("invalid trip calendar file name {file}"))?;
+
+    let stops = trip_stops(&data, trip_id, now);
+
+    let start = match query.start {
+        Some(station_id) => find_stop(&stops, station_id)?,
+        None => stops
+            .first()
+            .ok_or_else(|| eyre!("no trip found with id {trip_id}"))?,
+    };
+    let end = match query.end {
+        Some(station_id) => find_stop(&stops, station_id)?,
+        None => stops
+            .last()
+            .ok_or_else(|| eyre!("no trip found with id {trip_id}"))?,
+    };
+    if start.departure > end.departure {
+        return Err(eyre!(
+            "trip {trip_id} reaches station {} before station {}",
+            end.station_id,
+            start.station_id
+        )
+        .into());
+    }
+
+    let mut calendar = Calendar::new(data.timezone);
+    calendar.push(trip_event(&data, start, end, query.recurring, now)?);
+
+    Ok(calendar_response(&calendar, &format!("trip-{trip_id}.ics"), now).into_response())
+}
+
+/// `/api/upcoming-trips.ics` - one event for each of the remaining trips
+/// today between two stations.
+pub async fn upcoming_calendar(
+    Query(query): Query<UpcomingCalendarQuery>,
+    Now(now): Now,
+    Extension(data): Extension<Arc<State>>,
+) -> Result<(HeaderMap, String), Error> {
+    let twostops = get_twostops(&data, query.start, query.end, now)?;
+
+    let mut calendar = Calendar::new(data.timezone);
+
+    for twostop in twostops.trips.iter().filter(|t| t.start.departure > now) {
+        calendar.push(trip_event(
+            &data,
+            &twostop.start,
+            &twostop.end,
+            query.recurring,
+            now,
+        )?);
+    }
+
+    Ok(calendar_response(
+        &calendar,
+        &format!("trips-{}-{}.ics", query.start, query.end),
+        now,
+    ))
+}
+
+fn calendar_response(
+    calendar: &Calendar,
+    filename: &str,
+    now: DateTime<Utc>,
+) -> (HeaderMap, String) {
+    let mut headers = HeaderMap::new();
+
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/calendar; charset=utf-8"),
+    );
+    if let Ok(disposition) = HeaderValue::from_str(&format!("attachment; filename=\"{filename}\""))
+    {
+        headers.insert(header::CONTENT_DISPOSITION, disposition);
+    }
+
+    (headers, calendar.render(now))
+}
+
+/// Stops of `trip_id`, on the service day running at `now`.
+fn trip_stops(data: &State, trip_id: i64, now: DateTime<Utc>) -> Vec<Stop> {
+    let today = data.today(now);
+    let mut stops = data.stops_on(today, |s| s.trip_id == trip_id);
+
+    stops.sort_by_key(|s| s.departure);
+
+    stops
+}
+
+fn find_stop(stops: &[Stop], station_id: i64) -> Result<&Stop> {
+    stops
+        .iter()
+        .find(|s| s.station_id == station_id)
+        .ok_or_else(|| eyre!("trip does not stop at station {station_id}"))
+}
+
+fn trip_event(
+    data: &State,
+    start: &Stop,
+    end: &Stop,
+    recurring: bool,
+    now: DateTime<Utc>,
+) -> Result<Event> {
+    let trip_id = start.trip_id;
+    let start_station = station(start.station_id, &data.stations)?;
+
+    let route = data
+        .trips
+        .iter()
+        .find(|t| t.trip_id == trip_id)
+        .map(|t| t.route_name.clone())
+        .unwrap_or_default();
+
+    let departure = start.departure.with_timezone(&data.timezone).naive_local();
+    let arrival = end.arrival.with_timezone(&data.timezone).naive_local();
+
+    let mut event = Event {
+        uid: format!(
+            "trip-{trip_id}-{}-{}-{}@train-schedules",
+            start.station_id,
+            end.station_id,
+            departure.format("%Y%m%d")
+        ),
+        summary: format!(
+            "Train {trip_id}: {} to {}",
+            start.station_name, end.station_name
+        ),
+        description: format!(
+            "{route} train {trip_id}\nDeparts {} at {}\nArrives {} at {}",
+            start.station_name,
+            departure.format("%l:%M %p"),
+            end.station_name,
+            arrival.format("%l:%M %p"),
+        ),
+        location: start_station.name.clone(),
+        geo: Some((start_station.lat, start_station.lon)),
+        start: departure,
+        end: arrival,
+        recurrence: None,
+    };
+
+    let service = data.services.iter().find(|s| s.id == start.service_id);
+
+    if let (true, Some(service)) = (recurring, service) {
+        if service.weekdays.is_empty() {
+            return Ok(event);
+        }
+
+        let today = data.today(now);
+        let service_day =
+            trip_service_day(data, trip_id, today).unwrap_or_else(|| departure.date());
+
+        if let Some(recurrence) = weekly_recurrence(service, service_day, today, &mut event) {
+            event.uid = format!(
+                "trip-{trip_id}-{}-{}-{}@train-schedules",
+                start.station_id, end.station_id, service.id
+            );
+            event.recurrence = Some(recurrence);
+        }
+    }
+
+    Ok(event)
+}
+
+/// The date a trip runs on when it runs on `day`'s service, taken from the
+/// first departure of the trip. Stops after midnight on a late-night trip
+/// still belong to the day it started.
+fn trip_service_day(data: &State, trip_id: i64, day: NaiveDate) -> Option<NaiveDate> {
+    data.stops_on(day, |s| s.trip_id == trip_id)
+        .into_iter()
+        .map(|s| s.departure)
+        .min()
+        .map(|departure| departure.with_timezone(&data.timezone).date().naive_local())
+}
+
+/// Move `event` to the first upcoming day `service` runs and build the weekly
+/// recurrence matching the rest of the days it runs, skipping the days it's
+/// removed from.
+fn weekly_recurrence(
+    service: &Service,
+    service_day: NaiveDate,
+    today: NaiveDate,
+    event: &mut Event,
+) -> Option<Recurrence> {
+    // Trips after midnight occur on the day after the service day
+    let day_offset = event.start.date() - service_day;
+    let duration = event.end - event.start;
+
+    let start_date = service.start_date;
+    let end_date = service.end_date;
+
+    let mut first = std::cmp::max(today, start_date);
+    while !service.weekdays.contains(&first.weekday()) {
+        first += Duration::days(1);
+    }
+
+    if first > end_date {
+        return None;
+    }
+
+    event.start = (first + day_offset).and_time(event.start.time());
+    event.end = event.start + duration;
+
+    let mut weekdays = service.weekdays.clone();
+    for _ in 0..day_offset.num_days() {
+        weekdays = weekdays.into_iter().map(|day| day.succ()).collect();
+    }
+
+    let except = service
+        .removed_dates
+        .iter()
+        .filter(|day| first <= **day && **day <= end_date)
+        .filter(|day| service.weekdays.contains(&day.weekday()))
+        .map(|day| *day + day_offset)
+        .collect();
+
+    Some(Recurrence {
+        weekdays,
+        until: end_date + day_offset,
+        except,
+    })
+}
@@ -1,24 +1,54 @@
-use std::{sync::Arc, time::Duration};
+use std::sync::Arc;
 
 use crate::{
     error::HttpResult,
-    types::{self, MonitoredStopVisit},
+    realtime::{match_visits, Realtime, UnmatchedVisit, Visit},
     State,
 };
-use axum::{extract::Extension, Json};
-use chrono::{DateTime, FixedOffset, Local};
+use axum::{
+    extract::{Extension, Path},
+    Json,
+};
+use chrono::Utc;
 use eyre::Result;
-use eyre::{bail, Context};
-use reqwest::StatusCode;
-use tracing::{debug, info};
-use train_schedules_common::{Station, Stop};
+use tracing::info;
+use train_schedules_common::Stop;
+use ttl_cache::TtlCache;
+
+use super::upcoming::station;
 
 pub async fn live_station(Extension(data): Extension<Arc<State>>) -> HttpResult<Vec<Stop>> {
     Ok(Json(get_station_live_status(&data).await?))
 }
 
-async fn get_station_live_status(data: &State) -> Result<Vec<Stop>> {
+/// `/api/stations/{id}/live` - live status of the trains calling at one
+/// station.
+pub async fn station_live(
+    Path(station_id): Path<i64>,
+    Extension(data): Extension<Arc<State>>,
+) -> HttpResult<Vec<Stop>> {
+    Ok(Json(get_live_status_at(&data, station_id).await?))
+}
+
+/// `/api/trip/{id}/live` - live status of one trip at each station it has
+/// yet to reach, in the order it reaches them.
+pub async fn trip_live(
+    Path(trip_id): Path<i64>,
+    Extension(data): Extension<Arc<State>>,
+) -> HttpResult<Vec<Stop>> {
+    Ok(Json(get_trip_live_status(&data, trip_id).await?))
+}
+
+/// `/api/debug/unmatched-visits` - visits in the last live status update
+/// that couldn't be matched to a scheduled trip.
+pub async fn unmatched_visits(Extension(data): Extension<Arc<State>>) -> Json<Vec<UnmatchedVisit>> {
+    Json(data.unmatched_visits.read().await.clone())
+}
+
+/// Live status at every station.
+pub async fn get_station_live_status(data: &State) -> Result<Vec<Stop>> {
     if let Some(cached) = data.live_status_cache.read().await.get(&()).cloned() {
+        data.metrics.live_cache_hit();
         return Ok(cached);
     }
 
@@ -26,89 +56,92 @@ async fn get_station_live_status(data: &State) -> Result<Vec<Stop>> {
     // Check the cache again in case some other coroutine wrote while we were
     // waiting to take the lock
     if let Some(cached) = lock.get(&()).cloned() {
+        data.metrics.live_cache_hit();
         return Ok(cached);
     }
 
-    let api_key = &data.api_key;
-    let url = format!(
-        "https://api.511.org/transit/StopMonitoring?api_key={api_key}&agency=CT&format=json"
-    );
+    data.metrics.live_cache_miss();
 
-    let response = data.client.get(&url).send().await?;
+    let visits = match data.realtime.fetch().await? {
+        Realtime::Visits(visits) => visits,
+        Realtime::RateLimited => {
+            back_off(data, &mut lock);
+            return Ok(Vec::new());
+        }
+    };
 
-    let status = response.status();
+    let trips = match_and_record(data, visits).await;
 
-    let body = response.text().await?;
+    lock.insert((), trips.clone(), data.live_cache_ttl);
+    // Don't hold up everyone waiting on live status while webhooks are checked
+    drop(lock);
 
-    if status == StatusCode::TOO_MANY_REQUESTS {
-        info!("Recieved HTTP 429 from 511.org API - bypassing requests for next minute");
-        lock.insert((), Vec::new(), Duration::from_secs(60));
+    data.webhooks
+        .notify(&scheduled_stops(data, &trips), &trips)
+        .await;
 
-        return Ok(Vec::new());
-    }
+    Ok(trips)
+}
 
-    if status != StatusCode::OK {
-        bail!("Received HTTP {status} from 511.org API: {body}");
+/// Scheduled stops of the trips in `live`, on yesterday's service as well as
+/// today's for trips running past midnight.
+fn scheduled_stops(data: &State, live: &[Stop]) -> Vec<Stop> {
+    let today = data.today(data.clock.now());
+    let mut stops = Vec::new();
+
+    for day in [today.pred(), today] {
+        stops.extend(data.stops_on(day, |s| {
+            data.runs_on(&s.service_id, day) && live.iter().any(|l| l.same_trip(s))
+        }));
     }
 
-    let resp: types::ApiResponse = serde_json::from_str(&body[3..])
-        .wrap_err_with(|| format!("failed to parse 511.org API response as json. body: {body}"))?;
+    stops
+}
 
-    debug!("Parsed API response: {:?}", resp);
-    let mut trips = Vec::new();
+/// Live status of one trip, sliced from the status of every station, in the
+/// order it reaches each station.
+pub async fn get_trip_live_status(data: &State, trip_id: i64) -> Result<Vec<Stop>> {
+    let mut stops = get_station_live_status(data).await?;
+    stops.retain(|s| s.trip_id == trip_id);
+    stops.sort_by_key(|s| s.departure);
 
-    for visit in resp
-        .ServiceDelivery
-        .StopMonitoringDelivery
-        .MonitoredStopVisit
-    {
-        if let Some(stop) = try_find_stop(visit, &data.stations) {
-            trips.push(stop);
-        }
-    }
+    Ok(stops)
+}
 
-    lock.insert((), trips.clone(), Duration::from_secs(120));
+/// Live status at one station, sliced from the status of every station. A
+/// cold cache is filled with one request for everything rather than one per
+/// stop code, which the next station asked for can use too.
+pub async fn get_live_status_at(data: &State, station_id: i64) -> Result<Vec<Stop>> {
+    station(station_id, &data.stations)?;
 
-    Ok(trips)
+    let mut stops = get_station_live_status(data).await?;
+    stops.retain(|s| s.station_id == station_id);
+
+    Ok(stops)
 }
 
-fn try_find_stop(visit: MonitoredStopVisit, stations: &[Station]) -> Option<Stop> {
-    let vehicle_ref = visit.MonitoredVehicleJourney.VehicleRef?;
-    let trip_id = vehicle_ref.parse().ok()?;
-
-    let stopcode = visit
-        .MonitoredVehicleJourney
-        .MonitoredCall
-        .StopPointRef
-        .parse()
-        .ok()?;
-
-    let station = stations
-        .iter()
-        .find(|s| s.stop_codes.contains(&stopcode))?
-        .clone();
-
-    Some(Stop {
-        // TODO: find the service ID here
-        service_id: String::new(),
-        station_name: station.name.clone(),
-        station_id: station.station_id,
-        trip_id,
-        arrival: to_local_time(
-            visit
-                .MonitoredVehicleJourney
-                .MonitoredCall
-                .ExpectedArrivalTime?,
-        ),
-        departure: to_local_time(
-            visit
-                .MonitoredVehicleJourney
-                .MonitoredCall
-                .ExpectedDepartureTime?,
-        ),
-    })
+/// Stop asking the provider for anything until the backoff is over.
+fn back_off(data: &State, cache: &mut TtlCache<(), Vec<Stop>>) {
+    info!(
+        "{} realtime provider is rate limiting - bypassing requests for {:?}",
+        data.realtime.name(),
+        data.rate_limit_backoff
+    );
+    cache.insert((), Vec::new(), data.rate_limit_backoff);
 }
 
-fn to_local_time(time: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
-    time.with_timezone(Local::now().offset())
+/// Match `visits` to scheduled stops, keeping track of the ones that didn't
+/// match. `visits` has to cover every station, since the unmatched ones
+/// replace the last refresh's.
+async fn match_and_record(data: &State, visits: Vec<Visit>) -> Vec<Stop> {
+    let matched = match_visits(data, visits);
+
+    for unmatched in &matched.unmatched {
+        data.metrics.unmatched_visit(unmatched.reason);
+    }
+    *data.unmatched_visits.write().await = matched.unmatched;
+
+    data.metrics.live_cache_refreshed(Utc::now());
+
+    matched.stops
 }
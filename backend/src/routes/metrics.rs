@@ -0,0 +1,20 @@
+use std::sync::Arc;
+
+use crate::{error::Error, State};
+use axum::{
+    extract::Extension,
+    http::{header, HeaderMap, HeaderValue},
+};
+use chrono::Utc;
+
+pub async fn metrics(Extension(data): Extension<Arc<State>>) -> Result<(HeaderMap, String), Error> {
+    let body = data.metrics.render(Utc::now())?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; version=0.0.4"),
+    );
+
+    Ok((headers, body))
+}
@@ -1,3 +1,13 @@
+pub mod board;
+pub mod bundle;
+pub mod calendar;
 pub mod live;
+pub mod metrics;
+pub mod pages;
+pub mod reminders;
+pub mod stations;
+pub mod timetable;
 pub mod trip;
 pub mod upcoming;
+pub mod webhooks;
+pub mod widget;
@@ -0,0 +1,375 @@
+use std::{
+    fmt::Write,
+    path::{Path as FsPath, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    clock::Now,
+    db,
+    error::Error,
+    html::{escape, time},
+    routes::{
+        board::build_board,
+        live::{get_station_live_status, get_trip_live_status},
+        upcoming::get_twostops,
+    },
+    State,
+};
+use axum::{
+    extract::{Extension, Path},
+    response::Html,
+};
+use chrono::{DateTime, FixedOffset, Utc};
+use eyre::{eyre, Result};
+use tracing::warn;
+use train_schedules_common::{train_type, Stop, Trip, TripStop, DIRECTIONS};
+
+/// Departures listed in each direction on a station page
+const STATION_DEPARTURES: usize = 5;
+
+/// Trains listed on a two station page
+const TWOSTOP_TRIPS: usize = 10;
+
+const APP_CONTAINER: &str = r#"<div id="app-container"></div>"#;
+
+/// Served in place of index.html when the frontend hasn't been built, so the
+/// pages still work on their own.
+const BARE_SHELL: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8" />
+<meta content="width=device-width, initial-scale=1.0" name="viewport" />
+<title>Upcoming Trains</title>
+<link rel="stylesheet" href="/main.css" />
+</head>
+<body>
+<div id="app-container"></div>
+</body>
+</html>
+"#;
+
+/// The frontend's index.html, which pages are rendered into. The frontend
+/// replaces the rendered page with its own once it has loaded, see [`Page`].
+#[derive(Debug, Clone)]
+pub struct AppShell {
+    index: PathBuf,
+}
+
+impl AppShell {
+    pub fn new(static_dir: &FsPath) -> Self {
+        Self {
+            index: static_dir.join("index.html"),
+        }
+    }
+
+    async fn template(&self) -> String {
+        match tokio::fs::read_to_string(&self.index).await {
+            Ok(template) => template,
+            Err(e) => {
+                warn!(
+                    "rendering pages without the frontend, can't read {}: {e}",
+                    self.index.display()
+                );
+                String::from(BARE_SHELL)
+            }
+        }
+    }
+}
+
+/// A page rendered by the backend, for browsers without JavaScript, slow
+/// phones still loading the frontend and link previews.
+///
+/// The markup copies the frontend's views, so the same stylesheet applies and
+/// the page doesn't jump when the frontend takes over. Yew 0.19 can't render
+/// on the server or hydrate markup it didn't render, so the views can't be
+/// shared: the frontend throws this page away and renders its own, and
+/// changes to a view's markup need copying here.
+struct Page {
+    title: String,
+    /// What link previews show under the title
+    description: String,
+    body: String,
+}
+
+impl Page {
+    async fn render(self, shell: &AppShell) -> Result<Html<String>> {
+        let template = shell.template().await;
+
+        if !template.contains(APP_CONTAINER) {
+            return Err(eyre!("{} has no {APP_CONTAINER}", shell.index.display()));
+        }
+
+        let title = escape(&self.title);
+        let description = escape(&self.description);
+        let head = format!(
+            "<title>{title}</title>
+  <meta name=\"description\" content=\"{description}\" />
+  <meta property=\"og:title\" content=\"{title}\" />
+  <meta property=\"og:description\" content=\"{description}\" />
+  <meta property=\"og:site_name\" content=\"Train Schedules\" />"
+        );
+
+        let mut page = match (template.find("<title>"), template.find("</title>")) {
+            (Some(start), Some(end)) if start < end => {
+                let mut page = template.clone();
+                page.replace_range(start..end + "</title>".len(), &head);
+                page
+            }
+            _ => template.replacen("</head>", &format!("{head}\n</head>"), 1),
+        };
+
+        page = page.replacen(
+            APP_CONTAINER,
+            &format!("<div id=\"app-container\">{}</div>", self.body),
+            1,
+        );
+
+        Ok(Html(page))
+    }
+}
+
+/// `/c/station/{start}` - the station page, rendered by the backend.
+pub async fn station_page(
+    Path(start): Path<i64>,
+    Now(now): Now,
+    Extension(data): Extension<Arc<State>>,
+    Extension(shell): Extension<AppShell>,
+) -> Result<Html<String>, Error> {
+    Ok(station(&data, start, now).await?.render(&shell).await?)
+}
+
+/// The station page for `start`, copying the frontend's `StationUpcoming`.
+async fn station(data: &State, start: i64, now: DateTime<Utc>) -> Result<Page> {
+    let board = build_board(data, start, now).await?;
+
+    let mut body = format!(
+        "<h1>{}</h1><h2>Next departures</h2><div class=\"DeparturesBoard\">",
+        escape(&board.station.name)
+    );
+    for (direction_id, label) in DIRECTIONS {
+        write!(
+            body,
+            "<div class=\"DeparturesBoard-column\"><h3>{label}</h3>"
+        )?;
+        for departure in board
+            .departures
+            .iter()
+            .filter(|d| d.scheduled.direction_id == direction_id)
+            .take(STATION_DEPARTURES)
+        {
+            let stop = &departure.scheduled;
+            let live = departure.live.as_ref().map(|l| l.departure);
+
+            write!(
+                body,
+                "<div class=\"TripDisplay\">{}<div class=\"Destination\">{}<span class=\"TrainType\">{}</span></div>{}<div class=\"DepartTime\">Departing {}</div></div>",
+                trip_id(stop.trip_id),
+                escape(&stop.headsign),
+                train_type(stop.trip_id).unwrap_or_default(),
+                minutes_to_depart(departure.departure(), now),
+                time_display(stop.departure, live)
+            )?;
+        }
+        body.push_str("</div>");
+    }
+    body.push_str("</div>");
+
+    let description = match board.departures.first() {
+        Some(next) => format!(
+            "Next train {} to {} at {}",
+            next.scheduled.trip_id,
+            next.scheduled.headsign,
+            time(next.departure())
+        ),
+        None => String::from("No more trains today"),
+    };
+
+    Ok(Page {
+        title: board.station.name,
+        description,
+        body,
+    })
+}
+
+/// `/c/station/{start}/{end}` - trains from one station to another,
+/// rendered by the backend.
+pub async fn twostop_page(
+    Path((start, end)): Path<(i64, i64)>,
+    Now(now): Now,
+    Extension(data): Extension<Arc<State>>,
+    Extension(shell): Extension<AppShell>,
+) -> Result<Html<String>, Error> {
+    Ok(twostop(&data, start, end, now)
+        .await?
+        .render(&shell)
+        .await?)
+}
+
+/// The page for trains from `start` to `end`, copying the frontend's
+/// `TwostopList`.
+async fn twostop(data: &State, start: i64, end: i64, now: DateTime<Utc>) -> Result<Page> {
+    let list = get_twostops(data, start, end, now)?;
+    let live = live_or_scheduled(data).await;
+
+    let title = format!("{} to {}", list.start.name, list.end.name);
+
+    let trips = list
+        .trips
+        .iter()
+        .map(|twostop| {
+            let depart_live = twostop.start.live_in(&live).map(|s| s.departure);
+            let arrival_live = twostop.end.live_in(&live).map(|s| s.arrival);
+
+            (twostop, depart_live, arrival_live)
+        })
+        .filter(|(twostop, depart_live, _)| depart_live.unwrap_or(twostop.start.departure) > now)
+        .take(TWOSTOP_TRIPS)
+        .collect::<Vec<_>>();
+
+    let mut body = format!("<h1>{}</h1>", escape(&title));
+    for (twostop, depart_live, arrival_live) in &trips {
+        let depart = depart_live.unwrap_or(twostop.start.departure);
+        let arrive = arrival_live.unwrap_or(twostop.end.arrival);
+
+        write!(
+            body,
+            "<div class=\"TripDisplay\">{}{}<div class=\"DepartTime\">Departing {}</div><div class=\"ArrivalTime\">Arriving {}</div><div class=\"TransitTime\">{} min. in transit</div></div>",
+            trip_id(twostop.trip_id),
+            minutes_to_depart(depart, now),
+            time_display(twostop.start.departure, *depart_live),
+            time_display(twostop.end.arrival, *arrival_live),
+            (arrive - depart).num_minutes().abs()
+        )?;
+    }
+
+    let description = match trips.first() {
+        Some((twostop, depart_live, _)) => format!(
+            "Next train {} at {}",
+            twostop.trip_id,
+            time(depart_live.unwrap_or(twostop.start.departure))
+        ),
+        None => String::from("No more trains today"),
+    };
+
+    Ok(Page {
+        title,
+        description,
+        body,
+    })
+}
+
+/// `/c/trip/{id}` - every stop of a trip, rendered by the backend.
+pub async fn trip_page(
+    Path(id): Path<i64>,
+    Now(now): Now,
+    Extension(data): Extension<Arc<State>>,
+    Extension(shell): Extension<AppShell>,
+) -> Result<Html<String>, Error> {
+    Ok(trip(&data, id, now).await?.render(&shell).await?)
+}
+
+/// The page for trip `id`, copying the frontend's `TripView`.
+async fn trip(data: &State, id: i64, now: DateTime<Utc>) -> Result<Page> {
+    let stops = data.trip_stops(id, now);
+    let live = match get_trip_live_status(data, id).await {
+        Ok(live) => live,
+        Err(e) => {
+            warn!("trip page falling back to scheduled times: {e:?}");
+            Vec::new()
+        }
+    };
+    let trip = Trip::new(id, stops, &live, db::localize(now, data.timezone));
+
+    let mut body = format!("<div class=\"TripView\"><h1>{}</h1>", trip_id(id));
+    match trip.delay_minutes {
+        Some(delay) if delay > 0 => write!(
+            body,
+            "<div class=\"TripView-delay\">Running {delay} min. late</div>"
+        )?,
+        _ => {}
+    }
+    body.push_str("<ul>");
+    for stop in &trip.stops {
+        write!(
+            body,
+            "<li>{} <a href=\"/c/station/{}\">{}</a></li>",
+            trip_stop_times(stop),
+            stop.scheduled.station_id,
+            escape(&stop.scheduled.station_name)
+        )?;
+    }
+    body.push_str("</ul></div>");
+
+    let description = match (trip.stops.first(), trip.stops.last()) {
+        (Some(first), Some(last)) => format!(
+            "{} {} to {} {}",
+            time(first.departure()),
+            first.scheduled.station_name,
+            time(last.arrival()),
+            last.scheduled.station_name
+        ),
+        _ => String::from("Not running today"),
+    };
+
+    Ok(Page {
+        title: format!("Train {id}"),
+        description,
+        body,
+    })
+}
+
+/// Live status for every station, or none when it's unavailable so the page
+/// still shows the schedule.
+async fn live_or_scheduled(data: &State) -> Vec<Stop> {
+    match get_station_live_status(data).await {
+        Ok(live) => live,
+        Err(e) => {
+            warn!("page falling back to scheduled times: {e:?}");
+            Vec::new()
+        }
+    }
+}
+
+/// The same markup as the frontend's `TripId`.
+fn trip_id(id: i64) -> String {
+    format!(
+        "<a href=\"/c/trip/{id}\"><div class=\"TrainID {}\">{id}</div></a>",
+        train_type(id).unwrap_or_default()
+    )
+}
+
+fn minutes_to_depart(departure: DateTime<FixedOffset>, now: DateTime<Utc>) -> String {
+    format!(
+        "<div class=\"MinsToDepart\">{} min.</div>",
+        departure.signed_duration_since(now).num_minutes()
+    )
+}
+
+/// The same markup as the frontend's `TimeDisplay`.
+fn time_display(scheduled: DateTime<FixedOffset>, live: Option<DateTime<FixedOffset>>) -> String {
+    match live {
+        Some(live) => format!(
+            "<span class=\"TimeDisplay TimeDisplay--realtime\" title=\"Scheduled for {}\">{}</span>",
+            time(scheduled),
+            time(live)
+        ),
+        None => format!("<span class=\"TimeDisplay\">{}</span>", time(scheduled)),
+    }
+}
+
+/// Departure time, with the arrival time before it if the train waits. The
+/// same markup as the frontend's `times` in `TripView`.
+fn trip_stop_times(stop: &TripStop) -> String {
+    let live = stop.live.as_ref();
+    let departure = time_display(stop.scheduled.departure, live.map(|l| l.departure));
+
+    if !stop.waits() {
+        return departure;
+    }
+
+    format!(
+        "{} – {departure}",
+        time_display(stop.scheduled.arrival, live.map(|l| l.arrival))
+    )
+}
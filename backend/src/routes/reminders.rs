@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use crate::{error::HttpResult, push, State};
+use axum::{
+    extract::{Extension, Path},
+    Json,
+};
+use eyre::eyre;
+use train_schedules_common::{Reminder, ReminderRequest};
+
+/// Longest lead time a reminder can be set up with
+const MAX_MINUTES_BEFORE: i64 = 120;
+
+pub async fn public_key(Extension(data): Extension<Arc<State>>) -> Json<String> {
+    Json(data.vapid.public_key())
+}
+
+pub async fn create_reminder(
+    Json(request): Json<ReminderRequest>,
+    Extension(data): Extension<Arc<State>>,
+) -> HttpResult<Reminder> {
+    if !(0..=MAX_MINUTES_BEFORE).contains(&request.minutes_before) {
+        return Err(eyre!(
+            "reminders can be set between 0 and {MAX_MINUTES_BEFORE} minutes before departure"
+        )
+        .into());
+    }
+
+    let stops_at_station = data
+        .stops
+        .iter()
+        .any(|s| s.trip_id == request.trip_id && s.station_id == request.station_id);
+
+    if !stops_at_station {
+        return Err(eyre!(
+            "trip {} does not stop at station {}",
+            request.trip_id,
+            request.station_id
+        )
+        .into());
+    }
+
+    push::check_endpoint(&request.subscription, data.allow_private_targets).await?;
+
+    Ok(Json(data.reminders.add(request).await?))
+}
+
+pub async fn delete_reminder(
+    Path(id): Path<String>,
+    Extension(data): Extension<Arc<State>>,
+) -> HttpResult<bool> {
+    Ok(Json(data.reminders.remove(&id).await?))
+}
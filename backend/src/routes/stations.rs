@@ -0,0 +1,60 @@
+use std::{cmp::Ordering, sync::Arc};
+
+use crate::State;
+use axum::{
+    extract::{Extension, Query},
+    Json,
+};
+use serde::Deserialize;
+use train_schedules_common::{NearbyStation, Station};
+
+const EARTH_RADIUS_METERS: f64 = 6_371_000.0;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct NearbyQuery {
+    lat: f64,
+    lon: f64,
+    limit: Option<usize>,
+}
+
+pub async fn nearby(
+    Query(query): Query<NearbyQuery>,
+    Extension(data): Extension<Arc<State>>,
+) -> Json<Vec<NearbyStation>> {
+    Json(nearby_stations(
+        &data.stations,
+        query.lat,
+        query.lon,
+        query.limit.unwrap_or(5),
+    ))
+}
+
+fn nearby_stations(stations: &[Station], lat: f64, lon: f64, limit: usize) -> Vec<NearbyStation> {
+    let mut nearby = stations
+        .iter()
+        .map(|station| NearbyStation {
+            distance_meters: distance_meters(lat, lon, station.lat, station.lon),
+            station: station.clone(),
+        })
+        .collect::<Vec<_>>();
+
+    nearby.sort_by(|a, b| {
+        a.distance_meters
+            .partial_cmp(&b.distance_meters)
+            .unwrap_or(Ordering::Equal)
+    });
+    nearby.truncate(limit);
+
+    nearby
+}
+
+/// Great-circle distance between two coordinates using the haversine formula.
+fn distance_meters(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let d_lat = (lat2 - lat1).to_radians();
+    let d_lon = (lon2 - lon1).to_radians();
+
+    let a = (d_lat / 2.0).sin().powi(2)
+        + lat1.to_radians().cos() * lat2.to_radians().cos() * (d_lon / 2.0).sin().powi(2);
+
+    2.0 * EARTH_RADIUS_METERS * a.sqrt().asin()
+}
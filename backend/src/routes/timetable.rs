@@ -0,0 +1,137 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use crate::{clock::Now, State};
+use axum::{
+    extract::{Extension, Query},
+    Json,
+};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use train_schedules_common::{Stop, Timetable, TimetableTrip};
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TimetableQuery {
+    direction: i64,
+    date: Option<NaiveDate>,
+}
+
+pub async fn timetable(
+    Query(query): Query<TimetableQuery>,
+    Now(now): Now,
+    Extension(data): Extension<Arc<State>>,
+) -> Json<Timetable> {
+    let date = query.date.unwrap_or_else(|| data.today(now));
+
+    Json(build_timetable(&data, query.direction, date))
+}
+
+fn build_timetable(data: &State, direction_id: i64, date: NaiveDate) -> Timetable {
+    let stops = data.stops_on(date, |s| {
+        s.direction_id == direction_id && data.runs_on(&s.service_id, date)
+    });
+
+    let mut trip_stops: HashMap<i64, Vec<&Stop>> = HashMap::new();
+    for stop in &stops {
+        trip_stops.entry(stop.trip_id).or_default().push(stop);
+    }
+    for stops in trip_stops.values_mut() {
+        stops.sort_by_key(|s| s.stop_sequence);
+    }
+
+    let stations = station_order(&trip_stops)
+        .into_iter()
+        .filter_map(|id| data.stations.iter().find(|s| s.station_id == id).cloned())
+        .collect::<Vec<_>>();
+
+    let mut trips = trip_stops
+        .into_iter()
+        .map(|(trip_id, stops)| TimetableTrip {
+            trip_id,
+            stops: stations
+                .iter()
+                .map(|station| {
+                    stops
+                        .iter()
+                        .find(|s| s.station_id == station.station_id)
+                        .map(|s| (*s).clone())
+                })
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+
+    trips.sort_by_key(|t| {
+        (
+            t.stops.iter().flatten().map(|s| s.departure).min(),
+            t.trip_id,
+        )
+    });
+
+    Timetable {
+        direction_id,
+        date,
+        stations,
+        trips,
+    }
+}
+
+/// Merge the stop order of every trip into a single station order, so that
+/// stations only served by some trips still land between their neighbours.
+/// Ties are broken by the order stations appear in the longest trips.
+pub fn station_order(trips: &HashMap<i64, Vec<&Stop>>) -> Vec<i64> {
+    let mut trips = trips.iter().collect::<Vec<_>>();
+    trips.sort_by_key(|(trip_id, stops)| (std::cmp::Reverse(stops.len()), **trip_id));
+
+    let mut rank = HashMap::new();
+    let mut successors: HashMap<i64, HashSet<i64>> = HashMap::new();
+    let mut predecessor_count: HashMap<i64, usize> = HashMap::new();
+
+    for (_, stops) in &trips {
+        for stop in stops.iter() {
+            let next_rank = rank.len();
+            rank.entry(stop.station_id).or_insert(next_rank);
+            predecessor_count.entry(stop.station_id).or_insert(0);
+        }
+
+        for pair in stops.windows(2) {
+            let (from, to) = (pair[0].station_id, pair[1].station_id);
+
+            if successors.entry(from).or_default().insert(to) {
+                *predecessor_count.entry(to).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let mut order = Vec::with_capacity(rank.len());
+
+    while !predecessor_count.is_empty() {
+        let ready = predecessor_count
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(station, _)| *station)
+            .min_by_key(|station| rank[station]);
+
+        // Trips disagreeing on the order of two stations leave a cycle; fall
+        // back to the order of appearance for whatever is left
+        let station = match ready {
+            Some(station) => station,
+            None => *predecessor_count
+                .keys()
+                .min_by_key(|station| rank[*station])
+                .unwrap(),
+        };
+
+        predecessor_count.remove(&station);
+        for next in successors.get(&station).into_iter().flatten() {
+            if let Some(count) = predecessor_count.get_mut(next) {
+                *count = count.saturating_sub(1);
+            }
+        }
+
+        order.push(station);
+    }
+
+    order
+}
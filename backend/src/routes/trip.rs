@@ -1,31 +1,93 @@
 use std::sync::Arc;
 
-use crate::State;
+use crate::{
+    clock::Now,
+    db,
+    routes::live::get_trip_live_status,
+    text::{adjusted_time, Format, Table},
+    State,
+};
 use axum::{
     extract::{Extension, Query},
+    response::{IntoResponse, Response},
     Json,
 };
 use serde::Deserialize;
-use train_schedules_common::Trip;
+use tracing::warn;
+use train_schedules_common::{Trip, TripPosition};
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct TripQuery {
     id: i64,
 }
 
+/// `/api/trip?id={id}` - every stop of the trip, with live predictions and
+/// delays for the stations it has yet to reach and where the train is now.
+/// As a table for `Accept: text/plain` and `?format=txt`.
 pub async fn trip(
     Query(query): Query<TripQuery>,
+    format: Format,
+    Now(now): Now,
     Extension(data): Extension<Arc<State>>,
-) -> Json<Trip> {
-    let stops = data
-        .stops
-        .iter()
-        .filter(|s| s.trip_id == query.id)
-        .cloned()
-        .collect();
-
-    Json(Trip {
-        trip_id: query.id,
-        stops,
-    })
+) -> Response {
+    let stops = data.trip_stops(query.id, now);
+
+    let live = match get_trip_live_status(&data, query.id).await {
+        Ok(live) => live,
+        Err(e) => {
+            warn!("trip {} falling back to scheduled times: {e:?}", query.id);
+            Vec::new()
+        }
+    };
+
+    let trip = Trip::new(query.id, stops, &live, db::localize(now, data.timezone));
+
+    match format {
+        Format::Json => Json(trip).into_response(),
+        Format::Text => trip_text(&trip).into_response(),
+    }
+}
+
+/// The stops table for `trip`.
+fn trip_text(trip: &Trip) -> String {
+    let status = match trip.delay_minutes {
+        None => String::from("scheduled times"),
+        Some(0) => String::from("on time"),
+        Some(delay) if delay > 0 => format!("{delay} min. late"),
+        Some(delay) => format!("{} min. early", -delay),
+    };
+
+    if trip.stops.is_empty() {
+        return format!("Train {} is not running today\n", trip.trip_id);
+    }
+
+    let mut table = Table::new(&["Station", "Arrives", "Departs", "Notes"]);
+    for (index, stop) in trip.stops.iter().enumerate() {
+        let live = stop.live.as_ref();
+
+        let mut notes = Vec::new();
+        match trip.position {
+            TripPosition::AtStop { stop_index } if stop_index == index => {
+                notes.push("Train is here")
+            }
+            TripPosition::Between { stop_index, .. } if stop_index + 1 == index => {
+                notes.push("Next stop")
+            }
+            _ => {}
+        }
+        if stop.scheduled.drop_off_only() {
+            notes.push("Drop off only");
+        } else if stop.scheduled.pickup_only() {
+            notes.push("Pick up only");
+        }
+
+        table.push(vec![
+            stop.scheduled.station_name.clone(),
+            adjusted_time(stop.scheduled.arrival, live.map(|l| l.arrival)),
+            adjusted_time(stop.scheduled.departure, live.map(|l| l.departure)),
+            notes.join(", "),
+        ]);
+    }
+
+    format!("Train {}, {status}\n\n{}", trip.trip_id, table.render())
 }
@@ -1,14 +1,24 @@
-use std::{collections::HashMap, sync::Arc};
-
-use crate::{db::Service, error::HttpResult, State};
+use std::sync::Arc;
+
+use crate::{
+    clock::Now,
+    db::{self, Service},
+    error::Error,
+    html::time,
+    routes::live::get_station_live_status,
+    text::{adjusted_time, Format, Table},
+    State,
+};
 use axum::{
     extract::{Extension, Query},
+    response::{IntoResponse, Response},
     Json,
 };
-use chrono::{Datelike, FixedOffset, Local};
+use chrono::{DateTime, NaiveDate, Utc};
 use eyre::Result;
 use serde::{Deserialize, Serialize};
-use train_schedules_common::{Station, Stop, TwoStop, TwoStopList};
+use tracing::warn;
+use train_schedules_common::{twostops, Station, Stop, TwoStopList};
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct UpcomingTripsQuery {
@@ -26,40 +36,142 @@ where
     Ok(value)
 }
 
+/// `/api/upcoming-trips?start={start}[&end={end}]` - departures from a
+/// station, or trains from one station to another. As JSON with scheduled
+/// times, or as a table with live times for `Accept: text/plain` and
+/// `?format=txt`.
 pub async fn upcoming_trips(
     Query(query): Query<UpcomingTripsQuery>,
+    format: Format,
+    Now(now): Now,
     Extension(data): Extension<Arc<State>>,
-) -> HttpResult<serde_json::Value> {
-    match query.end {
-        Some(end) => Ok(Json(as_json_value(&get_twostops(
-            &*data,
-            query.start,
-            end,
-        )?)?)),
-        None => Ok(Json(as_json_value(&get_upcoming(&*data, query.start))?)),
+) -> Result<Response, Error> {
+    if format == Format::Text {
+        return Ok(upcoming_text(&data, query.start, query.end, now)
+            .await?
+            .into_response());
     }
+
+    let value = match query.end {
+        Some(end) => as_json_value(&get_twostops(&data, query.start, end, now)?)?,
+        None => as_json_value(&get_upcoming(&data, query.start, now))?,
+    };
+
+    Ok(Json(value).into_response())
 }
 
-fn get_upcoming(data: &State, station_id: i64) -> Vec<Stop> {
-    let services = active_service_ids(&data.services);
+/// The departures table for `upcoming_trips`.
+async fn upcoming_text(
+    data: &State,
+    start: i64,
+    end: Option<i64>,
+    now: DateTime<Utc>,
+) -> Result<String> {
+    let (live, footer) = match get_station_live_status(data).await {
+        Ok(live) => (live, ""),
+        Err(e) => {
+            warn!("departures table for {start} falling back to scheduled times: {e:?}");
+            (Vec::new(), "\nLive train times are unavailable\n")
+        }
+    };
+    let minutes_until = |departure: DateTime<_>| {
+        format!(
+            "{} min.",
+            departure.signed_duration_since(now).num_minutes()
+        )
+    };
+
+    let (title, table) = match end {
+        Some(end) => {
+            let list = get_twostops(data, start, end, now)?;
+            let mut table = Table::new(&["Train", "Departs", "Arrives", "Duration", "In"]);
+
+            for twostop in &list.trips {
+                let depart_live = twostop.start.live_in(&live).map(|l| l.departure);
+                let arrival_live = twostop.end.live_in(&live).map(|l| l.arrival);
+                let depart = depart_live.unwrap_or(twostop.start.departure);
+                let arrive = arrival_live.unwrap_or(twostop.end.arrival);
+
+                if depart <= now {
+                    continue;
+                }
+
+                table.push(vec![
+                    twostop.trip_id.to_string(),
+                    adjusted_time(twostop.start.departure, depart_live),
+                    adjusted_time(twostop.end.arrival, arrival_live),
+                    format!("{} min.", (arrive - depart).num_minutes()),
+                    minutes_until(depart),
+                ]);
+            }
+
+            (format!("{} to {}", list.start.name, list.end.name), table)
+        }
+        None => {
+            let station = station(start, &data.stations)?;
+            let mut table = Table::new(&["Train", "To", "Departs", "Platform", "In"]);
+
+            for stop in get_upcoming(data, start, now) {
+                let live = stop.live_in(&live);
+                let depart_live = live.map(|l| l.departure);
+                let depart = depart_live.unwrap_or(stop.departure);
+
+                if depart <= now {
+                    continue;
+                }
+
+                let platform = live
+                    .and_then(|l| l.platform.as_ref())
+                    .or(stop.platform.as_ref());
+
+                table.push(vec![
+                    stop.trip_id.to_string(),
+                    stop.headsign.clone(),
+                    adjusted_time(stop.departure, depart_live),
+                    platform.cloned().unwrap_or_default(),
+                    minutes_until(depart),
+                ]);
+            }
+
+            (station.name, table)
+        }
+    };
+
+    let table = if table.is_empty() {
+        String::from("No more trains today\n")
+    } else {
+        table.render()
+    };
+
+    Ok(format!(
+        "{title} at {}\n\n{table}{footer}",
+        time(db::localize(now, data.timezone))
+    ))
+}
 
-    let now = Local::now().with_timezone(&FixedOffset::east(0));
+pub fn get_upcoming(data: &State, station_id: i64, now: DateTime<Utc>) -> Vec<Stop> {
+    let today = data.today(now);
+    let mut stops = Vec::new();
 
-    let mut stops = data
-        .stops
-        .iter()
-        .filter(|s| {
-            s.station_id == station_id && s.departure > now && services.contains(&s.service_id)
-        })
-        .cloned()
-        .collect::<Vec<_>>();
+    // Yesterday's trips may still be running after midnight
+    for day in [today.pred(), today] {
+        let services = active_service_ids(&data.services, day);
+
+        stops.extend(
+            data.stops_on(day, |s| {
+                s.station_id == station_id && services.contains(&s.service_id)
+            })
+            .into_iter()
+            .filter(|s| s.departure > now),
+        );
+    }
 
-    stops.sort_by(|a, b| a.departure.cmp(&b.departure));
+    stops.sort_by_key(|s| s.departure);
 
     stops
 }
 
-fn station(id: i64, stations: &[Station]) -> Result<Station> {
+pub fn station(id: i64, stations: &[Station]) -> Result<Station> {
     stations
         .iter()
         .find(|s| s.station_id == id)
@@ -67,10 +179,32 @@ fn station(id: i64, stations: &[Station]) -> Result<Station> {
         .ok_or_else(|| eyre::eyre!("no station found with id {id}"))
 }
 
-fn get_twostops(data: &State, start_station_id: i64, end_station_id: i64) -> Result<TwoStopList> {
-    let services = active_service_ids(&data.services);
+pub fn get_twostops(
+    data: &State,
+    start_station_id: i64,
+    end_station_id: i64,
+    now: DateTime<Utc>,
+) -> Result<TwoStopList> {
+    let today = data.today(now);
+    let mut trips = Vec::new();
+
+    // Yesterday's trips may still be running after midnight
+    for day in [today.pred(), today] {
+        let services = active_service_ids(&data.services, day);
+
+        trips.extend(
+            twostops(
+                &data.stops_on(day, |s| services.contains(&s.service_id)),
+                start_station_id,
+                end_station_id,
+                &services,
+            )
+            .into_iter()
+            .filter(|t| day == today || t.end.arrival > now),
+        );
+    }
 
-    let trips = twostops(&data.stops, start_station_id, end_station_id, &services);
+    trips.sort_by_key(|t| t.start.departure);
 
     let start_station = station(start_station_id, &data.stations)?;
     let end_station = station(end_station_id, &data.stations)?;
@@ -82,61 +216,10 @@ fn get_twostops(data: &State, start_station_id: i64, end_station_id: i64) -> Res
     })
 }
 
-fn active_service_ids(services: &[Service]) -> Vec<String> {
-    let today = Local::today().with_timezone(&FixedOffset::east(0));
-
+fn active_service_ids(services: &[Service], date: NaiveDate) -> Vec<String> {
     services
         .iter()
-        .filter(|s| {
-            s.start_date < today && today < s.end_date && s.weekdays.contains(&today.weekday())
-        })
+        .filter(|s| s.runs_on(date))
         .map(|s| s.id.clone())
         .collect()
 }
-
-fn twostops(
-    stops: &[Stop],
-    start_station: i64,
-    end_station: i64,
-    services: &[String],
-) -> Vec<TwoStop> {
-    let mut trips = HashMap::new();
-
-    for stop in stops {
-        if stop.station_id != start_station && stop.station_id != end_station {
-            continue;
-        }
-
-        if !services.contains(&stop.service_id) {
-            continue;
-        }
-
-        trips
-            .entry(stop.trip_id)
-            .or_insert_with(Vec::new)
-            .push(stop.clone());
-    }
-
-    let mut stops = Vec::new();
-
-    for (trip_id, mut trip) in trips {
-        if trip.len() != 2 {
-            continue;
-        }
-
-        trip.sort_by(|a, b| a.departure.cmp(&b.departure));
-
-        let end = trip.pop().unwrap();
-        let start = trip.pop().unwrap();
-
-        stops.push(TwoStop {
-            trip_id,
-            start,
-            end,
-        });
-    }
-
-    stops.sort_by(|a, b| a.start.departure.cmp(&b.start.departure));
-
-    stops
-}
@@ -0,0 +1,107 @@
+use std::sync::Arc;
+
+use crate::{
+    error::{self, HttpResult},
+    webhooks::check_target,
+    State,
+};
+use async_trait::async_trait;
+use axum::{
+    extract::{Extension, FromRequest, Path, RequestParts},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use eyre::eyre;
+use reqwest::Url;
+use train_schedules_common::{Webhook, WebhookDelivery, WebhookRequest, WebhookWatch};
+
+/// Proof the request came with the webhooks admin token, as
+/// `Authorization: Bearer <token>`.
+pub struct Admin;
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Admin {
+    type Rejection = Response;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Extension(data) = Extension::<Arc<State>>::from_request(req)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        if !data.webhooks.has_admin_token() {
+            return Err(error::error_response(
+                StatusCode::FORBIDDEN,
+                eyre!("webhooks can't be managed without an admin token configured"),
+            ));
+        }
+
+        let token = req
+            .headers()
+            .and_then(|headers| headers.get(header::AUTHORIZATION))
+            .and_then(|authorization| authorization.to_str().ok())
+            .and_then(|authorization| authorization.strip_prefix("Bearer "))
+            .unwrap_or_default();
+
+        if !data.webhooks.is_admin(token) {
+            return Err(error::error_response(
+                StatusCode::UNAUTHORIZED,
+                eyre!("missing or wrong admin token"),
+            ));
+        }
+
+        Ok(Self)
+    }
+}
+
+pub async fn list_webhooks(_: Admin, Extension(data): Extension<Arc<State>>) -> Json<Vec<Webhook>> {
+    Json(data.webhooks.all().await)
+}
+
+pub async fn create_webhook(
+    _: Admin,
+    Json(request): Json<WebhookRequest>,
+    Extension(data): Extension<Arc<State>>,
+) -> HttpResult<Webhook> {
+    let url = Url::parse(&request.target_url)
+        .map_err(|e| eyre!("invalid target url {}: {e}", request.target_url))?;
+
+    check_target(&url, data.allow_private_targets).await?;
+
+    if request.delay_threshold_minutes < 1 {
+        return Err(eyre!("delay threshold must be at least 1 minute").into());
+    }
+
+    match request.watch {
+        WebhookWatch::Trip { trip_id } => {
+            if !data.stops.iter().any(|s| s.trip_id == trip_id) {
+                return Err(eyre!("no trip found with id {trip_id}").into());
+            }
+        }
+        WebhookWatch::StationPair { start, end } => {
+            for id in [start, end] {
+                if !data.stations.iter().any(|s| s.station_id == id) {
+                    return Err(eyre!("no station found with id {id}").into());
+                }
+            }
+        }
+    }
+
+    Ok(Json(data.webhooks.add(request).await?))
+}
+
+pub async fn delete_webhook(
+    _: Admin,
+    Path(id): Path<String>,
+    Extension(data): Extension<Arc<State>>,
+) -> HttpResult<bool> {
+    Ok(Json(data.webhooks.remove(&id).await?))
+}
+
+pub async fn webhook_deliveries(
+    _: Admin,
+    Path(id): Path<String>,
+    Extension(data): Extension<Arc<State>>,
+) -> Json<Vec<WebhookDelivery>> {
+    Json(data.webhooks.deliveries(&id).await)
+}
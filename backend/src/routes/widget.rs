@@ -0,0 +1,373 @@
+use std::{fmt::Write, sync::Arc};
+
+use crate::{
+    clock::Now,
+    error::{Error, HttpResult},
+    html::{escape, time},
+    routes::{
+        live::get_live_status_at,
+        upcoming::{get_twostops, get_upcoming, station},
+    },
+    State,
+};
+use axum::{
+    extract::{Extension, Path, Query},
+    http::{header, HeaderMap},
+    response::Html,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use eyre::{bail, eyre, Result};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use train_schedules_common::Stop;
+
+const DEFAULT_COUNT: usize = 3;
+const MAX_COUNT: usize = 10;
+const DEFAULT_WIDTH: u32 = 400;
+
+/// How often the widget reloads itself, in seconds
+const REFRESH_SECONDS: u32 = 60;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Light,
+    Dark,
+}
+
+impl Theme {
+    fn as_str(self) -> &'static str {
+        match self {
+            Theme::Light => "light",
+            Theme::Dark => "dark",
+        }
+    }
+}
+
+/// Options for how a widget looks, the same for the widget itself and for
+/// oEmbed requests for it.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct WidgetOptions {
+    /// How many trains to list
+    count: Option<usize>,
+    /// Light unless asked for dark
+    theme: Option<Theme>,
+    /// Only the train and departure time, in smaller type
+    #[serde(default)]
+    compact: bool,
+}
+
+impl WidgetOptions {
+    fn count(&self) -> usize {
+        self.count.unwrap_or(DEFAULT_COUNT).clamp(1, MAX_COUNT)
+    }
+
+    /// How tall the widget needs to be, in pixels
+    fn height(&self) -> u32 {
+        let (header, row) = if self.compact { (30, 22) } else { (44, 34) };
+
+        header + row * self.count() as u32
+    }
+
+    /// The options in `url`'s query, ignoring anything else there
+    fn from_url(url: &Url) -> Self {
+        let mut options = Self::default();
+
+        for (key, value) in url.query_pairs() {
+            match &*key {
+                "count" => options.count = value.parse().ok(),
+                "theme" if value == "dark" => options.theme = Some(Theme::Dark),
+                "compact" => options.compact = value == "true",
+                _ => {}
+            }
+        }
+
+        options
+    }
+
+    fn theme(&self) -> Theme {
+        self.theme.unwrap_or(Theme::Light)
+    }
+
+    fn query(&self) -> String {
+        format!(
+            "count={}&theme={}&compact={}",
+            self.count(),
+            self.theme().as_str(),
+            self.compact
+        )
+    }
+}
+
+/// `/embed/station/{start}` - the next trains leaving a station, as a
+/// standalone HTML page to put in an iframe.
+pub async fn station_widget(
+    Path(start): Path<i64>,
+    Query(options): Query<WidgetOptions>,
+    Now(now): Now,
+    Extension(data): Extension<Arc<State>>,
+) -> Result<Html<String>, Error> {
+    Ok(Html(
+        render_widget(&data, start, None, &options, now).await?,
+    ))
+}
+
+/// `/embed/station/{start}/{end}` - the next trains from one station to
+/// another, as a standalone HTML page to put in an iframe.
+pub async fn twostop_widget(
+    Path((start, end)): Path<(i64, i64)>,
+    Query(options): Query<WidgetOptions>,
+    Now(now): Now,
+    Extension(data): Extension<Arc<State>>,
+) -> Result<Html<String>, Error> {
+    Ok(Html(
+        render_widget(&data, start, Some(end), &options, now).await?,
+    ))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct OEmbedQuery {
+    url: String,
+    maxwidth: Option<u32>,
+    maxheight: Option<u32>,
+    format: Option<String>,
+}
+
+/// An oEmbed `rich` response, see <https://oembed.com>.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct OEmbed {
+    version: &'static str,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    title: String,
+    provider_name: &'static str,
+    html: String,
+    width: u32,
+    height: u32,
+    cache_age: u32,
+}
+
+/// This app's origin, if `url` is on it: the configured public url, or the
+/// host the request was sent to.
+fn own_origin(data: &State, headers: &HeaderMap, url: &Url) -> Result<String> {
+    if url.scheme() != "http" && url.scheme() != "https" {
+        bail!("{url} isn't an http or https url");
+    }
+
+    let origin = match &data.public_url {
+        Some(public_url) => public_url.origin(),
+        None => {
+            let host = headers
+                .get(header::HOST)
+                .and_then(|host| host.to_str().ok())
+                .ok_or_else(|| eyre!("no public_url is configured and the request has no Host"))?;
+
+            // Behind a proxy the scheme the app is served over isn't known,
+            // and either is fine for the same host
+            Url::parse(&format!("{}://{host}", url.scheme()))
+                .map_err(|e| eyre!("invalid Host {host}: {e}"))?
+                .origin()
+        }
+    };
+
+    if url.origin() != origin {
+        bail!(
+            "{url} isn't on this app, expected {}",
+            origin.ascii_serialization()
+        );
+    }
+
+    Ok(origin.ascii_serialization())
+}
+
+/// `/api/oembed?url=...` - how to embed a station or station pair page of
+/// the app, or a widget, as an iframe with the widget in it. Widget options
+/// are taken from the query of `url`, which has to be on this app.
+pub async fn oembed(
+    Query(query): Query<OEmbedQuery>,
+    headers: HeaderMap,
+    Extension(data): Extension<Arc<State>>,
+) -> HttpResult<OEmbed> {
+    if !matches!(query.format.as_deref(), None | Some("json")) {
+        return Err(eyre!("only json oEmbed responses are supported").into());
+    }
+
+    let url = Url::parse(&query.url).map_err(|e| eyre!("invalid url {}: {e}", query.url))?;
+    let origin = own_origin(&data, &headers, &url)?;
+    let (start, end) = widget_stations(&url)?;
+    let options = WidgetOptions::from_url(&url);
+
+    let mut title = station(start, &data.stations)?.name;
+    let mut path = format!("/embed/station/{start}");
+    if let Some(end) = end {
+        title = format!("{title} to {}", station(end, &data.stations)?.name);
+        path = format!("{path}/{end}");
+    }
+
+    let width = query
+        .maxwidth
+        .map_or(DEFAULT_WIDTH, |w| w.min(DEFAULT_WIDTH));
+    let height = query
+        .maxheight
+        .map_or(options.height(), |h| h.min(options.height()));
+
+    let src = format!("{origin}{path}?{}", options.query());
+
+    Ok(Json(OEmbed {
+        version: "1.0",
+        kind: "rich",
+        html: format!(
+            "<iframe src=\"{}\" width=\"{width}\" height=\"{height}\" title=\"{}\" frameborder=\"0\"></iframe>",
+            escape(&src),
+            escape(&title)
+        ),
+        title,
+        provider_name: "Train Schedules",
+        width,
+        height,
+        cache_age: 86400,
+    }))
+}
+
+/// The stations in an app URL like `/c/station/1/3`, or a widget URL like
+/// `/embed/station/1/3`.
+fn widget_stations(url: &Url) -> Result<(i64, Option<i64>)> {
+    let segments = url.path_segments().map(|s| s.collect::<Vec<_>>());
+
+    let ids = match segments.as_deref() {
+        Some(["c", "station", ids @ ..]) | Some(["embed", "station", ids @ ..]) => ids,
+        _ => bail!("{url} is not a station page"),
+    };
+
+    let ids = ids
+        .iter()
+        .filter(|id| !id.is_empty())
+        .map(|id| id.parse().map_err(|_| eyre!("invalid station id {id}")))
+        .collect::<Result<Vec<i64>>>()?;
+
+    match ids[..] {
+        [start] => Ok((start, None)),
+        [start, end] => Ok((start, Some(end))),
+        _ => bail!("{url} is not a station page"),
+    }
+}
+
+/// The widget page for trains leaving `start`, and going to `end` if there
+/// is one.
+async fn render_widget(
+    data: &State,
+    start: i64,
+    end: Option<i64>,
+    options: &WidgetOptions,
+    now: DateTime<Utc>,
+) -> Result<String> {
+    let start_station = station(start, &data.stations)?;
+
+    let live = match get_live_status_at(data, start).await {
+        Ok(live) => live,
+        Err(e) => {
+            warn!("widget for {start} falling back to scheduled times: {e:?}");
+            Vec::new()
+        }
+    };
+    let live_departure = |stop: &Stop| stop.live_in(&live).map(|l| l.departure);
+
+    let (title, app_path, trains) = match end {
+        Some(end) => {
+            let list = get_twostops(data, start, end, now)?;
+            let trains = list
+                .trips
+                .into_iter()
+                .map(|t| {
+                    let live = live_departure(&t.start);
+                    (t.start, live, Some(t.end.arrival))
+                })
+                .collect::<Vec<_>>();
+
+            (
+                format!("{} to {}", list.start.name, list.end.name),
+                format!("/c/station/{start}/{end}"),
+                trains,
+            )
+        }
+        None => (
+            start_station.name,
+            format!("/c/station/{start}"),
+            get_upcoming(data, start, now)
+                .into_iter()
+                .map(|s| {
+                    let live = live_departure(&s);
+                    (s, live, None)
+                })
+                .collect(),
+        ),
+    };
+
+    let mut rows = String::new();
+    for (stop, live, arrival) in trains
+        .into_iter()
+        .filter(|(stop, live, _)| live.unwrap_or(stop.departure) > now)
+        .take(options.count())
+    {
+        let departure = live.unwrap_or(stop.departure);
+        let minutes = departure.signed_duration_since(now).num_minutes();
+        let realtime = if live.is_some() { " live" } else { "" };
+
+        write!(
+            rows,
+            "<tr><td class=\"train\">{}</td><td class=\"time{realtime}\">{}</td>",
+            stop.trip_id,
+            time(departure)
+        )?;
+        if !options.compact {
+            match arrival {
+                Some(arrival) => write!(rows, "<td>{}</td>", time(arrival))?,
+                None => write!(rows, "<td>{}</td>", escape(&stop.headsign))?,
+            }
+            write!(rows, "<td class=\"minutes\">{minutes} min.</td>")?;
+        }
+        rows.push_str("</tr>");
+    }
+
+    if rows.is_empty() {
+        rows.push_str("<tr><td>No more trains today</td></tr>");
+    }
+
+    let theme = options.theme().as_str();
+    let compact = if options.compact { " compact" } else { "" };
+
+    Ok(format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta http-equiv="refresh" content="{REFRESH_SECONDS}">
+<title>{title}</title>
+<style>{WIDGET_CSS}</style>
+</head>
+<body class="{theme}{compact}">
+<a href="{app_path}" target="_blank">{title}</a>
+<table>{rows}</table>
+</body>
+</html>
+"#,
+        title = escape(&title),
+    ))
+}
+
+const WIDGET_CSS: &str = "\
+body { margin: 0; padding: 6px; font-family: Arial, Helvetica, sans-serif; font-size: 14px; }
+body.light { color: black; background-color: white; }
+body.dark { color: #eee; background-color: #222; }
+body.compact { font-size: 12px; padding: 3px; }
+a { display: block; font-weight: bold; color: inherit; text-decoration: none; margin-bottom: 4px; }
+table { width: 100%; border-collapse: collapse; }
+td { padding: 4px 2px; }
+.compact td { padding: 1px 2px; }
+.train { font-weight: bold; }
+.live { color: blue; }
+.dark .live { color: #7fb2ff; }
+.minutes { text-align: right; }
+";
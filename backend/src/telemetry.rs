@@ -0,0 +1,212 @@
+//! Logging and trace export setup, plus W3C trace context propagation so
+//! traces can follow a request from the browser through to the 511.org API.
+
+use std::str::FromStr;
+
+use axum::http::{HeaderMap, HeaderValue};
+use eyre::{bail, eyre, Result};
+use opentelemetry::{
+    global,
+    propagation::{Extractor, Injector},
+    sdk::{
+        export::trace::stdout,
+        propagation::TraceContextPropagator,
+        trace::{self, Sampler, SamplingResult, ShouldSample},
+        Resource,
+    },
+    trace::{Link, SpanKind, TraceContextExt, TraceId},
+    Context, KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{fmt::format::FmtSpan, prelude::*, EnvFilter, Registry};
+
+/// Where finished spans are sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceExporter {
+    /// Don't export traces at all
+    None,
+    /// Print spans to stdout, for debugging without a collector
+    Stdout,
+    OtlpGrpc,
+    OtlpHttp,
+}
+
+impl FromStr for TraceExporter {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "none" => Ok(Self::None),
+            "stdout" => Ok(Self::Stdout),
+            "otlp-grpc" => Ok(Self::OtlpGrpc),
+            "otlp-http" => Ok(Self::OtlpHttp),
+            _ => bail!(
+                "unknown trace exporter {s:?}, expected one of none, stdout, otlp-grpc, otlp-http"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TelemetryConfig {
+    pub exporter: TraceExporter,
+    /// Collector endpoint, defaulting to the exporter's usual local address
+    pub endpoint: Option<String>,
+    /// Fraction of traces to record, between 0 and 1. Requests the caller
+    /// has already sampled are always recorded.
+    pub sample_ratio: f64,
+    pub service_name: String,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            exporter: TraceExporter::None,
+            endpoint: None,
+            sample_ratio: 1.0,
+            service_name: String::from("train-backend"),
+        }
+    }
+}
+
+/// Samples every span whose parent was sampled, and the given ratio of the
+/// rest. Unlike [`Sampler::ParentBased`], a parent that wasn't sampled doesn't
+/// drop the trace, so the frontend leaves the decision to the backend by
+/// sending unsampled trace ids.
+#[derive(Debug, Clone, Copy)]
+pub struct ParentOrRatio(pub f64);
+
+impl ShouldSample for ParentOrRatio {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        links: &[Link],
+    ) -> SamplingResult {
+        let parent_sampled = matches!(
+            parent_context,
+            Some(cx) if cx.has_active_span() && cx.span().span_context().is_sampled()
+        );
+
+        let sampler = if parent_sampled {
+            Sampler::AlwaysOn
+        } else {
+            // Based on the trace id, so every span in a trace agrees
+            Sampler::TraceIdRatioBased(self.0)
+        };
+
+        sampler.should_sample(parent_context, trace_id, name, span_kind, attributes, links)
+    }
+}
+
+/// Install the global tracing subscriber, exporting spans as configured.
+pub fn init(config: &TelemetryConfig) -> Result<()> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let trace_config = trace::config()
+        .with_sampler(ParentOrRatio(config.sample_ratio))
+        .with_resource(Resource::new(vec![KeyValue::new(
+            "service.name",
+            config.service_name.clone(),
+        )]));
+
+    let tracer = match config.exporter {
+        TraceExporter::None => None,
+        TraceExporter::Stdout => Some(
+            stdout::new_pipeline()
+                .with_trace_config(trace_config)
+                .install_simple(),
+        ),
+        TraceExporter::OtlpGrpc => {
+            let mut exporter = opentelemetry_otlp::new_exporter().tonic();
+
+            if let Some(endpoint) = &config.endpoint {
+                exporter = exporter.with_endpoint(endpoint);
+            }
+
+            Some(
+                opentelemetry_otlp::new_pipeline()
+                    .tracing()
+                    .with_exporter(exporter)
+                    .with_trace_config(trace_config)
+                    .install_batch(opentelemetry::runtime::Tokio)?,
+            )
+        }
+        TraceExporter::OtlpHttp => {
+            let mut exporter = opentelemetry_otlp::new_exporter().http();
+
+            if let Some(endpoint) = &config.endpoint {
+                exporter = exporter.with_endpoint(endpoint);
+            }
+
+            Some(
+                opentelemetry_otlp::new_pipeline()
+                    .tracing()
+                    .with_exporter(exporter)
+                    .with_trace_config(trace_config)
+                    .install_batch(opentelemetry::runtime::Tokio)?,
+            )
+        }
+    };
+
+    let telemetry = tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer));
+
+    Registry::default()
+        .with(EnvFilter::from_default_env())
+        .with(telemetry)
+        .with(tracing_subscriber::fmt::layer().with_span_events(FmtSpan::EXIT))
+        .try_init()
+        .map_err(|e| eyre!("failed to install tracing subscriber: {e}"))?;
+
+    Ok(())
+}
+
+/// Make `span` a child of the trace context sent by the caller, if any.
+pub fn set_remote_parent(span: &Span, headers: &HeaderMap) {
+    let context =
+        global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)));
+
+    span.set_parent(context);
+}
+
+/// Headers carrying the current span's trace context, for outgoing requests.
+pub fn trace_headers() -> HeaderMap {
+    let context: Context = Span::current().context();
+    let mut headers = HeaderMap::new();
+
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(&mut headers))
+    });
+
+    headers
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl<'a> Extractor for HeaderExtractor<'a> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl<'a> Injector for HeaderInjector<'a> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            axum::http::header::HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Plain text responses, for terminals and scripts that would rather not
+//! parse JSON.
+
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRequest, Query, RequestParts},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, FixedOffset};
+use eyre::eyre;
+use serde::Deserialize;
+
+use crate::{error, html::time};
+
+/// What a response should be formatted as. `?format=txt` or `?format=json`
+/// wins, then whichever of `text/plain` and `application/json` comes first in
+/// the `Accept` header. JSON otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Text,
+}
+
+#[derive(Deserialize)]
+struct FormatQuery {
+    format: Option<String>,
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Format {
+    type Rejection = Response;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Query(query) = Query::<FormatQuery>::from_request(req)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        match query.format.as_deref() {
+            Some("txt") | Some("text") => return Ok(Self::Text),
+            Some("json") => return Ok(Self::Json),
+            Some(format) => {
+                return Err(error::eyre_into_response(eyre!(
+                    "unknown format {format}, expected txt or json"
+                )))
+            }
+            None => {}
+        }
+
+        let accept = req
+            .headers()
+            .and_then(|headers| headers.get(header::ACCEPT))
+            .and_then(|accept| accept.to_str().ok())
+            .unwrap_or_default();
+
+        // Quality values are ignored, clients asking for text put it first
+        let format = accept
+            .split(',')
+            .map(|range| range.split(';').next().unwrap_or_default().trim())
+            .find_map(|media_type| match media_type {
+                "text/plain" => Some(Self::Text),
+                "application/json" => Some(Self::Json),
+                _ => None,
+            });
+
+        Ok(format.unwrap_or(Self::Json))
+    }
+}
+
+/// A table with each column padded to line up.
+#[derive(Debug, Clone, Default)]
+pub struct Table {
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(headers: &[&str]) -> Self {
+        Self {
+            rows: vec![headers.iter().map(|h| h.to_string()).collect()],
+        }
+    }
+
+    pub fn push(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.len() <= 1
+    }
+
+    pub fn render(&self) -> String {
+        let columns = self.rows.iter().map(Vec::len).max().unwrap_or_default();
+        let widths = (0..columns)
+            .map(|i| {
+                self.rows
+                    .iter()
+                    .filter_map(|row| row.get(i))
+                    .map(|cell| cell.chars().count())
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+
+        let mut text = String::new();
+        for row in &self.rows {
+            let line = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect::<Vec<_>>()
+                .join("  ");
+
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+
+        text
+    }
+}
+
+/// The time a train is expected, and how far that is from the schedule when
+/// there's live status saying so, like `8:07 AM (+2)`.
+pub fn adjusted_time(
+    scheduled: DateTime<FixedOffset>,
+    live: Option<DateTime<FixedOffset>>,
+) -> String {
+    let expected = live.unwrap_or(scheduled);
+
+    match (expected - scheduled).num_minutes() {
+        0 => time(expected),
+        minutes => format!("{} ({minutes:+})", time(expected)),
+    }
+}
@@ -25,13 +25,22 @@ pub struct MonitoredStopVisit {
 
 #[derive(Deserialize, Debug)]
 pub struct MonitoredVehicleJourney {
+    pub FramedVehicleJourneyRef: Option<FramedVehicleJourneyRef>,
     pub VehicleRef: Option<String>,
     pub MonitoredCall: MonitoredCall,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct FramedVehicleJourneyRef {
+    pub DataFrameRef: String,
+    pub DatedVehicleJourneyRef: String,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct MonitoredCall {
     pub ExpectedArrivalTime: Option<DateTime<FixedOffset>>,
     pub ExpectedDepartureTime: Option<DateTime<FixedOffset>>,
     pub StopPointRef: String,
+    pub ArrivalPlatformName: Option<String>,
+    pub DeparturePlatformName: Option<String>,
 }
@@ -0,0 +1,482 @@
+//! Outgoing webhooks, called with a signed JSON payload when a watched train
+//! is running late.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::Utc;
+use eyre::{bail, Context, Result};
+use hmac::{Hmac, Mac};
+use rand::{rngs::OsRng, RngCore};
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{info, warn};
+use train_schedules_common::{
+    DelayNotification, Stop, Webhook, WebhookDelivery, WebhookRequest, WebhookWatch,
+};
+
+use crate::{
+    outbound::{self, resolve_public},
+    push::encode_base64,
+    routes::live::get_station_live_status,
+    State,
+};
+
+/// Header carrying the hex encoded HMAC-SHA256 of the request body
+pub const SIGNATURE_HEADER: &str = "X-Trains-Signature";
+
+/// How many delivery attempts are kept for the delivery log
+const MAX_LOG_ENTRIES: usize = 500;
+
+/// How much later a train has to get before webhooks are called again for it
+const RENOTIFY_INCREASE_MINUTES: i64 = 5;
+
+/// How often live data is refreshed while there are webhooks to evaluate
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub attempts: u32,
+    /// Wait before the first retry, doubling for each one after
+    pub base_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 4,
+            base_delay: Duration::from_secs(2),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct StoredWebhook {
+    id: String,
+    watch: WebhookWatch,
+    delay_threshold_minutes: i64,
+    target_url: String,
+    secret: String,
+}
+
+impl From<&StoredWebhook> for Webhook {
+    fn from(webhook: &StoredWebhook) -> Self {
+        Self {
+            id: webhook.id.clone(),
+            watch: webhook.watch.clone(),
+            delay_threshold_minutes: webhook.delay_threshold_minutes,
+            target_url: webhook.target_url.clone(),
+        }
+    }
+}
+
+/// A notification that is ready to be sent.
+#[derive(Debug, Clone)]
+pub struct PendingDelivery {
+    target_url: String,
+    secret: String,
+    pub notification: DelayNotification,
+}
+
+/// Registered webhooks and their delivery log. Cheap to clone, all clones
+/// share the same webhooks.
+#[derive(Clone)]
+pub struct Webhooks {
+    inner: Arc<Inner>,
+    /// Bearer token needed to manage webhooks. They can't be managed at all
+    /// without one
+    admin_token: Option<Arc<str>>,
+    /// Whether deliveries may go to private addresses
+    allow_private: bool,
+}
+
+struct Inner {
+    path: Option<PathBuf>,
+    retry: RetryPolicy,
+    webhooks: RwLock<Vec<StoredWebhook>>,
+    /// Delay each webhook was last notified of, by webhook and trip
+    notified: Mutex<HashMap<(String, i64), i64>>,
+    deliveries: Mutex<VecDeque<WebhookDelivery>>,
+}
+
+impl Webhooks {
+    pub fn in_memory(retry: RetryPolicy) -> Self {
+        Self::new(None, retry, Vec::new())
+    }
+
+    pub fn load(path: PathBuf, retry: RetryPolicy) -> Result<Self> {
+        let webhooks = if path.exists() {
+            let json = std::fs::read_to_string(&path)
+                .wrap_err_with(|| format!("failed to read webhooks from {}", path.display()))?;
+
+            serde_json::from_str(&json)
+                .wrap_err_with(|| format!("failed to parse webhooks in {}", path.display()))?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self::new(Some(path), retry, webhooks))
+    }
+
+    fn new(path: Option<PathBuf>, retry: RetryPolicy, webhooks: Vec<StoredWebhook>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                path,
+                retry,
+                webhooks: RwLock::new(webhooks),
+                notified: Mutex::new(HashMap::new()),
+                deliveries: Mutex::new(VecDeque::new()),
+            }),
+            admin_token: None,
+            allow_private: false,
+        }
+    }
+
+    pub fn with_admin_token(mut self, token: Option<String>) -> Self {
+        self.admin_token = token.filter(|t| !t.is_empty()).map(Arc::from);
+        self
+    }
+
+    /// Let deliveries go to private addresses, like receivers on the same
+    /// network while testing.
+    pub fn with_private_targets(mut self, allow: bool) -> Self {
+        self.allow_private = allow;
+        self
+    }
+
+    /// Whether `token` is the admin token. Always false when there isn't one.
+    pub fn is_admin(&self, token: &str) -> bool {
+        match &self.admin_token {
+            // Compare every byte so timing doesn't give the token away
+            Some(admin) => {
+                admin.len() == token.len()
+                    && admin
+                        .bytes()
+                        .zip(token.bytes())
+                        .fold(0, |diff, (a, b)| diff | (a ^ b))
+                        == 0
+            }
+            None => false,
+        }
+    }
+
+    pub fn has_admin_token(&self) -> bool {
+        self.admin_token.is_some()
+    }
+
+    pub async fn add(&self, request: WebhookRequest) -> Result<Webhook> {
+        let mut id = [0u8; 16];
+        OsRng.fill_bytes(&mut id);
+
+        let webhook = StoredWebhook {
+            id: encode_base64(&id),
+            watch: request.watch,
+            delay_threshold_minutes: request.delay_threshold_minutes,
+            target_url: request.target_url,
+            secret: request.secret,
+        };
+
+        let mut webhooks = self.inner.webhooks.write().await;
+        webhooks.push(webhook.clone());
+        self.save(&webhooks).await?;
+
+        Ok(Webhook::from(&webhook))
+    }
+
+    /// Remove the webhook with the given id, returning whether it existed.
+    pub async fn remove(&self, id: &str) -> Result<bool> {
+        let mut webhooks = self.inner.webhooks.write().await;
+
+        let before = webhooks.len();
+        webhooks.retain(|w| w.id != id);
+
+        if webhooks.len() == before {
+            return Ok(false);
+        }
+
+        self.save(&webhooks).await?;
+        self.inner
+            .notified
+            .lock()
+            .await
+            .retain(|(webhook_id, _), _| webhook_id != id);
+
+        Ok(true)
+    }
+
+    pub async fn all(&self) -> Vec<Webhook> {
+        self.inner
+            .webhooks
+            .read()
+            .await
+            .iter()
+            .map(Webhook::from)
+            .collect()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.inner.webhooks.read().await.is_empty()
+    }
+
+    /// Delivery attempts for a webhook, oldest first.
+    pub async fn deliveries(&self, id: &str) -> Vec<WebhookDelivery> {
+        self.inner
+            .deliveries
+            .lock()
+            .await
+            .iter()
+            .filter(|d| d.webhook_id == id)
+            .cloned()
+            .collect()
+    }
+
+    /// Compare `live` departures to the `scheduled` ones, returning a
+    /// notification for every webhook whose threshold a train has newly
+    /// crossed. A train is reported again only if it gets noticeably later.
+    pub async fn evaluate(&self, scheduled: &[Stop], live: &[Stop]) -> Vec<PendingDelivery> {
+        let webhooks = self.inner.webhooks.read().await;
+        let mut notified = self.inner.notified.lock().await;
+
+        // Trips that left the live feed have finished, so forget about them
+        notified.retain(|(_, trip_id), _| live.iter().any(|s| s.trip_id == *trip_id));
+
+        let mut pending = Vec::new();
+
+        for webhook in webhooks.iter() {
+            for (live_stop, scheduled_stop) in watched_stops(&webhook.watch, scheduled, live) {
+                let delay = (live_stop.departure - scheduled_stop.departure).num_minutes();
+
+                if delay < webhook.delay_threshold_minutes {
+                    continue;
+                }
+
+                let key = (webhook.id.clone(), live_stop.trip_id);
+
+                if let Some(last) = notified.get(&key) {
+                    if delay < last + RENOTIFY_INCREASE_MINUTES {
+                        continue;
+                    }
+                }
+
+                notified.insert(key, delay);
+
+                pending.push(PendingDelivery {
+                    target_url: webhook.target_url.clone(),
+                    secret: webhook.secret.clone(),
+                    notification: DelayNotification {
+                        webhook_id: webhook.id.clone(),
+                        trip_id: live_stop.trip_id,
+                        station_id: live_stop.station_id,
+                        station_name: live_stop.station_name.clone(),
+                        scheduled_departure: scheduled_stop.departure,
+                        expected_departure: live_stop.departure,
+                        delay_minutes: delay,
+                    },
+                });
+            }
+        }
+
+        pending
+    }
+
+    /// Send a notification, retrying with backoff on network errors, server
+    /// errors and rate limiting. Returns whether it was delivered. The target
+    /// is checked again for every attempt, as the addresses it resolves to
+    /// may have changed since it was created.
+    pub async fn deliver(&self, pending: PendingDelivery) -> bool {
+        let body = match serde_json::to_vec(&pending.notification) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("failed to serialize webhook payload: {e:?}");
+                return false;
+            }
+        };
+
+        let signature = sign(&pending.secret, &body);
+        let mut delay = self.inner.retry.base_delay;
+
+        for attempt in 1..=self.inner.retry.attempts {
+            let (status, error) = match self.post(&pending.target_url, &signature, &body).await {
+                Ok(status) => (Some(status), None),
+                Err(e) => (None, Some(format!("{e:#}"))),
+            };
+            let success = matches!(status, Some(s) if s.is_success());
+
+            self.log(WebhookDelivery {
+                webhook_id: pending.notification.webhook_id.clone(),
+                trip_id: pending.notification.trip_id,
+                delay_minutes: pending.notification.delay_minutes,
+                attempt,
+                attempted_at: Utc::now(),
+                status: status.map(|s| s.as_u16()),
+                error,
+                success,
+            })
+            .await;
+
+            if success {
+                info!(
+                    "delivered delay of trip {} to webhook {}",
+                    pending.notification.trip_id, pending.notification.webhook_id
+                );
+                return true;
+            }
+
+            let retryable = match status {
+                Some(status) => status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS,
+                None => true,
+            };
+
+            if !retryable || attempt == self.inner.retry.attempts {
+                break;
+            }
+
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+
+        warn!(
+            "giving up on delivering delay of trip {} to webhook {}",
+            pending.notification.trip_id, pending.notification.webhook_id
+        );
+
+        false
+    }
+
+    /// Post a signed notification `body` to `target_url`, over a connection
+    /// to the address that was checked.
+    async fn post(&self, target_url: &str, signature: &str, body: &[u8]) -> Result<StatusCode> {
+        let url = Url::parse(target_url)?;
+        let client = outbound::client_for(&url, self.allow_private).await?;
+
+        let response = client
+            .post(url)
+            .timeout(Duration::from_secs(10))
+            .header("Content-Type", "application/json")
+            .header("X-Trains-Event", "trip.delayed")
+            .header(SIGNATURE_HEADER, signature)
+            .body(body.to_vec())
+            .send()
+            .await?;
+
+        Ok(response.status())
+    }
+
+    /// Evaluate `live` and deliver the resulting notifications in the
+    /// background.
+    pub async fn notify(&self, scheduled: &[Stop], live: &[Stop]) {
+        for pending in self.evaluate(scheduled, live).await {
+            let webhooks = self.clone();
+
+            tokio::spawn(async move { webhooks.deliver(pending).await });
+        }
+    }
+
+    async fn log(&self, delivery: WebhookDelivery) {
+        let mut deliveries = self.inner.deliveries.lock().await;
+
+        deliveries.push_back(delivery);
+
+        while deliveries.len() > MAX_LOG_ENTRIES {
+            deliveries.pop_front();
+        }
+    }
+
+    async fn save(&self, webhooks: &[StoredWebhook]) -> Result<()> {
+        if let Some(path) = &self.inner.path {
+            let json = serde_json::to_string(webhooks)?;
+
+            tokio::fs::write(path, json)
+                .await
+                .wrap_err_with(|| format!("failed to save webhooks to {}", path.display()))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Hex encoded HMAC-SHA256 of `body`, as sent in [`SIGNATURE_HEADER`].
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Make sure webhooks may be sent to `url`: http or https, on a host that
+/// only resolves to public addresses unless `allow_private` is set, so
+/// webhooks can't be pointed at the server itself or the network it's on.
+pub async fn check_target(url: &Url, allow_private: bool) -> Result<()> {
+    if url.scheme() != "http" && url.scheme() != "https" {
+        bail!("target url must be http or https");
+    }
+
+    if !allow_private {
+        resolve_public(url).await?;
+    }
+
+    Ok(())
+}
+
+/// Live stops that `watch` covers, one per trip, paired with the scheduled
+/// stop they correspond to.
+fn watched_stops<'a>(
+    watch: &WebhookWatch,
+    scheduled: &'a [Stop],
+    live: &'a [Stop],
+) -> Vec<(&'a Stop, &'a Stop)> {
+    // A trip can be scheduled on more than one day, the live one is the
+    // closest to its live time
+    let scheduled_stop = |live: &Stop, station_id: i64| {
+        scheduled
+            .iter()
+            .filter(|s| s.same_trip(live) && s.station_id == station_id)
+            .min_by_key(|s| (s.departure - live.departure).num_seconds().abs())
+    };
+
+    match *watch {
+        WebhookWatch::Trip { trip_id } => live
+            .iter()
+            .filter(|s| s.trip_id == trip_id)
+            // The next station the train gets to
+            .min_by_key(|s| s.departure)
+            .and_then(|live| Some((live, scheduled_stop(live, live.station_id)?)))
+            .into_iter()
+            .collect(),
+        WebhookWatch::StationPair { start, end } => live
+            .iter()
+            .filter(|s| s.station_id == start)
+            .filter_map(|live| {
+                let departure = scheduled_stop(live, start)?;
+                let arrival = scheduled_stop(live, end)?;
+
+                (departure.departure < arrival.arrival).then_some((live, departure))
+            })
+            .collect(),
+    }
+}
+
+/// Keep live data fresh while there are webhooks, so delays are noticed even
+/// when nobody has the site open.
+pub async fn run_poller(state: Arc<State>) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if state.webhooks.is_empty().await {
+            continue;
+        }
+
+        if let Err(e) = get_station_live_status(&state).await {
+            warn!("failed to refresh live status for webhooks: {e:?}");
+        }
+    }
+}
@@ -0,0 +1,840 @@
+//! End to end scenarios, running the app against the fixture feed in
+//! `tests/fixtures/gtfs` and a mock 511.org API.
+
+use axum::http::StatusCode;
+use chrono::prelude::*;
+use chrono_tz::US::Pacific;
+use train_schedules_common::{Boarding, DeparturesBoard, Stop, Trip, TripPosition, TwoStopList};
+
+use common::{pacific, recording, TestApp};
+
+mod common;
+
+const SAN_FRANCISCO: i64 = 1;
+const PALO_ALTO: i64 = 2;
+const SAN_JOSE: i64 = 3;
+
+fn trip_ids(stops: &[Stop]) -> Vec<i64> {
+    stops.iter().map(|s| s.trip_id).collect()
+}
+
+#[tokio::test]
+async fn upcoming_lists_the_rest_of_todays_departures() {
+    // A Tuesday morning, after the first train has left
+    let app = TestApp::start(pacific(2022, 3, 8, 7, 30), Vec::new());
+
+    let stops: Vec<Stop> = app
+        .get_json(&format!("/api/upcoming-trips?start={SAN_FRANCISCO}"))
+        .await;
+
+    assert_eq!(trip_ids(&stops), vec![102, 103, 199]);
+    assert_eq!(
+        stops[1].departure,
+        Pacific.ymd(2022, 3, 8).and_hms(17, 0, 0)
+    );
+}
+
+#[tokio::test]
+async fn upcoming_follows_the_clock() {
+    let app = TestApp::start(pacific(2022, 3, 1, 7, 30), Vec::new());
+
+    app.clock.set(pacific(2022, 3, 1, 18, 0));
+
+    let stops: Vec<Stop> = app
+        .get_json(&format!("/api/upcoming-trips?start={SAN_FRANCISCO}"))
+        .await;
+
+    assert_eq!(trip_ids(&stops), vec![199]);
+}
+
+#[tokio::test]
+async fn upcoming_includes_trips_running_past_midnight() {
+    // Just after midnight on Saturday, Friday's last train is still out
+    let app = TestApp::start(pacific(2022, 3, 5, 0, 10), Vec::new());
+
+    let stops: Vec<Stop> = app
+        .get_json(&format!("/api/upcoming-trips?start={PALO_ALTO}"))
+        .await;
+
+    assert_eq!(trip_ids(&stops), vec![199, 201, 202]);
+    assert_eq!(
+        stops[0].departure,
+        Pacific.ymd(2022, 3, 5).and_hms(0, 36, 0)
+    );
+}
+
+#[tokio::test]
+async fn holidays_run_the_weekend_schedule() {
+    // Memorial Day, a Monday
+    let app = TestApp::start(pacific(2022, 5, 30, 6, 0), Vec::new());
+
+    let stops: Vec<Stop> = app
+        .get_json(&format!("/api/upcoming-trips?start={SAN_FRANCISCO}"))
+        .await;
+
+    assert_eq!(trip_ids(&stops), vec![201, 202]);
+}
+
+#[tokio::test]
+async fn now_parameter_overrides_the_clock_when_allowed() {
+    let app = TestApp::with_time_override(pacific(2022, 3, 1, 7, 30));
+
+    // Saturday morning
+    let stops: Vec<Stop> = app
+        .get_json(&format!(
+            "/api/upcoming-trips?start={SAN_FRANCISCO}&now=2022-03-05T08:00:00-08:00"
+        ))
+        .await;
+
+    assert_eq!(trip_ids(&stops), vec![201, 202]);
+    assert_eq!(stops[0].departure, Pacific.ymd(2022, 3, 5).and_hms(9, 0, 0));
+}
+
+#[tokio::test]
+async fn now_parameter_is_ignored_unless_allowed() {
+    let app = TestApp::start(pacific(2022, 3, 8, 7, 30), Vec::new());
+
+    let stops: Vec<Stop> = app
+        .get_json(&format!(
+            "/api/upcoming-trips?start={SAN_FRANCISCO}&now=2022-03-05T08:00:00-08:00"
+        ))
+        .await;
+
+    assert_eq!(trip_ids(&stops), vec![102, 103, 199]);
+}
+
+#[tokio::test]
+async fn invalid_now_parameter_is_rejected() {
+    let app = TestApp::with_time_override(pacific(2022, 3, 1, 7, 30));
+
+    let response = app
+        .get(&format!(
+            "/api/upcoming-trips?start={SAN_FRANCISCO}&now=tuesday"
+        ))
+        .await;
+
+    assert!(response.status().is_client_error());
+}
+
+#[tokio::test]
+async fn two_stop_trips_only_go_in_the_requested_direction() {
+    let app = TestApp::start(pacific(2022, 3, 1, 6, 0), Vec::new());
+
+    let list: TwoStopList = app
+        .get_json(&format!(
+            "/api/upcoming-trips?start={SAN_FRANCISCO}&end={SAN_JOSE}"
+        ))
+        .await;
+
+    let trip_ids: Vec<i64> = list.trips.iter().map(|t| t.trip_id).collect();
+    assert_eq!(trip_ids, vec![101, 103, 199]);
+    assert_eq!(list.start.name, "San Francisco Caltrain");
+    assert_eq!(list.end.name, "San Jose Diridon Caltrain");
+}
+
+#[tokio::test]
+async fn overnight_trips_stay_listed_after_midnight() {
+    // Just after midnight, with Tuesday's last train still on its way
+    let app = TestApp::start(pacific(2022, 3, 9, 0, 10), Vec::new());
+
+    let list: TwoStopList = app
+        .get_json(&format!(
+            "/api/upcoming-trips?start={SAN_FRANCISCO}&end={SAN_JOSE}"
+        ))
+        .await;
+
+    let trip_ids: Vec<i64> = list.trips.iter().map(|t| t.trip_id).collect();
+    assert_eq!(trip_ids, vec![199, 101, 103, 199]);
+    assert_eq!(
+        list.trips[0].end.arrival,
+        Pacific.ymd(2022, 3, 9).and_hms(1, 20, 0)
+    );
+
+    let trip: Trip = app.get_json("/api/trip?id=199").await;
+    assert_eq!(trip.stops.len(), 3);
+    assert_eq!(
+        trip.stops[0].scheduled.departure,
+        Pacific.ymd(2022, 3, 8).and_hms(23, 50, 0)
+    );
+
+    // Once it has arrived, it's today's trip again
+    app.clock.set(pacific(2022, 3, 9, 2, 0));
+
+    let trip: Trip = app.get_json("/api/trip?id=199").await;
+    assert_eq!(
+        trip.stops[0].scheduled.departure,
+        Pacific.ymd(2022, 3, 9).and_hms(23, 50, 0)
+    );
+}
+
+#[tokio::test]
+async fn live_status_is_cached() {
+    let app = TestApp::start(
+        pacific(2022, 3, 1, 8, 0),
+        vec![(StatusCode::OK, recording("20220301T160000Z.json"))],
+    );
+
+    let stops: Vec<Stop> = app.get_json("/api/stations/live").await;
+
+    assert_eq!(trip_ids(&stops), vec![104, 104]);
+
+    let again: Vec<Stop> = app.get_json("/api/stations/live").await;
+    assert_eq!(again, stops);
+    assert_eq!(app.upstream_requests(), 1);
+}
+
+#[tokio::test]
+async fn rate_limited_live_status_backs_off() {
+    let app = TestApp::start(
+        pacific(2022, 3, 1, 8, 0),
+        vec![(StatusCode::TOO_MANY_REQUESTS, String::from("slow down"))],
+    );
+
+    let stops: Vec<Stop> = app.get_json("/api/stations/live").await;
+    assert!(stops.is_empty());
+
+    let stops: Vec<Stop> = app.get_json("/api/stations/live").await;
+    assert!(stops.is_empty());
+    assert_eq!(app.upstream_requests(), 1);
+}
+
+#[tokio::test]
+async fn malformed_live_status_is_an_error_and_not_cached() {
+    let app = TestApp::start(
+        pacific(2022, 3, 1, 8, 0),
+        vec![
+            (StatusCode::OK, String::from("\u{feff}<html>oops</html>")),
+            (StatusCode::OK, recording("20220301T163000Z.json")),
+        ],
+    );
+
+    let response = app.get("/api/stations/live").await;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    let stops: Vec<Stop> = app.get_json("/api/stations/live").await;
+    assert_eq!(trip_ids(&stops), vec![108]);
+    assert_eq!(app.upstream_requests(), 2);
+}
+
+#[tokio::test]
+async fn upstream_errors_are_reported() {
+    let app = TestApp::start(
+        pacific(2022, 3, 1, 8, 0),
+        vec![(StatusCode::SERVICE_UNAVAILABLE, String::from("down"))],
+    );
+
+    let response = app.get("/api/stations/live").await;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[tokio::test]
+async fn live_status_is_matched_to_the_scheduled_trip() {
+    let app = TestApp::start(
+        pacific(2022, 3, 1, 8, 0),
+        vec![(StatusCode::OK, recording("20220301T160000Z.json"))],
+    );
+
+    let stops: Vec<Stop> = app.get_json("/api/stations/live").await;
+
+    let palo_alto = stops.iter().find(|s| s.station_id == PALO_ALTO).unwrap();
+    assert_eq!(palo_alto.trip_id, 104);
+    assert_eq!(palo_alto.service_id, "special");
+    assert_eq!(palo_alto.stop_sequence, 2);
+    assert_eq!(palo_alto.station_name, "Palo Alto Caltrain");
+    assert_eq!(
+        palo_alto.departure,
+        Pacific.ymd(2022, 3, 1).and_hms(8, 5, 0)
+    );
+}
+
+#[tokio::test]
+async fn live_status_for_a_day_the_trip_does_not_run_is_not_matched() {
+    // Trip 104 only runs on March 1st
+    let response = recording("20220301T160000Z.json").replace(
+        "\"DataFrameRef\": \"2022-03-01\"",
+        "\"DataFrameRef\": \"2022-03-02\"",
+    );
+    let app = TestApp::start(pacific(2022, 3, 2, 8, 0), vec![(StatusCode::OK, response)]);
+
+    let stops: Vec<Stop> = app.get_json("/api/stations/live").await;
+    assert!(stops.is_empty());
+
+    let unmatched: Vec<serde_json::Value> = app.get_json("/api/debug/unmatched-visits").await;
+    let reasons: Vec<&str> = unmatched
+        .iter()
+        .map(|u| u["reason"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        reasons,
+        vec!["not_running", "not_running", "unknown_trip", "unknown_trip"]
+    );
+}
+
+#[tokio::test]
+async fn unmatched_visits_are_reported() {
+    let app = TestApp::start(
+        pacific(2022, 3, 1, 8, 30),
+        vec![(StatusCode::OK, recording("20220301T163000Z.json"))],
+    );
+
+    let stops: Vec<Stop> = app.get_json("/api/stations/live").await;
+    assert_eq!(trip_ids(&stops), vec![108]);
+
+    let unmatched: Vec<serde_json::Value> = app.get_json("/api/debug/unmatched-visits").await;
+    let reasons: Vec<&str> = unmatched
+        .iter()
+        .map(|u| u["reason"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        reasons,
+        vec!["no_journey_ref", "unknown_stop", "unknown_trip"]
+    );
+    assert_eq!(unmatched[1]["visit"]["stop_point_ref"], "SF Caltrain");
+
+    let metrics = app.get("/metrics").await.text().await.unwrap();
+    assert!(metrics.contains("realtime_unmatched_visits_total{reason=\"unknown_stop\"} 1"));
+    assert!(metrics.contains("realtime_unmatched_visits_total{reason=\"no_journey_ref\"} 1"));
+}
+
+#[tokio::test]
+async fn station_live_status_reports_unmatched_visits_everywhere() {
+    let app = TestApp::start(
+        pacific(2022, 3, 1, 8, 30),
+        vec![(StatusCode::OK, recording("20220301T163000Z.json"))],
+    );
+
+    let _: Vec<Stop> = app
+        .get_json(&format!("/api/stations/{PALO_ALTO}/live"))
+        .await;
+
+    let unmatched: Vec<serde_json::Value> = app.get_json("/api/debug/unmatched-visits").await;
+    assert_eq!(unmatched.len(), 3);
+}
+
+#[tokio::test]
+async fn station_live_status_is_sliced_from_the_cached_snapshot() {
+    let app = TestApp::start(
+        pacific(2022, 3, 1, 8, 0),
+        vec![(StatusCode::OK, recording("20220301T160000Z.json"))],
+    );
+
+    let _: Vec<Stop> = app.get_json("/api/stations/live").await;
+
+    let stops: Vec<Stop> = app
+        .get_json(&format!("/api/stations/{PALO_ALTO}/live"))
+        .await;
+
+    assert_eq!(trip_ids(&stops), vec![104]);
+    assert_eq!(stops[0].station_id, PALO_ALTO);
+    assert_eq!(app.upstream_requests(), 1);
+}
+
+#[tokio::test]
+async fn cold_station_live_status_fetches_every_station_once() {
+    let app = TestApp::start(
+        pacific(2022, 3, 1, 8, 0),
+        vec![(StatusCode::OK, recording("20220301T160000Z.json"))],
+    );
+
+    let stops: Vec<Stop> = app
+        .get_json(&format!("/api/stations/{SAN_FRANCISCO}/live"))
+        .await;
+
+    assert_eq!(trip_ids(&stops), vec![104]);
+    assert_eq!(stops[0].station_id, SAN_FRANCISCO);
+    assert_eq!(app.upstream_requests(), 1);
+
+    // Other stations are answered from the same fetch
+    let stops: Vec<Stop> = app
+        .get_json(&format!("/api/stations/{PALO_ALTO}/live"))
+        .await;
+    assert_eq!(trip_ids(&stops), vec![104]);
+    assert_eq!(app.upstream_requests(), 1);
+}
+
+#[tokio::test]
+async fn trip_live_status_follows_the_trip() {
+    let app = TestApp::start(
+        pacific(2022, 3, 1, 8, 0),
+        vec![(StatusCode::OK, recording("20220301T160000Z.json"))],
+    );
+
+    let stops: Vec<Stop> = app.get_json("/api/trip/104/live").await;
+
+    let stations: Vec<i64> = stops.iter().map(|s| s.station_id).collect();
+    assert_eq!(stations, vec![PALO_ALTO, SAN_FRANCISCO]);
+    assert!(stops.iter().all(|s| s.trip_id == 104));
+}
+
+#[tokio::test]
+async fn trip_shows_live_delay_and_position() {
+    // Trip 104 running four minutes late into Palo Alto
+    let response = recording("20220301T160000Z.json")
+        .replace("2022-03-01T08:04:00-08:00", "2022-03-01T08:08:00-08:00")
+        .replace("2022-03-01T08:05:00-08:00", "2022-03-01T08:09:00-08:00");
+    let app = TestApp::start(pacific(2022, 3, 1, 8, 0), vec![(StatusCode::OK, response)]);
+
+    let trip: Trip = app.get_json("/api/trip?id=104").await;
+
+    let stations: Vec<i64> = trip.stops.iter().map(|s| s.scheduled.station_id).collect();
+    assert_eq!(stations, vec![SAN_JOSE, PALO_ALTO, SAN_FRANCISCO]);
+
+    // Already gone from San Jose, so there's no prediction there
+    assert_eq!(trip.stops[0].live, None);
+    assert_eq!(trip.stops[1].delay_minutes, Some(4));
+    assert_eq!(trip.stops[2].delay_minutes, Some(0));
+    assert_eq!(trip.delay_minutes, Some(4));
+
+    assert_eq!(trip.segment_minutes(0), Some(48));
+    assert_eq!(trip.stops[1].dwell_minutes(), 1);
+
+    match trip.position {
+        TripPosition::Between {
+            stop_index: 0,
+            progress,
+        } => assert!((progress - 40.0 / 48.0).abs() < 1e-9),
+        position => panic!("unexpected position {:?}", position),
+    }
+
+    // The frontend keeps it up to date with the trip's own live status
+    let live: Vec<Stop> = app.get_json("/api/trip/104/live").await;
+    let now = pacific(2022, 3, 1, 8, 0).with_timezone(&FixedOffset::west(8 * 3600));
+    assert_eq!(trip.with_live(&live, now), trip);
+}
+
+#[tokio::test]
+async fn trip_falls_back_to_the_schedule_without_live_status() {
+    let app = TestApp::start(
+        pacific(2022, 3, 8, 7, 45),
+        vec![(StatusCode::INTERNAL_SERVER_ERROR, String::new())],
+    );
+
+    let trip: Trip = app.get_json("/api/trip?id=101").await;
+
+    assert_eq!(trip.stops.len(), 3);
+    assert!(trip.stops.iter().all(|s| s.live.is_none()));
+    assert_eq!(trip.delay_minutes, None);
+    assert_eq!(trip.position, TripPosition::AtStop { stop_index: 1 });
+
+    app.clock.set(pacific(2022, 3, 8, 9, 0));
+
+    let trip: Trip = app.get_json("/api/trip?id=101").await;
+    assert_eq!(trip.position, TripPosition::Finished);
+}
+
+#[tokio::test]
+async fn trip_is_not_running_on_days_off_its_service() {
+    // Trip 104 only runs on March 1st
+    let app = TestApp::start(
+        pacific(2022, 3, 2, 8, 0),
+        vec![(StatusCode::INTERNAL_SERVER_ERROR, String::new()); 2],
+    );
+
+    let trip: Trip = app.get_json("/api/trip?id=104").await;
+    assert!(trip.stops.is_empty());
+
+    let text = app.get_text("/api/trip?id=104").await;
+    assert_eq!(text, "Train 104 is not running today\n");
+}
+
+#[tokio::test]
+async fn trip_stops_are_in_sequence_with_boarding_restrictions() {
+    let app = TestApp::start(
+        pacific(2022, 3, 1, 7, 0),
+        vec![(StatusCode::INTERNAL_SERVER_ERROR, String::new())],
+    );
+
+    let trip: Trip = app.get_json("/api/trip?id=108").await;
+
+    let sequence: Vec<i64> = trip
+        .stops
+        .iter()
+        .map(|s| s.scheduled.stop_sequence)
+        .collect();
+    assert_eq!(sequence, vec![1, 2, 3]);
+
+    let palo_alto = &trip.stops[1].scheduled;
+    assert_eq!(palo_alto.station_id, PALO_ALTO);
+    assert_eq!(palo_alto.pickup_type, Boarding::None);
+    assert_eq!(palo_alto.drop_off_type, Boarding::Regular);
+    assert!(palo_alto.drop_off_only());
+
+    let san_francisco = &trip.stops[2].scheduled;
+    assert_eq!(san_francisco.pickup_type, Boarding::Regular);
+    assert!(!san_francisco.drop_off_only() && !san_francisco.pickup_only());
+    assert_eq!(
+        san_francisco.arrival,
+        Pacific.ymd(2022, 3, 1).and_hms(9, 28, 0)
+    );
+}
+
+#[tokio::test]
+async fn scheduled_platform_comes_from_the_stop() {
+    let app = TestApp::start(pacific(2022, 3, 8, 7, 0), Vec::new());
+
+    let stops: Vec<Stop> = app
+        .get_json(&format!("/api/upcoming-trips?start={PALO_ALTO}"))
+        .await;
+
+    let platforms: Vec<(i64, Option<&str>)> = stops
+        .iter()
+        .map(|s| (s.trip_id, s.platform.as_deref()))
+        .collect();
+    assert_eq!(
+        platforms,
+        vec![
+            (101, Some("SB")),
+            (102, Some("NB")),
+            (103, Some("SB")),
+            (199, Some("SB"))
+        ]
+    );
+}
+
+#[tokio::test]
+async fn live_platform_overrides_the_schedule() {
+    let response = recording("20220301T160000Z.json")
+        .replace(
+            "\"StopPointRef\": \"70171\",",
+            "\"StopPointRef\": \"70171\", \"DeparturePlatformName\": \"3\",",
+        )
+        // Arriving at San Francisco on the southbound side
+        .replace("\"StopPointRef\": \"70011\"", "\"StopPointRef\": \"70012\"");
+    let app = TestApp::start(pacific(2022, 3, 1, 8, 0), vec![(StatusCode::OK, response)]);
+
+    let stops: Vec<Stop> = app.get_json("/api/trip/104/live").await;
+
+    let platforms: Vec<(i64, Option<&str>)> = stops
+        .iter()
+        .map(|s| (s.station_id, s.platform.as_deref()))
+        .collect();
+    assert_eq!(
+        platforms,
+        vec![(PALO_ALTO, Some("3")), (SAN_FRANCISCO, Some("SB"))]
+    );
+}
+
+#[tokio::test]
+async fn upcoming_stops_say_where_the_trip_is_going() {
+    let app = TestApp::start(pacific(2022, 3, 8, 7, 0), Vec::new());
+
+    let stops: Vec<Stop> = app
+        .get_json(&format!("/api/upcoming-trips?start={PALO_ALTO}"))
+        .await;
+
+    let directions: Vec<(i64, i64, &str)> = stops
+        .iter()
+        .map(|s| (s.trip_id, s.direction_id, s.headsign.as_str()))
+        .collect();
+    assert_eq!(
+        directions,
+        vec![
+            (101, 1, "San Jose Diridon"),
+            (102, 0, "San Francisco"),
+            (103, 1, "San Jose Diridon"),
+            (199, 1, "San Jose Diridon")
+        ]
+    );
+}
+
+#[tokio::test]
+async fn board_keeps_late_trains_and_alerts_about_them() {
+    // Trip 104 was due out of Palo Alto at 8:05 but is six minutes late
+    let response = recording("20220301T160000Z.json")
+        .replace("2022-03-01T08:04:00-08:00", "2022-03-01T08:10:00-08:00")
+        .replace("2022-03-01T08:05:00-08:00", "2022-03-01T08:11:00-08:00");
+    let app = TestApp::start(pacific(2022, 3, 1, 8, 7), vec![(StatusCode::OK, response)]);
+
+    let board: DeparturesBoard = app
+        .get_json(&format!("/api/stations/{PALO_ALTO}/board"))
+        .await;
+
+    assert_eq!(board.station.station_id, PALO_ALTO);
+    let trips: Vec<i64> = board
+        .departures
+        .iter()
+        .map(|d| d.scheduled.trip_id)
+        .collect();
+    assert_eq!(trips, vec![104, 108, 102, 103, 199]);
+    assert_eq!(
+        board.departures[0].departure(),
+        Pacific.ymd(2022, 3, 1).and_hms(8, 11, 0)
+    );
+    assert_eq!(
+        board.alerts,
+        vec!["Train 104 to San Francisco is running 6 min. late"]
+    );
+}
+
+#[tokio::test]
+async fn board_events_push_the_board() {
+    let app = TestApp::start(
+        pacific(2022, 3, 1, 8, 0),
+        vec![(StatusCode::OK, recording("20220301T160000Z.json"))],
+    );
+
+    let mut response = app
+        .get(&format!("/api/stations/{PALO_ALTO}/board/events"))
+        .await;
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+
+    let mut body = String::new();
+    while !body.contains("\n\n") {
+        let chunk = response.chunk().await.unwrap().unwrap();
+        body.push_str(std::str::from_utf8(&chunk).unwrap());
+    }
+
+    let field = |name: &str| {
+        body.lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .unwrap()
+            .trim()
+    };
+    assert_eq!(field("event"), "board");
+
+    let board: DeparturesBoard = serde_json::from_str(field("data")).unwrap();
+    assert_eq!(board.departures[0].scheduled.trip_id, 104);
+    assert!(board.departures[0].live.is_some());
+}
+
+#[tokio::test]
+async fn board_without_live_status_says_so() {
+    let app = TestApp::start(
+        pacific(2022, 3, 8, 7, 0),
+        vec![(StatusCode::INTERNAL_SERVER_ERROR, String::new())],
+    );
+
+    let board: DeparturesBoard = app
+        .get_json(&format!("/api/stations/{PALO_ALTO}/board"))
+        .await;
+
+    assert_eq!(board.departures.len(), 4);
+    assert!(board.departures.iter().all(|d| d.live.is_none()));
+    assert_eq!(board.alerts, vec!["Live train times are unavailable"]);
+
+    let response = app.get("/api/stations/999/board/events").await;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[tokio::test]
+async fn widget_lists_the_next_trains_between_stations() {
+    let app = TestApp::start(
+        pacific(2022, 3, 8, 7, 0),
+        vec![(StatusCode::INTERNAL_SERVER_ERROR, String::new())],
+    );
+
+    let response = app
+        .get(&format!(
+            "/embed/station/{SAN_FRANCISCO}/{SAN_JOSE}?theme=dark&compact=true"
+        ))
+        .await;
+    assert!(response.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+
+    let html = response.text().await.unwrap();
+    assert!(html.contains("San Francisco Caltrain to San Jose Diridon Caltrain"));
+    assert!(html.contains("<body class=\"dark compact\">"));
+    // 101 has just left
+    assert!(!html.contains("<td class=\"train\">101</td>"));
+    assert!(html.contains("<td class=\"train\">103</td><td class=\"time\">5:00 PM</td></tr>"));
+    assert!(html.contains("<td class=\"train\">199</td>"));
+}
+
+#[tokio::test]
+async fn widget_for_a_station_shows_live_times_and_destinations() {
+    let app = TestApp::start(
+        pacific(2022, 3, 1, 8, 0),
+        vec![(StatusCode::OK, recording("20220301T160000Z.json"))],
+    );
+
+    let html = app
+        .get(&format!("/embed/station/{PALO_ALTO}?count=1"))
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    assert!(html.contains(
+        "<td class=\"train\">104</td><td class=\"time live\">8:05 AM</td><td>San Francisco</td><td class=\"minutes\">5 min.</td>"
+    ));
+    assert_eq!(html.matches("<td class=\"train\">").count(), 1);
+}
+
+#[tokio::test]
+async fn oembed_points_at_the_widget() {
+    let app = TestApp::start(pacific(2022, 3, 8, 7, 0), Vec::new());
+
+    let origin = format!("http://{}", app.addr);
+    let url = format!("{origin}/c/station/1/3?count=5&theme=dark");
+    let oembed: serde_json::Value = app
+        .get_json(&format!(
+            "/api/oembed?url={}&maxwidth=300",
+            url.replace('&', "%26").replace('?', "%3F")
+        ))
+        .await;
+
+    assert_eq!(oembed["type"], "rich");
+    assert_eq!(oembed["version"], "1.0");
+    assert_eq!(
+        oembed["title"],
+        "San Francisco Caltrain to San Jose Diridon Caltrain"
+    );
+    assert_eq!(oembed["width"], 300);
+    assert_eq!(oembed["height"], 44 + 34 * 5);
+    assert!(oembed["html"].as_str().unwrap().contains(&format!(
+        "src=\"{origin}/embed/station/1/3?count=5&amp;theme=dark&amp;compact=false\""
+    )));
+
+    let response = app
+        .get(&format!("/api/oembed?url={origin}/c/trip/101"))
+        .await;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[tokio::test]
+async fn oembed_only_embeds_this_app() {
+    let app = TestApp::start(pacific(2022, 3, 8, 7, 0), Vec::new());
+
+    for url in [
+        String::from("https://trains.example.com/c/station/1"),
+        format!("http://{}.evil.example/c/station/1", app.addr.ip()),
+        format!("http://{}:1/c/station/1", app.addr.ip()),
+        format!("javascript://{}/c/station/1", app.addr),
+    ] {
+        let response = app.get(&format!("/api/oembed?url={url}")).await;
+        assert_eq!(
+            response.status(),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "{} was embedded",
+            url
+        );
+    }
+}
+
+#[tokio::test]
+async fn station_page_is_rendered_without_the_frontend() {
+    let app = TestApp::start(
+        pacific(2022, 3, 1, 8, 0),
+        vec![(StatusCode::OK, recording("20220301T160000Z.json"))],
+    );
+
+    let response = app.get(&format!("/c/station/{PALO_ALTO}")).await;
+    assert!(response.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+
+    let html = response.text().await.unwrap();
+    assert!(html.contains("<title>Palo Alto Caltrain</title>"));
+    assert!(html.contains("<meta property=\"og:description\" content=\"Next train 104 to San Francisco at 8:05 AM\" />"));
+    assert!(html.contains("<div id=\"app-container\"><h1>Palo Alto Caltrain</h1>"));
+    assert!(html.contains("<a href=\"/c/trip/104\"><div class=\"TrainID local\">104</div></a>"));
+    assert!(html.contains("<span class=\"TimeDisplay TimeDisplay--realtime\" title=\"Scheduled for 8:05 AM\">8:05 AM</span>"));
+}
+
+#[tokio::test]
+async fn twostop_and_trip_pages_fall_back_to_the_schedule() {
+    let app = TestApp::start(
+        pacific(2022, 3, 8, 7, 0),
+        vec![(StatusCode::INTERNAL_SERVER_ERROR, String::new()); 2],
+    );
+
+    let html = app
+        .get(&format!("/c/station/{SAN_FRANCISCO}/{SAN_JOSE}"))
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("<title>San Francisco Caltrain to San Jose Diridon Caltrain</title>"));
+    assert!(!html.contains("<div class=\"TrainID local\">101</div>"));
+    assert!(html.contains("<div class=\"TrainID local\">103</div>"));
+    assert!(html.contains("Departing <span class=\"TimeDisplay\">5:00 PM</span>"));
+
+    let html = app.get("/c/trip/103").await.text().await.unwrap();
+    assert!(html.contains("<title>Train 103</title>"));
+    assert!(html.contains(&format!(
+        "<a href=\"/c/station/{SAN_FRANCISCO}\">San Francisco Caltrain</a>"
+    )));
+
+    let response = app.get("/c/station/12345").await;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[tokio::test]
+async fn upcoming_trips_as_text_line_up_with_live_times() {
+    // Trip 104 running four minutes late into Palo Alto
+    let response = recording("20220301T160000Z.json")
+        .replace("2022-03-01T08:04:00-08:00", "2022-03-01T08:08:00-08:00")
+        .replace("2022-03-01T08:05:00-08:00", "2022-03-01T08:09:00-08:00");
+    let app = TestApp::start(pacific(2022, 3, 1, 8, 0), vec![(StatusCode::OK, response)]);
+
+    let text = app
+        .get_text(&format!("/api/upcoming-trips?start={PALO_ALTO}"))
+        .await;
+    let lines: Vec<&str> = text.lines().collect();
+
+    assert_eq!(lines[0], "Palo Alto Caltrain at 8:00 AM");
+    assert_eq!(lines[1], "");
+    assert_eq!(
+        lines[2],
+        "Train  To                Departs       Platform  In"
+    );
+    assert_eq!(
+        lines[3],
+        "104    San Francisco     8:09 AM (+4)  NB        9 min."
+    );
+    assert_eq!(
+        lines[4],
+        "108    San Francisco     8:35 AM       NB        35 min."
+    );
+
+    let text = app.get_text("/api/trip?id=104").await;
+    let lines: Vec<&str> = text.lines().collect();
+
+    assert_eq!(lines[0], "Train 104, 4 min. late");
+    assert_eq!(
+        lines[2],
+        "Station                    Arrives       Departs       Notes"
+    );
+    assert_eq!(
+        lines[4],
+        "Palo Alto Caltrain         8:08 AM (+4)  8:09 AM (+4)  Next stop"
+    );
+
+    // JSON is still the default, and can be asked for
+    let stops: Vec<Stop> = app
+        .get_json(&format!(
+            "/api/upcoming-trips?start={PALO_ALTO}&format=json"
+        ))
+        .await;
+    assert_eq!(stops[0].trip_id, 104);
+}
+
+#[tokio::test]
+async fn twostops_as_text_fall_back_to_the_schedule() {
+    let app = TestApp::start(
+        pacific(2022, 3, 8, 7, 0),
+        vec![(StatusCode::INTERNAL_SERVER_ERROR, String::new())],
+    );
+
+    let response = app
+        .get(&format!(
+            "/api/upcoming-trips?start={SAN_FRANCISCO}&end={SAN_JOSE}&format=txt"
+        ))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    assert_eq!(
+        response.text().await.unwrap(),
+        "San Francisco Caltrain to San Jose Diridon Caltrain at 7:00 AM
+
+Train  Departs   Arrives  Duration  In
+103    5:00 PM   6:30 PM  90 min.   600 min.
+199    11:50 PM  1:20 AM  90 min.   1010 min.
+
+Live train times are unavailable
+"
+    );
+}
@@ -0,0 +1,71 @@
+//! The schedule bundle the frontend answers from when it's offline.
+
+use chrono::prelude::*;
+use train_schedules_common::{ScheduleBundle, Stop};
+
+use common::{pacific, TestApp};
+
+mod common;
+
+const SAN_FRANCISCO: i64 = 1;
+
+/// `time` as a browser in Tokyo would see it.
+fn in_tokyo(time: DateTime<Utc>) -> DateTime<FixedOffset> {
+    time.with_timezone(&FixedOffset::east(9 * 3600))
+}
+
+async fn bundle(app: &TestApp) -> ScheduleBundle {
+    app.get_json("/api/schedule-bundle?days=7").await
+}
+
+#[tokio::test]
+async fn offline_times_match_the_backend_when_clocks_go_forward() {
+    // Loaded the day before the clocks change
+    let app = TestApp::start(pacific(2022, 3, 12, 12, 0), Vec::new());
+    let bundle = bundle(&app).await;
+
+    app.clock.set(pacific(2022, 3, 13, 8, 0));
+    let now = in_tokyo(pacific(2022, 3, 13, 8, 0));
+
+    assert_eq!(
+        bundle.today(now),
+        NaiveDate::from_ymd(2022, 3, 13),
+        "the agency's day, not Tokyo's"
+    );
+
+    let offline = bundle.upcoming(SAN_FRANCISCO, now);
+    let online: Vec<Stop> = app
+        .get_json(&format!("/api/upcoming-trips?start={SAN_FRANCISCO}"))
+        .await;
+
+    assert_eq!(offline[0].trip_id, 201);
+    assert_eq!(
+        offline[0].departure.to_rfc3339(),
+        "2022-03-13T09:00:00-07:00"
+    );
+    assert_eq!(offline[0].departure, online[0].departure);
+}
+
+#[tokio::test]
+async fn offline_times_match_the_backend_when_clocks_go_back() {
+    let app = TestApp::start(pacific(2022, 11, 5, 12, 0), Vec::new());
+    let bundle = bundle(&app).await;
+
+    let stops = bundle.stops_on(NaiveDate::from_ymd(2022, 11, 6));
+    let departure = stops
+        .iter()
+        .find(|s| s.trip_id == 201 && s.station_id == SAN_FRANCISCO)
+        .unwrap()
+        .departure;
+
+    assert_eq!(departure.to_rfc3339(), "2022-11-06T09:00:00-08:00");
+}
+
+#[tokio::test]
+async fn days_before_the_bundle_have_no_stops() {
+    let app = TestApp::start(pacific(2022, 3, 8, 12, 0), Vec::new());
+    let bundle = bundle(&app).await;
+
+    assert!(!bundle.stops_on(NaiveDate::from_ymd(2022, 3, 8)).is_empty());
+    assert!(bundle.stops_on(NaiveDate::from_ymd(2022, 3, 1)).is_empty());
+}
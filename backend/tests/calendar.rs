@@ -0,0 +1,181 @@
+//! The iCalendar exports, and the calendar writer behind them.
+
+use axum::http::StatusCode;
+use chrono::prelude::*;
+use chrono_tz::US::Pacific;
+use train_backend::ical::{Calendar, Event, Recurrence};
+
+use common::{pacific, TestApp};
+
+mod common;
+
+const SAN_FRANCISCO: i64 = 1;
+const SAN_JOSE: i64 = 3;
+
+fn event(description: &str, recurrence: Option<Recurrence>) -> Event {
+    Event {
+        uid: String::from("trip-101@train-schedules"),
+        summary: String::from("Train 101: San Francisco to San Jose"),
+        description: description.to_owned(),
+        location: String::from("San Francisco Caltrain"),
+        geo: None,
+        start: NaiveDate::from_ymd(2022, 3, 8).and_hms(7, 0, 0),
+        end: NaiveDate::from_ymd(2022, 3, 8).and_hms(8, 30, 0),
+        recurrence,
+    }
+}
+
+fn render(events: Vec<Event>) -> String {
+    let mut calendar = Calendar::new(Pacific);
+    for event in events {
+        calendar.push(event);
+    }
+
+    calendar.render(pacific(2022, 3, 8, 6, 0))
+}
+
+/// The calendar with folded lines joined back up.
+fn unfold(calendar: &str) -> Vec<String> {
+    calendar
+        .replace("\r\n ", "")
+        .split("\r\n")
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn weekly_recurrence_ends_in_utc() {
+    let calendar = render(vec![event(
+        "Weekdays",
+        Some(Recurrence {
+            weekdays: vec![Weekday::Mon, Weekday::Wed, Weekday::Fri],
+            until: NaiveDate::from_ymd(2022, 6, 30),
+            except: Vec::new(),
+        }),
+    )]);
+    let lines = unfold(&calendar);
+
+    assert!(lines.contains(&String::from("DTSTART;TZID=US/Pacific:20220308T070000")));
+    // The end of June 30th in California, in UTC
+    assert!(lines.contains(&String::from(
+        "RRULE:FREQ=WEEKLY;BYDAY=MO,WE,FR;UNTIL=20220701T065959Z"
+    )));
+}
+
+#[test]
+fn time_zone_has_each_clock_change() {
+    let calendar = render(vec![event("One off", None)]);
+    let lines = unfold(&calendar);
+
+    let start = lines.iter().position(|l| l == "BEGIN:VTIMEZONE").unwrap();
+    let end = lines.iter().position(|l| l == "END:VTIMEZONE").unwrap();
+
+    assert_eq!(
+        lines[start..=end],
+        [
+            "BEGIN:VTIMEZONE",
+            "TZID:US/Pacific",
+            "BEGIN:DAYLIGHT",
+            "DTSTART:20220313T020000",
+            "TZOFFSETFROM:-0800",
+            "TZOFFSETTO:-0700",
+            "TZNAME:PDT",
+            "END:DAYLIGHT",
+            "BEGIN:STANDARD",
+            "DTSTART:20221106T020000",
+            "TZOFFSETFROM:-0700",
+            "TZOFFSETTO:-0800",
+            "TZNAME:PST",
+            "END:STANDARD",
+            "END:VTIMEZONE",
+        ]
+    );
+}
+
+#[test]
+fn time_zone_covers_every_year_of_the_recurrence() {
+    let calendar = render(vec![event(
+        "Weekdays",
+        Some(Recurrence {
+            weekdays: vec![Weekday::Mon],
+            until: NaiveDate::from_ymd(2099, 12, 31),
+            except: Vec::new(),
+        }),
+    )]);
+    let lines = unfold(&calendar);
+
+    // Two clock changes a year, the event's own DTSTART carries a TZID
+    let transitions: Vec<&String> = lines.iter().filter(|l| l.starts_with("DTSTART:")).collect();
+    assert_eq!(transitions.len(), 2 * (2099 - 2022 + 1));
+    assert_eq!(
+        transitions.last().unwrap().as_str(),
+        "DTSTART:20991101T020000"
+    );
+}
+
+#[test]
+fn long_lines_are_folded() {
+    let description =
+        "Departs San Francisco Caltrain at 7:00 AM, arrives San José Diridon Caltrain at 8:30 AM";
+    let calendar = render(vec![event(description, None)]);
+
+    for line in calendar.split("\r\n") {
+        assert!(line.len() <= 75, "{:?} is longer than 75 octets", line);
+    }
+
+    assert!(unfold(&calendar).contains(&format!("DESCRIPTION:{}", description.replace(',', "\\,"))));
+}
+
+#[tokio::test]
+async fn trip_calendar_needs_the_ics_extension() {
+    let app = TestApp::start(pacific(2022, 3, 8, 6, 0), Vec::new());
+
+    let response = app.get("/api/trip/101.ics").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/calendar"));
+
+    let response = app.get("/api/trip/101").await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn trip_calendar_rejects_stations_out_of_order() {
+    let app = TestApp::start(pacific(2022, 3, 8, 6, 0), Vec::new());
+
+    let response = app
+        .get(&format!(
+            "/api/trip/101.ics?start={SAN_FRANCISCO}&end={SAN_JOSE}"
+        ))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .get(&format!(
+            "/api/trip/101.ics?start={SAN_JOSE}&end={SAN_FRANCISCO}"
+        ))
+        .await;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[tokio::test]
+async fn recurring_trip_calendar_skips_holidays() {
+    let app = TestApp::start(pacific(2022, 3, 8, 6, 0), Vec::new());
+
+    let calendar = app
+        .get("/api/trip/101.ics?recurring=true")
+        .await
+        .text()
+        .await
+        .unwrap();
+    let lines = unfold(&calendar);
+
+    // Weekday service doesn't run on Memorial Day
+    let exdates: Vec<&String> = lines.iter().filter(|l| l.starts_with("EXDATE")).collect();
+    assert_eq!(
+        exdates,
+        vec!["EXDATE;TZID=America/Los_Angeles:20220530T070000"]
+    );
+}
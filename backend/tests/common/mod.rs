@@ -0,0 +1,380 @@
+//! Helpers shared by the integration tests.
+
+#![allow(dead_code)]
+
+use std::{
+    collections::HashMap,
+    net::{SocketAddr, TcpListener},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    body::Bytes,
+    extract::Extension,
+    http::{HeaderMap, StatusCode},
+    routing::{get, post},
+    AddExtensionLayer, Router,
+};
+use chrono::prelude::*;
+use chrono_tz::US::Pacific;
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use tokio::sync::RwLock;
+use train_backend::{
+    clock::{ManualClock, SystemClock},
+    db::{self, Service},
+    metrics::Metrics,
+    push::VapidKey,
+    realtime::{Api511Provider, NoopProvider},
+    reminders::Reminders,
+    webhooks::{RetryPolicy, Webhooks},
+    State,
+};
+use train_schedules_common::{Boarding, Stop};
+use ttl_cache::TtlCache;
+
+#[derive(Clone, Debug)]
+pub struct Received {
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+pub type Inbox = Arc<Mutex<Vec<Received>>>;
+
+/// Start an HTTP server that records every POST it receives. It answers with
+/// `statuses` in order, repeating the last one once they run out.
+pub fn mock_receiver(statuses: Vec<StatusCode>) -> (SocketAddr, Inbox) {
+    let inbox = Inbox::default();
+    let statuses = Arc::new(statuses);
+
+    let app = Router::new()
+        .route(
+            "/*path",
+            post(
+                move |headers: HeaderMap, body: Bytes, Extension(inbox): Extension<Inbox>| async move {
+                    let mut inbox = inbox.lock().unwrap();
+                    inbox.push(Received {
+                        headers,
+                        body: body.to_vec(),
+                    });
+
+                    statuses[(inbox.len() - 1).min(statuses.len() - 1)]
+                },
+            ),
+        )
+        .layer(AddExtensionLayer::new(inbox.clone()));
+
+    (serve(app), inbox)
+}
+
+/// How many requests the mock 511.org API has had.
+pub type UpstreamCount = Arc<Mutex<usize>>;
+
+/// Start a stand-in for the 511.org StopMonitoring API. It answers with
+/// `responses` in order, repeating the last one once they run out, and counts
+/// the requests it gets.
+pub fn mock_511(responses: Vec<(StatusCode, String)>) -> (SocketAddr, UpstreamCount) {
+    let count = UpstreamCount::default();
+    let requests = count.clone();
+    let responses = Arc::new(responses);
+
+    let app = Router::new().route(
+        "/StopMonitoring",
+        get(move || async move {
+            let mut requests = requests.lock().unwrap();
+            *requests += 1;
+
+            responses[(*requests - 1).min(responses.len() - 1)].clone()
+        }),
+    );
+
+    (serve(app), count)
+}
+
+fn serve(app: Router) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service())
+            .await
+            .unwrap();
+    });
+
+    addr
+}
+
+/// A time in the fixture feed's time zone.
+pub fn pacific(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+    Pacific
+        .ymd(year, month, day)
+        .and_hms(hour, minute, 0)
+        .with_timezone(&Utc)
+}
+
+/// A 511.org response from `tests/fixtures/511`.
+pub fn recording(name: &str) -> String {
+    std::fs::read_to_string(format!(
+        "{}/tests/fixtures/511/{name}",
+        env!("CARGO_MANIFEST_DIR")
+    ))
+    .unwrap()
+}
+
+/// A GTFS feed from `tests/fixtures`, loaded into an in-memory database the
+/// way `new-db.sh` does it: a table per file with every column as text, and
+/// a `station_id` shared by each station's platforms. Stations are numbered
+/// from 1 in the order they appear in stops.txt.
+pub fn fixture_db(feed: &str) -> sqlite::Connection {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(feed);
+    let connection = sqlite::Connection::open(":memory:").unwrap();
+
+    for entry in std::fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        let table = path.file_stem().unwrap().to_str().unwrap().to_owned();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let mut lines = contents.lines();
+
+        // The fixtures don't quote anything, so there's no need for a real
+        // CSV parser
+        let columns: Vec<&str> = lines.next().unwrap().split(',').collect();
+        connection
+            .execute(format!("create table {table} ({})", columns.join(", ")))
+            .unwrap();
+
+        for line in lines.filter(|line| !line.is_empty()) {
+            let values: Vec<String> = line
+                .split(',')
+                .map(|value| format!("'{}'", value.replace('\'', "''")))
+                .collect();
+
+            connection
+                .execute(format!(
+                    "insert into {table} values ({})",
+                    values.join(", ")
+                ))
+                .unwrap();
+        }
+    }
+
+    connection
+        .execute("alter table stops add column station_id int")
+        .unwrap();
+
+    let mut names = Vec::new();
+    {
+        let mut stmt = connection.prepare("select stop_name from stops").unwrap();
+        while let sqlite::State::Row = stmt.next().unwrap() {
+            let name: String = stmt.read(0).unwrap();
+
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+
+    for (index, name) in names.iter().enumerate() {
+        let mut stmt = connection
+            .prepare("update stops set station_id = ? where stop_name = ?")
+            .unwrap();
+        stmt.bind(1, index as i64 + 1).unwrap();
+        stmt.bind(2, name.as_str()).unwrap();
+        stmt.next().unwrap();
+    }
+
+    connection
+}
+
+/// Needed to manage webhooks on a [`TestApp`]
+pub const ADMIN_TOKEN: &str = "test-admin-token";
+
+/// The whole app running in-process against the fixture feed, with a clock
+/// the test controls and live status from a [`mock_511`] server.
+pub struct TestApp {
+    pub addr: SocketAddr,
+    pub state: Arc<State>,
+    pub clock: Arc<ManualClock>,
+    /// Requests the mock 511.org API has received
+    upstream_requests: UpstreamCount,
+    client: Client,
+}
+
+impl TestApp {
+    pub fn start(now: DateTime<Utc>, upstream: Vec<(StatusCode, String)>) -> Self {
+        Self::new(now, upstream, false)
+    }
+
+    /// Start the app accepting `?now=` overrides.
+    pub fn with_time_override(now: DateTime<Utc>) -> Self {
+        Self::new(now, Vec::new(), true)
+    }
+
+    fn new(
+        now: DateTime<Utc>,
+        upstream: Vec<(StatusCode, String)>,
+        allow_time_override: bool,
+    ) -> Self {
+        let connection = fixture_db("gtfs");
+        let clock = Arc::new(ManualClock::new(now));
+        let client = Client::new();
+        let metrics = Arc::new(Metrics::new());
+        let (upstream_addr, upstream_requests) = mock_511(upstream);
+
+        let timezone = db::agency_timezone(&connection).unwrap();
+        let service_day = db::service_day(now, timezone);
+
+        let state = Arc::new(State {
+            stations: db::all_stations(&connection).unwrap(),
+            platforms: db::platforms(&connection).unwrap(),
+            stops: db::all_stops(&connection, service_day).unwrap(),
+            client: client.clone(),
+            realtime: Box::new(
+                Api511Provider::new(client.clone(), String::from("test"), metrics.clone())
+                    .with_url(format!("http://{upstream_addr}/StopMonitoring")),
+            ),
+            live_status_cache: Arc::new(RwLock::new(TtlCache::new(50))),
+            unmatched_visits: RwLock::new(Vec::new()),
+            live_cache_ttl: Duration::from_secs(120),
+            rate_limit_backoff: Duration::from_secs(60),
+            services: db::services(&connection).unwrap(),
+            trips: db::trips(&connection).unwrap(),
+            service_day: service_day.naive_local(),
+            timezone,
+            reminders: Reminders::in_memory(),
+            vapid: VapidKey::generate("mailto:test@example.com"),
+            webhooks: Webhooks::in_memory(RetryPolicy::default())
+                .with_admin_token(Some(String::from(ADMIN_TOKEN))),
+            metrics,
+            clock: clock.clone(),
+            allow_time_override,
+            allow_private_targets: false,
+            public_url: None,
+        });
+
+        let static_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../static");
+        let addr = serve(train_backend::app(state.clone(), &static_dir));
+
+        Self {
+            addr,
+            state,
+            clock,
+            upstream_requests,
+            client,
+        }
+    }
+
+    pub async fn get(&self, path: &str) -> reqwest::Response {
+        self.client
+            .get(format!("http://{}{path}", self.addr))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    /// A request to `path` on the app, to send with whatever else it needs.
+    pub fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        self.client
+            .request(method, format!("http://{}{path}", self.addr))
+    }
+
+    pub async fn get_text(&self, path: &str) -> String {
+        let response = self
+            .client
+            .get(format!("http://{}{path}", self.addr))
+            .header("Accept", "text/plain")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "GET {path}");
+        assert!(response.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain"));
+
+        response.text().await.unwrap()
+    }
+
+    pub async fn get_json<T: DeserializeOwned>(&self, path: &str) -> T {
+        let response = self.get(path).await;
+        assert_eq!(response.status(), StatusCode::OK, "GET {path}");
+
+        serde_json::from_slice(&response.bytes().await.unwrap()).unwrap()
+    }
+
+    pub fn upstream_requests(&self) -> usize {
+        *self.upstream_requests.lock().unwrap()
+    }
+}
+
+pub fn state(stops: Vec<Stop>) -> State {
+    State {
+        stations: Vec::new(),
+        platforms: HashMap::new(),
+        stops,
+        client: Client::new(),
+        realtime: Box::new(NoopProvider),
+        live_status_cache: Arc::new(RwLock::new(TtlCache::new(50))),
+        unmatched_visits: RwLock::new(Vec::new()),
+        live_cache_ttl: Duration::from_secs(120),
+        rate_limit_backoff: Duration::from_secs(60),
+        services: vec![daily_service()],
+        trips: Vec::new(),
+        service_day: db::service_day(Utc::now(), Pacific).naive_local(),
+        timezone: Pacific,
+        reminders: Reminders::in_memory(),
+        vapid: VapidKey::generate("mailto:test@example.com"),
+        webhooks: Webhooks::in_memory(RetryPolicy::default()).with_private_targets(true),
+        metrics: Arc::new(Metrics::new()),
+        clock: Arc::new(SystemClock),
+        allow_time_override: false,
+        allow_private_targets: true,
+        public_url: None,
+    }
+}
+
+/// A service running every day, which [`stop`]s belong to.
+fn daily_service() -> Service {
+    let today = db::service_day(Utc::now(), Pacific).naive_local();
+
+    Service {
+        start_date: today.pred(),
+        end_date: today.succ(),
+        id: String::from("daily"),
+        weekdays: vec![
+            Weekday::Mon,
+            Weekday::Tue,
+            Weekday::Wed,
+            Weekday::Thu,
+            Weekday::Fri,
+            Weekday::Sat,
+            Weekday::Sun,
+        ],
+        added_dates: Vec::new(),
+        removed_dates: Vec::new(),
+    }
+}
+
+pub fn stop(trip_id: i64, station_id: i64, departure: DateTime<Utc>) -> Stop {
+    let departure = departure.with_timezone(&FixedOffset::east(0));
+
+    Stop {
+        station_id,
+        trip_id,
+        station_name: format!("Station {station_id}"),
+        arrival: departure,
+        departure,
+        service_id: String::from("daily"),
+        stop_sequence: 1,
+        pickup_type: Boarding::Regular,
+        drop_off_type: Boarding::Regular,
+        platform: None,
+        direction_id: 0,
+        headsign: String::from("San Francisco"),
+    }
+}
@@ -0,0 +1,171 @@
+//! Layering settings from defaults, a config file, env vars and flags.
+
+use std::{collections::HashMap, fs, net::SocketAddr, path::PathBuf, time::Duration};
+
+use tempfile::TempDir;
+use train_backend::config::{Layers, RealtimeConfig};
+
+/// A config file with `contents`, in a directory that goes away with it.
+fn config_file(contents: &str) -> (TempDir, PathBuf) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("train-backend.toml");
+    fs::write(&path, contents).unwrap();
+
+    (dir, path)
+}
+
+fn load(file: &str, env: &[(&str, &str)], flags: &[&str]) -> eyre::Result<Layers> {
+    let (_dir, path) = config_file(file);
+
+    let mut args = vec!["train-backend", "--config", path.to_str().unwrap()];
+    args.extend(flags);
+    let args = args.into_iter().map(String::from).collect::<Vec<_>>();
+    let env = env
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect::<HashMap<_, _>>();
+
+    Layers::load(args, &env)
+}
+
+#[test]
+fn defaults_apply_without_overrides() {
+    let config = load("", &[], &[]).unwrap().config().unwrap();
+
+    assert_eq!(
+        config.bind_address,
+        "0.0.0.0:8088".parse::<SocketAddr>().unwrap()
+    );
+    assert_eq!(config.db_path, PathBuf::from("/var/schedules.db"));
+    assert_eq!(config.live_cache_ttl, Duration::from_secs(120));
+    assert_eq!(config.realtime, RealtimeConfig::None);
+    assert_eq!(config.webhooks_admin_token, None);
+}
+
+#[test]
+fn each_layer_overrides_the_last() {
+    let file = r#"
+        db_path = "/from/file.db"
+        static_dir = "/from/file/"
+
+        [live_cache]
+        ttl_seconds = 30
+        rate_limit_backoff_seconds = 10
+    "#;
+    let env = [
+        ("STATIC_FILE_PATH", "/from/env/"),
+        ("LIVE_CACHE_TTL_SECONDS", "45"),
+    ];
+    let flags = ["--live-cache-ttl-seconds", "90"];
+
+    let layers = load(file, &env, &flags).unwrap();
+    let config = layers.config().unwrap();
+
+    // Only the file sets it
+    assert_eq!(config.db_path, PathBuf::from("/from/file.db"));
+    assert_eq!(config.rate_limit_backoff, Duration::from_secs(10));
+    // The file and env set it
+    assert_eq!(config.static_dir, PathBuf::from("/from/env/"));
+    // All three set it
+    assert_eq!(config.live_cache_ttl, Duration::from_secs(90));
+
+    let described = layers.describe();
+    for (key, source) in [
+        ("bind_address", "(default)"),
+        ("db_path", "(config file"),
+        ("static_dir", "(env var STATIC_FILE_PATH)"),
+        ("live_cache.ttl_seconds", "(flag --live-cache-ttl-seconds)"),
+    ] {
+        let line = described
+            .lines()
+            .find(|l| l.starts_with(&format!("{key} ")))
+            .unwrap();
+        assert!(line.contains(source), "{:?} doesn't say {}", line, source);
+    }
+}
+
+#[test]
+fn secrets_are_hidden_when_described() {
+    let layers = load(
+        "",
+        &[("API_KEY", "abc123"), ("WEBHOOKS_ADMIN_TOKEN", "hunter2")],
+        &[],
+    )
+    .unwrap();
+
+    let described = layers.describe();
+    assert!(!described.contains("abc123"), "{}", described);
+    assert!(!described.contains("hunter2"), "{}", described);
+
+    let config = layers.config().unwrap();
+    assert_eq!(config.webhooks_admin_token.as_deref(), Some("hunter2"));
+    assert!(
+        matches!(config.realtime, RealtimeConfig::Api511 { ref api_key, .. } if api_key == "abc123")
+    );
+}
+
+#[test]
+fn unknown_keys_in_the_file_are_rejected() {
+    let error = load("[live_cache]\nttl = 30\n", &[], &[]).err().unwrap();
+
+    assert!(
+        format!("{error:#}").contains("live_cache.ttl"),
+        "{:#}",
+        error
+    );
+}
+
+#[test]
+fn invalid_values_are_rejected() {
+    for (env, expected) in [
+        (
+            ("LIVE_CACHE_TTL_SECONDS", "0"),
+            "live_cache.ttl_seconds must be greater than 0",
+        ),
+        (
+            ("LIVE_CACHE_RATE_LIMIT_BACKOFF_SECONDS", "lots"),
+            "invalid live_cache.rate_limit_backoff_seconds",
+        ),
+        (
+            ("OTEL_TRACES_SAMPLER_ARG", "1.5"),
+            "telemetry.sample_ratio must be between 0 and 1",
+        ),
+        (
+            ("REALTIME_PROVIDER", "gtfs-rt"),
+            "unknown realtime provider",
+        ),
+        (
+            ("REALTIME_PROVIDER", "replay"),
+            "realtime.replay_dir is required",
+        ),
+    ] {
+        let error = load("", &[env], &[]).unwrap().config().err().unwrap();
+
+        assert!(
+            format!("{error}").contains(expected),
+            "{:?} gave {}, expected {}",
+            env,
+            error,
+            expected
+        );
+    }
+}
+
+#[test]
+fn validation_errors_name_the_layer_responsible() {
+    let error = load(
+        "[live_cache]\nttl_seconds = 5\n",
+        &[],
+        &["--live-cache-ttl-seconds", "0"],
+    )
+    .unwrap()
+    .config()
+    .err()
+    .unwrap();
+
+    assert!(
+        format!("{error}").contains("set by flag --live-cache-ttl-seconds"),
+        "{}",
+        error
+    );
+}
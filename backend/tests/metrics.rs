@@ -0,0 +1,157 @@
+//! Prometheus metrics, and the route labels requests are counted under.
+
+use chrono::prelude::*;
+use chrono_tz::US::Pacific;
+use train_backend::{
+    db::Service,
+    metrics::{route_label, Metrics},
+    realtime::Unmatched,
+};
+
+use common::{pacific, stop, TestApp};
+
+mod common;
+
+#[test]
+fn ids_are_collapsed_out_of_api_routes() {
+    for (path, route) in [
+        ("/api/stations", "/api/stations"),
+        ("/api/trip/101", "/api/trip/:id"),
+        ("/api/trip/101.ics", "/api/trip/:id"),
+        ("/api/trip/foo.ics", "/api/trip/:id"),
+        ("/api/trip/101/live", "/api/trip/:id/live"),
+        ("/api/stations/12/board", "/api/stations/:id/board"),
+        ("/api/reminders/d5c8e2a1", "/api/reminders/:id"),
+        ("/api/webhooks", "/api/webhooks"),
+        (
+            "/api/webhooks/wh-1/deliveries",
+            "/api/webhooks/:id/deliveries",
+        ),
+    ] {
+        assert_eq!(route_label(path), route, "for {}", path);
+    }
+}
+
+#[test]
+fn other_paths_share_a_few_routes() {
+    for (path, route) in [
+        ("/", "/"),
+        ("/metrics", "/metrics"),
+        ("/sw.js", "/sw.js"),
+        ("/c/", "/c/"),
+        ("/c/station/1", "/c/station/:start"),
+        ("/c/station/1/3", "/c/station/:start/:end"),
+        ("/c/trip/101", "/c/trip/:id"),
+        ("/embed/station/1", "/embed/station/:start"),
+        ("/embed/station/1/3", "/embed/station/:start/:end"),
+        ("/main.css", "static"),
+        ("/icons/train-192.png", "static"),
+    ] {
+        assert_eq!(route_label(path), route, "for {}", path);
+    }
+}
+
+#[test]
+fn unknown_api_paths_share_one_route() {
+    for path in [
+        "/api",
+        "/api/",
+        "/api/nope",
+        "/api/trip/101/live/extra",
+        "/api/stations//board",
+        "/api/webhooks/wh-1/deliveries/2",
+    ] {
+        assert_eq!(route_label(path), "other", "for {}", path);
+    }
+}
+
+#[test]
+fn renders_recorded_metrics() {
+    let metrics = Metrics::new();
+
+    metrics.observe_request("GET", "/api/trip/:id", 200, 0.01);
+    metrics.observe_request("GET", "/api/trip/:id", 200, 0.02);
+    metrics.observe_upstream(Some(429), 0.1);
+    metrics.observe_upstream(None, 0.1);
+    metrics.unmatched_visit(Unmatched::UnknownTrip);
+    metrics.live_cache_hit();
+    metrics.live_cache_miss();
+    metrics.live_cache_refreshed(pacific(2022, 3, 8, 6, 0));
+
+    let rendered = metrics.render(pacific(2022, 3, 8, 6, 2)).unwrap();
+
+    for line in [
+        "http_requests_total{method=\"GET\",route=\"/api/trip/:id\",status=\"200\"} 2",
+        "http_request_duration_seconds_count{method=\"GET\",route=\"/api/trip/:id\"} 2",
+        "upstream_511_requests_total{status=\"429\"} 1",
+        "upstream_511_requests_total{status=\"error\"} 1",
+        "realtime_unmatched_visits_total{reason=\"unknown_trip\"} 1",
+        "live_cache_hits_total 1",
+        "live_cache_misses_total 1",
+        "live_cache_age_seconds 120",
+    ] {
+        assert!(
+            rendered.lines().any(|l| l == line),
+            "no {} in\n{}",
+            line,
+            rendered
+        );
+    }
+}
+
+#[test]
+fn feed_expires_at_midnight_after_the_last_service_day() {
+    let metrics = Metrics::new();
+    let service = |id: &str, end_date| Service {
+        start_date: NaiveDate::from_ymd(2022, 1, 1),
+        end_date,
+        id: id.to_owned(),
+        weekdays: vec![Weekday::Mon],
+        added_dates: Vec::new(),
+        removed_dates: Vec::new(),
+    };
+
+    metrics.set_schedule(
+        &[stop(101, 1, pacific(2022, 3, 8, 6, 0))],
+        &[],
+        &[
+            service("weekday", NaiveDate::from_ymd(2022, 6, 30)),
+            service("weekend", NaiveDate::from_ymd(2022, 9, 30)),
+        ],
+        Pacific,
+    );
+
+    let rendered = metrics.render(pacific(2022, 3, 8, 6, 0)).unwrap();
+    let expiry = pacific(2022, 10, 1, 0, 0).timestamp();
+
+    for line in [
+        String::from("schedule_stops 1"),
+        String::from("schedule_trips 0"),
+        String::from("schedule_services 2"),
+        format!("schedule_feed_expiry_timestamp_seconds {expiry}"),
+    ] {
+        assert!(
+            rendered.lines().any(|l| l == line),
+            "no {} in\n{}",
+            line,
+            rendered
+        );
+    }
+}
+
+#[tokio::test]
+async fn requests_are_counted_by_route() {
+    let app = TestApp::start(pacific(2022, 3, 8, 6, 0), Vec::new());
+
+    app.get("/api/trip/101.ics").await;
+    app.get("/api/trip/102.ics").await;
+
+    let metrics = app.get("/metrics").await.text().await.unwrap();
+    assert!(
+        metrics.contains(
+            "http_requests_total{method=\"GET\",route=\"/api/trip/:id\",status=\"200\"} 2"
+        ),
+        "{}",
+        metrics
+    );
+}
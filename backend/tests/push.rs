@@ -0,0 +1,358 @@
+use std::convert::{TryFrom, TryInto};
+
+use aes_gcm::{
+    aead::{Aead, NewAead},
+    Aes128Gcm, Key, Nonce,
+};
+use axum::http::{header, StatusCode};
+use chrono::{prelude::*, Duration};
+use hkdf::Hkdf;
+use p256::{
+    ecdsa::{signature::Verifier, Signature, VerifyingKey},
+    elliptic_curve::{ecdh::diffie_hellman, sec1::ToEncodedPoint},
+    PublicKey, SecretKey,
+};
+use rand::{rngs::OsRng, RngCore};
+use reqwest::Method;
+use sha2::Sha256;
+use train_backend::{
+    push::{self, decode_base64, encode_base64, Delivery, VapidKey},
+    reminders::{self, Reminders, MAX_PER_SUBSCRIPTION},
+};
+use train_schedules_common::{PushSubscription, PushSubscriptionKeys, ReminderRequest};
+
+use common::{mock_receiver, pacific, state, stop, TestApp};
+
+mod common;
+
+/// A browser's half of a push subscription, able to decrypt what is sent to
+/// it.
+struct Subscriber {
+    secret: SecretKey,
+    auth: [u8; 16],
+}
+
+impl Subscriber {
+    fn new() -> Self {
+        let mut auth = [0u8; 16];
+        OsRng.fill_bytes(&mut auth);
+
+        Self {
+            secret: SecretKey::random(&mut OsRng),
+            auth,
+        }
+    }
+
+    fn subscription(&self, endpoint: &str) -> PushSubscription {
+        PushSubscription {
+            endpoint: endpoint.to_owned(),
+            keys: PushSubscriptionKeys {
+                p256dh: encode_base64(self.public_bytes().as_ref()),
+                auth: encode_base64(&self.auth),
+            },
+        }
+    }
+
+    fn public_bytes(&self) -> Vec<u8> {
+        self.secret
+            .public_key()
+            .to_encoded_point(false)
+            .as_bytes()
+            .to_vec()
+    }
+
+    fn decrypt(&self, body: &[u8]) -> Vec<u8> {
+        let salt = &body[..16];
+        let record_size = u32::from_be_bytes(body[16..20].try_into().unwrap());
+        let key_id_len = body[20] as usize;
+        let as_public_bytes = &body[21..21 + key_id_len];
+        let ciphertext = &body[21 + key_id_len..];
+
+        assert_eq!(record_size, 4096);
+
+        let as_public = PublicKey::from_sec1_bytes(as_public_bytes).unwrap();
+        let shared = diffie_hellman(self.secret.to_nonzero_scalar(), as_public.as_affine());
+
+        let mut key_info = b"WebPush: info\0".to_vec();
+        key_info.extend_from_slice(&self.public_bytes());
+        key_info.extend_from_slice(as_public_bytes);
+
+        let mut ikm = [0u8; 32];
+        Hkdf::<Sha256>::new(Some(&self.auth[..]), shared.as_bytes())
+            .expand(&key_info, &mut ikm)
+            .unwrap();
+
+        let prk = Hkdf::<Sha256>::new(Some(salt), &ikm);
+        let mut cek = [0u8; 16];
+        let mut nonce = [0u8; 12];
+        prk.expand(b"Content-Encoding: aes128gcm\0", &mut cek)
+            .unwrap();
+        prk.expand(b"Content-Encoding: nonce\0", &mut nonce)
+            .unwrap();
+
+        let mut plaintext = Aes128Gcm::new(Key::from_slice(&cek))
+            .decrypt(Nonce::from_slice(&nonce), ciphertext)
+            .unwrap();
+
+        assert_eq!(plaintext.pop(), Some(2), "missing last record delimiter");
+
+        plaintext
+    }
+}
+
+#[test]
+fn encrypted_payload_decrypts_with_subscriber_key() {
+    let subscriber = Subscriber::new();
+    let subscription = subscriber.subscription("https://push.example.com/push/1");
+
+    let body = push::encrypt(&subscription, b"hello train").unwrap();
+
+    assert_eq!(subscriber.decrypt(&body), b"hello train");
+}
+
+#[tokio::test]
+async fn send_delivers_signed_encrypted_message() {
+    let (addr, inbox) = mock_receiver(vec![StatusCode::CREATED]);
+    let subscriber = Subscriber::new();
+    let subscription = subscriber.subscription(&format!("http://{addr}/push/1"));
+    let key = VapidKey::generate("mailto:test@example.com");
+
+    let delivery = push::send(&key, &subscription, b"{\"a\":1}", 600, true)
+        .await
+        .unwrap();
+
+    assert_eq!(delivery, Delivery::Accepted);
+
+    let received = inbox.lock().unwrap().pop().unwrap();
+    let header = |name: &str| {
+        received
+            .headers
+            .get(name)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_owned()
+    };
+
+    assert_eq!(header("ttl"), "600");
+    assert_eq!(header("content-encoding"), "aes128gcm");
+    assert_eq!(subscriber.decrypt(&received.body), b"{\"a\":1}");
+
+    let authorization = header("authorization");
+    let (token, public_key) = authorization
+        .strip_prefix("vapid t=")
+        .and_then(|rest| rest.split_once(", k="))
+        .unwrap();
+
+    assert_eq!(public_key, key.public_key());
+
+    let (signing_input, signature) = token.rsplit_once('.').unwrap();
+    let verifying_key = VerifyingKey::from_sec1_bytes(&decode_base64(public_key).unwrap()).unwrap();
+    let signature = Signature::try_from(&decode_base64(signature).unwrap()[..]).unwrap();
+
+    verifying_key
+        .verify(signing_input.as_bytes(), &signature)
+        .unwrap();
+
+    let claims = signing_input.split('.').nth(1).unwrap();
+    let claims: serde_json::Value =
+        serde_json::from_slice(&decode_base64(claims).unwrap()).unwrap();
+
+    assert_eq!(claims["aud"], format!("http://{addr}"));
+    assert_eq!(claims["sub"], "mailto:test@example.com");
+    assert!(claims["exp"].as_i64().unwrap() > Utc::now().timestamp());
+}
+
+#[tokio::test]
+async fn expired_subscription_is_gone() {
+    let (addr, _inbox) = mock_receiver(vec![StatusCode::GONE]);
+    let subscription = Subscriber::new().subscription(&format!("http://{addr}/push/1"));
+    let key = VapidKey::generate("mailto:test@example.com");
+
+    let delivery = push::send(&key, &subscription, b"hi", 60, true)
+        .await
+        .unwrap();
+
+    assert_eq!(delivery, Delivery::Gone);
+}
+
+#[tokio::test]
+async fn send_due_only_sends_reminders_whose_time_has_come() {
+    let (addr, inbox) = mock_receiver(vec![StatusCode::CREATED]);
+    let subscriber = Subscriber::new();
+    let now = Utc::now();
+
+    let state = state(vec![
+        stop(101, 70011, now + Duration::minutes(5)),
+        stop(102, 70011, now + Duration::minutes(30)),
+    ]);
+
+    for (id, trip_id) in [(1, 101), (2, 102)] {
+        state
+            .reminders
+            .add(ReminderRequest {
+                subscription: subscriber.subscription(&format!("http://{addr}/push/{id}")),
+                trip_id,
+                station_id: 70011,
+                minutes_before: 10,
+            })
+            .await
+            .unwrap();
+    }
+
+    let sent = reminders::send_due(&state, &[], now).await.unwrap();
+
+    assert_eq!(sent, 1);
+
+    let remaining = state.reminders.all().await;
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].trip_id, 102);
+
+    let received = inbox.lock().unwrap().pop().unwrap();
+    let payload: serde_json::Value =
+        serde_json::from_slice(&subscriber.decrypt(&received.body)).unwrap();
+
+    assert_eq!(payload["url"], "/c/trip/101");
+}
+
+#[tokio::test]
+async fn send_due_prefers_live_departure_times() {
+    let (addr, inbox) = mock_receiver(vec![StatusCode::CREATED]);
+    let subscriber = Subscriber::new();
+    let now = Utc::now();
+
+    let state = state(vec![stop(101, 70011, now + Duration::minutes(5))]);
+
+    state
+        .reminders
+        .add(ReminderRequest {
+            subscription: subscriber.subscription(&format!("http://{addr}/push/1")),
+            trip_id: 101,
+            station_id: 70011,
+            minutes_before: 10,
+        })
+        .await
+        .unwrap();
+
+    // Running 20 minutes late, so not due yet
+    let live = [stop(101, 70011, now + Duration::minutes(25))];
+
+    assert_eq!(reminders::send_due(&state, &live, now).await.unwrap(), 0);
+    assert!(inbox.lock().unwrap().is_empty());
+    assert_eq!(state.reminders.all().await.len(), 1);
+}
+
+#[tokio::test]
+async fn send_due_drops_reminders_for_departed_trips() {
+    let state = state(vec![stop(101, 70011, Utc::now() - Duration::minutes(1))]);
+
+    state
+        .reminders
+        .add(ReminderRequest {
+            subscription: Subscriber::new().subscription("http://127.0.0.1:9/push/1"),
+            trip_id: 101,
+            station_id: 70011,
+            minutes_before: 10,
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(
+        reminders::send_due(&state, &[], Utc::now()).await.unwrap(),
+        0
+    );
+    assert!(state.reminders.all().await.is_empty());
+}
+
+#[tokio::test]
+async fn send_due_drops_reminders_for_trips_not_running_today() {
+    let (addr, inbox) = mock_receiver(vec![StatusCode::CREATED]);
+    let mut stop = stop(101, 70011, Utc::now() + Duration::minutes(5));
+    stop.service_id = String::from("holiday");
+    let state = state(vec![stop]);
+
+    state
+        .reminders
+        .add(ReminderRequest {
+            subscription: Subscriber::new().subscription(&format!("http://{addr}/push/1")),
+            trip_id: 101,
+            station_id: 70011,
+            minutes_before: 10,
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(
+        reminders::send_due(&state, &[], Utc::now()).await.unwrap(),
+        0
+    );
+    assert!(inbox.lock().unwrap().is_empty());
+    assert!(state.reminders.all().await.is_empty());
+}
+
+#[tokio::test]
+async fn reminders_only_go_to_push_services() {
+    let app = TestApp::start(pacific(2022, 3, 8, 6, 0), Vec::new());
+    let subscriber = Subscriber::new();
+
+    for endpoint in [
+        "http://fcm.googleapis.com/fcm/send/abc",
+        "https://127.0.0.1/push",
+        "https://169.254.169.254/latest/meta-data",
+        "https://example.com/push",
+        "https://fcm.googleapis.com.example.com/push",
+        "https://notfcm.googleapis.com/push",
+    ] {
+        let body = serde_json::to_vec(&ReminderRequest {
+            subscription: subscriber.subscription(endpoint),
+            trip_id: 101,
+            station_id: 1,
+            minutes_before: 10,
+        })
+        .unwrap();
+
+        let response = app
+            .request(Method::POST, "/api/reminders")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            response.status(),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "{} was accepted",
+            endpoint
+        );
+    }
+
+    assert!(app.state.reminders.all().await.is_empty());
+}
+
+#[tokio::test]
+async fn subscriptions_have_a_limited_number_of_reminders() {
+    let reminders = Reminders::in_memory();
+    let subscriber = Subscriber::new();
+    let request = |endpoint: &str| ReminderRequest {
+        subscription: subscriber.subscription(endpoint),
+        trip_id: 101,
+        station_id: 1,
+        minutes_before: 10,
+    };
+
+    for _ in 0..MAX_PER_SUBSCRIPTION {
+        reminders
+            .add(request("https://push.example/1"))
+            .await
+            .unwrap();
+    }
+
+    assert!(reminders
+        .add(request("https://push.example/1"))
+        .await
+        .is_err());
+    assert!(reminders
+        .add(request("https://push.example/2"))
+        .await
+        .is_ok());
+}
@@ -0,0 +1,140 @@
+//! Regression tests against 511.org responses archived by the recorder. New
+//! recordings dropped into `tests/fixtures/511` are matched against the
+//! fixture schedule automatically, review their snapshots with
+//! `cargo insta review`.
+
+use std::{path::Path, sync::Arc};
+
+use chrono::{prelude::*, Duration};
+use train_backend::{
+    clock::ManualClock,
+    realtime::{
+        match_visits, parse_stop_monitoring, Realtime, RealtimeProvider, Recorder, ReplayProvider,
+        Visit,
+    },
+};
+
+use common::TestApp;
+
+mod common;
+
+/// Parse a recording, with times in UTC so snapshots don't depend on the
+/// time zone of the machine running the tests.
+fn parse_recording(path: &Path) -> Vec<Visit> {
+    let body = std::fs::read_to_string(path).unwrap();
+    let utc = FixedOffset::east(0);
+
+    parse_stop_monitoring(&body)
+        .unwrap()
+        .into_iter()
+        .map(|visit| Visit {
+            arrival: visit.arrival.map(|t| t.with_timezone(&utc)),
+            departure: visit.departure.map(|t| t.with_timezone(&utc)),
+            ..visit
+        })
+        .collect()
+}
+
+/// When `path` was recorded, from its file name.
+fn recorded_at(path: &Path) -> DateTime<Utc> {
+    let stem = path.file_stem().unwrap().to_str().unwrap();
+
+    Utc.datetime_from_str(stem, "%Y%m%dT%H%M%SZ").unwrap()
+}
+
+#[tokio::test]
+async fn recorded_responses() {
+    insta::glob!("fixtures/511/*.json", |path| {
+        let app = TestApp::start(recorded_at(path), Vec::new());
+
+        insta::assert_yaml_snapshot!(match_visits(&app.state, parse_recording(path)));
+    });
+}
+
+#[test]
+fn malformed_response_is_an_error() {
+    assert!(parse_stop_monitoring("\u{feff}<html>502 Bad Gateway</html>").is_err());
+    assert!(parse_stop_monitoring("{\"ServiceDelivery\": {}}").is_err());
+}
+
+#[tokio::test]
+async fn recorder_archives_responses_by_day() {
+    let dir = tempfile::tempdir().unwrap();
+    let recorder = Recorder::new(dir.path().to_owned(), 7);
+    let at = Utc.ymd(2022, 3, 1).and_hms(16, 0, 0);
+
+    let path = recorder.record(at, 200, "{}").await.unwrap();
+
+    assert_eq!(path, dir.path().join("2022-03-01/20220301T160000Z.json"));
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "{}");
+}
+
+#[tokio::test]
+async fn errors_are_archived_and_replayed() {
+    let dir = tempfile::tempdir().unwrap();
+    let recorder = Recorder::new(dir.path().to_owned(), 7);
+    let at = Utc.ymd(2022, 3, 1).and_hms(16, 0, 0);
+
+    let path = recorder.record(at, 429, "Too many requests").await.unwrap();
+    assert_eq!(path, dir.path().join("2022-03-01/20220301T160000Z-429.txt"));
+    recorder
+        .record(at + Duration::minutes(1), 502, "<html>Bad Gateway</html>")
+        .await
+        .unwrap();
+
+    let clock = Arc::new(ManualClock::new(at));
+    let provider = ReplayProvider::load(dir.path(), None, clock.clone()).unwrap();
+
+    assert_eq!(provider.fetch().await.unwrap(), Realtime::RateLimited);
+
+    clock.set(at + Duration::minutes(1));
+    assert!(provider.fetch().await.is_err());
+}
+
+#[tokio::test]
+async fn recorder_removes_days_past_retention() {
+    let dir = tempfile::tempdir().unwrap();
+    let recorder = Recorder::new(dir.path().to_owned(), 2);
+    let first = Utc.ymd(2022, 3, 1).and_hms(16, 0, 0);
+
+    recorder.record(first, 200, "{}").await.unwrap();
+    recorder
+        .record(first + Duration::days(2), 200, "{}")
+        .await
+        .unwrap();
+    assert!(dir.path().join("2022-03-01").exists());
+
+    recorder
+        .record(first + Duration::days(3), 200, "{}")
+        .await
+        .unwrap();
+    assert!(!dir.path().join("2022-03-01").exists());
+    assert!(dir.path().join("2022-03-03").exists());
+    assert!(dir.path().join("2022-03-04").exists());
+}
+
+#[tokio::test]
+async fn replay_shifts_recordings_to_now() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/511");
+    let start = Utc.ymd(2022, 3, 1).and_hms(16, 0, 0);
+    let now = Utc::now();
+
+    let provider =
+        ReplayProvider::load(&dir, Some(start), Arc::new(ManualClock::new(now))).unwrap();
+
+    let visits = match provider.fetch().await.unwrap() {
+        Realtime::Visits(visits) => visits,
+        Realtime::RateLimited => panic!("replay is never rate limited"),
+    };
+    let expected = parse_recording(&dir.join("20220301T160000Z.json"));
+
+    assert_eq!(visits.len(), expected.len());
+
+    for (visit, expected) in visits.iter().zip(&expected) {
+        assert_eq!(visit.journey_ref, expected.journey_ref);
+        assert_eq!(
+            visit.departure,
+            expected.departure.map(|t| t + (now - start))
+        );
+    }
+}
@@ -0,0 +1,48 @@
+//! Sampling traces that start in the browser.
+
+use opentelemetry::{
+    sdk::trace::{SamplingDecision, ShouldSample},
+    trace::{SpanContext, SpanId, SpanKind, TraceContextExt, TraceFlags, TraceId, TraceState},
+    Context,
+};
+use train_backend::telemetry::ParentOrRatio;
+
+/// The decision for a span under a remote parent with the given flags.
+fn decide(sampler: ParentOrRatio, trace_id: TraceId, flags: TraceFlags) -> SamplingDecision {
+    let parent = Context::new().with_remote_span_context(SpanContext::new(
+        trace_id,
+        SpanId::from_u64(1),
+        flags,
+        true,
+        TraceState::default(),
+    ));
+
+    sampler
+        .should_sample(Some(&parent), trace_id, "GET", &SpanKind::Server, &[], &[])
+        .decision
+}
+
+#[test]
+fn sampled_parents_are_always_recorded() {
+    let decision = decide(
+        ParentOrRatio(0.0),
+        TraceId::from_u128(1),
+        TraceFlags::SAMPLED,
+    );
+
+    assert_eq!(decision, SamplingDecision::RecordAndSample);
+}
+
+#[test]
+fn unsampled_parents_leave_the_decision_to_the_ratio() {
+    let trace_id = TraceId::from_u128(1);
+
+    assert_eq!(
+        decide(ParentOrRatio(1.0), trace_id, TraceFlags::default()),
+        SamplingDecision::RecordAndSample
+    );
+    assert_eq!(
+        decide(ParentOrRatio(0.0), trace_id, TraceFlags::default()),
+        SamplingDecision::Drop
+    );
+}
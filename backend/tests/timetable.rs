@@ -0,0 +1,90 @@
+//! Timetables, with every trip in a direction lined up by station.
+
+use std::collections::HashMap;
+
+use train_backend::routes::timetable::station_order;
+use train_schedules_common::{Stop, Timetable};
+
+use common::{pacific, stop, TestApp};
+
+mod common;
+
+/// Stops of trips calling at `stations`, in order.
+fn trips(trips: &[(i64, &[i64])]) -> Vec<Stop> {
+    trips
+        .iter()
+        .flat_map(|(trip_id, stations)| {
+            stations.iter().enumerate().map(move |(i, station)| {
+                let mut stop = stop(*trip_id, *station, pacific(2022, 3, 8, 7, i as u32));
+                stop.stop_sequence = i as i64 + 1;
+                stop
+            })
+        })
+        .collect()
+}
+
+fn order(stops: &[Stop]) -> Vec<i64> {
+    let mut by_trip: HashMap<i64, Vec<&Stop>> = HashMap::new();
+    for stop in stops {
+        by_trip.entry(stop.trip_id).or_default().push(stop);
+    }
+
+    station_order(&by_trip)
+}
+
+#[test]
+fn stations_only_some_trips_serve_land_between_their_neighbours() {
+    let stops = trips(&[
+        (1, &[1, 2, 3, 4]),
+        // An express skipping 2, and going on further
+        (2, &[1, 3, 4, 5]),
+        // A short trip calling somewhere nothing else does
+        (3, &[2, 6, 3]),
+    ]);
+
+    assert_eq!(order(&stops), vec![1, 2, 6, 3, 4, 5]);
+}
+
+#[test]
+fn trips_disagreeing_on_order_fall_back_to_the_longest_trip() {
+    let stops = trips(&[(1, &[1, 2]), (2, &[2, 1]), (3, &[3, 1, 2])]);
+
+    assert_eq!(order(&stops), vec![3, 1, 2]);
+}
+
+#[tokio::test]
+async fn timetable_lists_the_day_in_one_direction() {
+    let app = TestApp::start(pacific(2022, 3, 8, 6, 0), Vec::new());
+
+    let timetable: Timetable = app
+        .get_json("/api/timetable?direction=1&date=2022-03-08")
+        .await;
+
+    let stations: Vec<&str> = timetable.stations.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(
+        stations,
+        vec![
+            "San Francisco Caltrain",
+            "Palo Alto Caltrain",
+            "San Jose Diridon Caltrain"
+        ]
+    );
+
+    let trips: Vec<i64> = timetable.trips.iter().map(|t| t.trip_id).collect();
+    assert_eq!(trips, vec![101, 103, 199]);
+}
+
+#[tokio::test]
+async fn timetable_times_are_on_the_requested_date() {
+    let app = TestApp::start(pacific(2022, 3, 8, 6, 0), Vec::new());
+
+    // A week later, after the clocks have gone forward
+    let timetable: Timetable = app
+        .get_json("/api/timetable?direction=1&date=2022-03-15")
+        .await;
+
+    let first = timetable.trips[0].stops[0].as_ref().unwrap();
+    assert_eq!(first.trip_id, 101);
+    assert_eq!(first.departure, pacific(2022, 3, 15, 7, 0));
+    assert_eq!(first.departure.offset().local_minus_utc(), -7 * 3600);
+}
@@ -0,0 +1,143 @@
+//! Times come from the agency's time zone in agency.txt, whatever the time
+//! zone of the server, and stay right when the clocks change.
+
+use std::process::Command;
+
+use axum::http::StatusCode;
+use chrono::{prelude::*, Duration};
+use chrono_tz::{America::Los_Angeles, US::Pacific};
+use train_backend::db;
+use train_schedules_common::Stop;
+
+use common::{fixture_db, pacific, recording, TestApp};
+
+mod common;
+
+const SAN_FRANCISCO: i64 = 1;
+const SAN_JOSE: i64 = 3;
+
+fn pst() -> FixedOffset {
+    FixedOffset::west(8 * 3600)
+}
+
+fn pdt() -> FixedOffset {
+    FixedOffset::west(7 * 3600)
+}
+
+fn find(stops: &[Stop], trip_id: i64, station_id: i64) -> &Stop {
+    stops
+        .iter()
+        .find(|s| s.trip_id == trip_id && s.station_id == station_id)
+        .unwrap()
+}
+
+#[test]
+fn server_time_zone_does_not_matter() {
+    for tz in [
+        "UTC",
+        "Asia/Tokyo",
+        "America/New_York",
+        "Pacific/Kiritimati",
+    ] {
+        let status = Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "evening_in_the_agency_time_zone", "--quiet"])
+            .env("TZ", tz)
+            .status()
+            .unwrap();
+
+        assert!(status.success(), "failed with TZ={}", tz);
+    }
+}
+
+/// Run by [`server_time_zone_does_not_matter`] under other `TZ` settings.
+/// Late afternoon in California is already tomorrow in UTC.
+#[tokio::test]
+async fn evening_in_the_agency_time_zone() {
+    let app = TestApp::start(
+        pacific(2022, 3, 1, 17, 30),
+        vec![(StatusCode::OK, recording("20220301T160000Z.json"))],
+    );
+
+    let stops: Vec<Stop> = app
+        .get_json(&format!("/api/upcoming-trips?start={SAN_FRANCISCO}"))
+        .await;
+
+    assert_eq!(stops.len(), 1);
+    assert_eq!(stops[0].trip_id, 199);
+    assert_eq!(stops[0].departure.offset(), &pst());
+    assert_eq!(stops[0].departure.hour(), 23);
+
+    let live: Vec<Stop> = app.get_json("/api/stations/live").await;
+
+    assert!(!live.is_empty());
+    for stop in live {
+        assert_eq!(stop.departure.offset(), &pst());
+    }
+}
+
+#[test]
+fn agency_timezone_comes_from_agency_txt() {
+    let connection = fixture_db("gtfs");
+
+    assert_eq!(db::agency_timezone(&connection).unwrap(), Los_Angeles);
+}
+
+#[test]
+fn stop_times_count_from_noon_minus_twelve_hours() {
+    let connection = fixture_db("gtfs");
+
+    // The clocks go forward at 2am on Sunday March 13th 2022
+    let stops = db::all_stops(&connection, Pacific.ymd(2022, 3, 13)).unwrap();
+    let departure = find(&stops, 101, SAN_FRANCISCO).departure;
+    assert_eq!(departure, Pacific.ymd(2022, 3, 13).and_hms(7, 0, 0));
+    assert_eq!(departure.offset(), &pdt());
+
+    // And back at 2am on Sunday November 6th. Saturday's service runs into
+    // the first 1am of Sunday
+    let stops = db::all_stops(&connection, Pacific.ymd(2022, 11, 5)).unwrap();
+    let arrival = find(&stops, 199, SAN_JOSE).arrival;
+    assert_eq!(arrival.to_rfc3339(), "2022-11-06T01:20:00-07:00");
+
+    let stops = db::all_stops(&connection, Pacific.ymd(2022, 11, 6)).unwrap();
+    let departure = find(&stops, 101, SAN_FRANCISCO).departure;
+    assert_eq!(departure.to_rfc3339(), "2022-11-06T07:00:00-08:00");
+}
+
+#[tokio::test]
+async fn upcoming_keeps_local_times_when_clocks_go_forward() {
+    // Loaded on a Thursday in standard time
+    let app = TestApp::start(pacific(2022, 3, 10, 12, 0), Vec::new());
+
+    app.clock.set(pacific(2022, 3, 13, 8, 0));
+
+    let stops: Vec<Stop> = app
+        .get_json(&format!("/api/upcoming-trips?start={SAN_FRANCISCO}"))
+        .await;
+
+    assert_eq!(stops[0].trip_id, 201);
+    assert_eq!(stops[0].departure.to_rfc3339(), "2022-03-13T09:00:00-07:00");
+}
+
+#[tokio::test]
+async fn upcoming_keeps_local_times_when_clocks_go_back() {
+    // Loaded on a Thursday in daylight saving time
+    let app = TestApp::start(pacific(2022, 11, 3, 12, 0), Vec::new());
+
+    app.clock.set(pacific(2022, 11, 6, 8, 0));
+
+    let stops: Vec<Stop> = app
+        .get_json(&format!("/api/upcoming-trips?start={SAN_FRANCISCO}"))
+        .await;
+
+    assert_eq!(stops[0].trip_id, 201);
+    assert_eq!(stops[0].departure.to_rfc3339(), "2022-11-06T09:00:00-08:00");
+
+    // A week later the clock has moved on but the schedule hasn't
+    app.clock.advance(Duration::weeks(1));
+
+    let stops: Vec<Stop> = app
+        .get_json(&format!("/api/upcoming-trips?start={SAN_FRANCISCO}"))
+        .await;
+
+    assert_eq!(stops[0].departure.to_rfc3339(), "2022-11-13T09:00:00-08:00");
+}
@@ -0,0 +1,385 @@
+use std::time::Duration as StdDuration;
+
+use axum::http::{header, StatusCode};
+use chrono::{prelude::*, Duration};
+use hmac::{Hmac, Mac};
+use reqwest::Method;
+use sha2::Sha256;
+use train_backend::webhooks::{RetryPolicy, Webhooks, SIGNATURE_HEADER};
+use train_schedules_common::{DelayNotification, Stop, WebhookRequest, WebhookWatch};
+
+use common::{mock_receiver, pacific, recording, stop, TestApp, ADMIN_TOKEN};
+
+mod common;
+
+const SECRET: &str = "hunter2";
+
+fn fast_retries() -> RetryPolicy {
+    RetryPolicy {
+        attempts: 4,
+        base_delay: StdDuration::from_millis(10),
+    }
+}
+
+fn request(watch: WebhookWatch, target_url: String) -> WebhookRequest {
+    WebhookRequest {
+        watch,
+        delay_threshold_minutes: 5,
+        target_url,
+        secret: SECRET.to_owned(),
+    }
+}
+
+/// The same stop, departing `minutes` late.
+fn late(stop: &Stop, minutes: i64) -> Stop {
+    let mut late = stop.clone();
+    late.departure = late.departure + Duration::minutes(minutes);
+    late.arrival = late.arrival + Duration::minutes(minutes);
+    late
+}
+
+#[tokio::test]
+async fn delivers_signed_payload_for_late_trip() {
+    let (addr, inbox) = mock_receiver(vec![StatusCode::OK]);
+    let webhooks = Webhooks::in_memory(fast_retries()).with_private_targets(true);
+    let now = Utc::now();
+
+    let webhook = webhooks
+        .add(request(
+            WebhookWatch::Trip { trip_id: 101 },
+            format!("http://{addr}/hook"),
+        ))
+        .await
+        .unwrap();
+
+    let scheduled = vec![
+        stop(101, 1, now + Duration::minutes(10)),
+        stop(101, 2, now + Duration::minutes(20)),
+    ];
+    let live = vec![late(&scheduled[0], 8), late(&scheduled[1], 8)];
+
+    let pending = webhooks.evaluate(&scheduled, &live).await;
+    assert_eq!(pending.len(), 1);
+
+    assert!(webhooks.deliver(pending[0].clone()).await);
+
+    let received = inbox.lock().unwrap().pop().unwrap();
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+    mac.update(&received.body);
+    let expected = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+
+    assert_eq!(received.headers[SIGNATURE_HEADER], expected.as_str());
+
+    let notification: DelayNotification = serde_json::from_slice(&received.body).unwrap();
+    assert_eq!(notification.webhook_id, webhook.id);
+    assert_eq!(notification.trip_id, 101);
+    assert_eq!(notification.station_id, 1);
+    assert_eq!(notification.delay_minutes, 8);
+
+    let log = webhooks.deliveries(&webhook.id).await;
+    assert_eq!(log.len(), 1);
+    assert!(log[0].success);
+    assert_eq!(log[0].status, Some(200));
+}
+
+#[tokio::test]
+async fn ignores_delays_under_threshold() {
+    let webhooks = Webhooks::in_memory(fast_retries()).with_private_targets(true);
+
+    webhooks
+        .add(request(
+            WebhookWatch::Trip { trip_id: 101 },
+            "http://127.0.0.1:9/hook".to_owned(),
+        ))
+        .await
+        .unwrap();
+
+    let scheduled = vec![stop(101, 1, Utc::now() + Duration::minutes(10))];
+    let live = vec![late(&scheduled[0], 4)];
+
+    assert!(webhooks.evaluate(&scheduled, &live).await.is_empty());
+}
+
+#[tokio::test]
+async fn notifies_again_only_when_delay_grows() {
+    let webhooks = Webhooks::in_memory(fast_retries()).with_private_targets(true);
+
+    webhooks
+        .add(request(
+            WebhookWatch::Trip { trip_id: 101 },
+            "http://127.0.0.1:9/hook".to_owned(),
+        ))
+        .await
+        .unwrap();
+
+    let scheduled = vec![stop(101, 1, Utc::now() + Duration::minutes(10))];
+
+    assert_eq!(
+        webhooks
+            .evaluate(&scheduled, &[late(&scheduled[0], 6)])
+            .await
+            .len(),
+        1
+    );
+    assert!(webhooks
+        .evaluate(&scheduled, &[late(&scheduled[0], 8)])
+        .await
+        .is_empty());
+    assert_eq!(
+        webhooks
+            .evaluate(&scheduled, &[late(&scheduled[0], 11)])
+            .await
+            .len(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn station_pair_only_matches_trips_in_that_direction() {
+    let webhooks = Webhooks::in_memory(fast_retries()).with_private_targets(true);
+    let now = Utc::now();
+
+    webhooks
+        .add(request(
+            WebhookWatch::StationPair { start: 1, end: 2 },
+            "http://127.0.0.1:9/hook".to_owned(),
+        ))
+        .await
+        .unwrap();
+
+    let scheduled = vec![
+        stop(101, 1, now + Duration::minutes(10)),
+        stop(101, 2, now + Duration::minutes(20)),
+        stop(102, 2, now + Duration::minutes(10)),
+        stop(102, 1, now + Duration::minutes(20)),
+    ];
+    let live: Vec<Stop> = scheduled.iter().map(|s| late(s, 10)).collect();
+
+    let pending = webhooks.evaluate(&scheduled, &live).await;
+
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].notification.trip_id, 101);
+    assert_eq!(pending[0].notification.station_id, 1);
+}
+
+#[tokio::test]
+async fn retries_server_errors() {
+    let (addr, inbox) = mock_receiver(vec![
+        StatusCode::INTERNAL_SERVER_ERROR,
+        StatusCode::SERVICE_UNAVAILABLE,
+        StatusCode::OK,
+    ]);
+    let webhooks = Webhooks::in_memory(fast_retries()).with_private_targets(true);
+
+    let webhook = webhooks
+        .add(request(
+            WebhookWatch::Trip { trip_id: 101 },
+            format!("http://{addr}/hook"),
+        ))
+        .await
+        .unwrap();
+
+    let scheduled = vec![stop(101, 1, Utc::now() + Duration::minutes(10))];
+    let pending = webhooks
+        .evaluate(&scheduled, &[late(&scheduled[0], 10)])
+        .await;
+
+    assert!(webhooks.deliver(pending[0].clone()).await);
+    assert_eq!(inbox.lock().unwrap().len(), 3);
+
+    let log = webhooks.deliveries(&webhook.id).await;
+    let statuses: Vec<_> = log.iter().map(|d| d.status).collect();
+
+    assert_eq!(statuses, vec![Some(500), Some(503), Some(200)]);
+    assert_eq!(
+        log.iter().map(|d| d.attempt).collect::<Vec<_>>(),
+        vec![1, 2, 3]
+    );
+}
+
+#[tokio::test]
+async fn gives_up_on_client_errors() {
+    let (addr, inbox) = mock_receiver(vec![StatusCode::BAD_REQUEST]);
+    let webhooks = Webhooks::in_memory(fast_retries()).with_private_targets(true);
+
+    let webhook = webhooks
+        .add(request(
+            WebhookWatch::Trip { trip_id: 101 },
+            format!("http://{addr}/hook"),
+        ))
+        .await
+        .unwrap();
+
+    let scheduled = vec![stop(101, 1, Utc::now() + Duration::minutes(10))];
+    let pending = webhooks
+        .evaluate(&scheduled, &[late(&scheduled[0], 10)])
+        .await;
+
+    assert!(!webhooks.deliver(pending[0].clone()).await);
+    assert_eq!(inbox.lock().unwrap().len(), 1);
+    assert!(!webhooks.deliveries(&webhook.id).await[0].success);
+}
+
+#[tokio::test]
+async fn deliveries_check_the_target_address_again() {
+    let (addr, inbox) = mock_receiver(vec![StatusCode::OK]);
+    let webhooks = Webhooks::in_memory(fast_retries());
+
+    // As if the target's host resolved to a public address when the webhook
+    // was created, and was pointed at this one since
+    let webhook = webhooks
+        .add(request(
+            WebhookWatch::Trip { trip_id: 101 },
+            format!("http://localhost:{}/hook", addr.port()),
+        ))
+        .await
+        .unwrap();
+
+    let scheduled = vec![stop(101, 1, Utc::now() + Duration::minutes(10))];
+    let pending = webhooks
+        .evaluate(&scheduled, &[late(&scheduled[0], 10)])
+        .await;
+
+    assert!(!webhooks.deliver(pending[0].clone()).await);
+    assert!(inbox.lock().unwrap().is_empty());
+
+    let log = webhooks.deliveries(&webhook.id).await;
+    assert!(log
+        .iter()
+        .all(|d| d.error.as_ref().unwrap().contains("isn't a public address")));
+}
+
+#[tokio::test]
+async fn logs_unreachable_targets() {
+    // Bind and immediately drop a listener to find a port nothing listens on
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let webhooks = Webhooks::in_memory(fast_retries()).with_private_targets(true);
+
+    let webhook = webhooks
+        .add(request(
+            WebhookWatch::Trip { trip_id: 101 },
+            format!("http://{addr}/hook"),
+        ))
+        .await
+        .unwrap();
+
+    let scheduled = vec![stop(101, 1, Utc::now() + Duration::minutes(10))];
+    let pending = webhooks
+        .evaluate(&scheduled, &[late(&scheduled[0], 10)])
+        .await;
+
+    assert!(!webhooks.deliver(pending[0].clone()).await);
+
+    let log = webhooks.deliveries(&webhook.id).await;
+    assert_eq!(log.len(), 4);
+    assert!(log.iter().all(|d| d.status.is_none() && d.error.is_some()));
+}
+
+#[tokio::test]
+async fn trains_on_time_days_after_startup_are_not_reported() {
+    // The server started the day before the live status
+    let app = TestApp::start(
+        pacific(2022, 2, 28, 8, 0),
+        vec![(StatusCode::OK, recording("20220301T160000Z.json"))],
+    );
+    app.clock.set(pacific(2022, 3, 1, 8, 0));
+
+    let webhook = app
+        .state
+        .webhooks
+        .add(request(
+            WebhookWatch::Trip { trip_id: 104 },
+            String::from("http://127.0.0.1:9/hook"),
+        ))
+        .await
+        .unwrap();
+
+    let live: Vec<Stop> = app.get_json("/api/stations/live").await;
+    assert!(live.iter().any(|s| s.trip_id == 104));
+
+    // Deliveries are made in the background, give them a moment to start
+    tokio::time::sleep(StdDuration::from_millis(100)).await;
+    assert!(app.state.webhooks.deliveries(&webhook.id).await.is_empty());
+}
+
+/// Ask the app to create a webhook for train 101 posting to `target_url`.
+async fn create(app: &TestApp, target_url: &str) -> reqwest::Response {
+    let body = serde_json::to_vec(&request(
+        WebhookWatch::Trip { trip_id: 101 },
+        target_url.to_owned(),
+    ))
+    .unwrap();
+
+    app.request(Method::POST, "/api/webhooks")
+        .bearer_auth(ADMIN_TOKEN)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn managing_webhooks_needs_the_admin_token() {
+    let app = TestApp::start(pacific(2022, 3, 8, 6, 0), Vec::new());
+
+    let response = app.get("/api/webhooks").await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = app
+        .request(Method::GET, "/api/webhooks")
+        .bearer_auth("hunter2")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = app
+        .request(Method::DELETE, "/api/webhooks/abc")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = app
+        .request(Method::GET, "/api/webhooks")
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn webhooks_only_post_to_public_addresses() {
+    let app = TestApp::start(pacific(2022, 3, 8, 6, 0), Vec::new());
+
+    for target in [
+        "http://127.0.0.1:8088/hook",
+        "http://localhost/hook",
+        "http://10.0.0.5/hook",
+        "http://192.168.1.1/hook",
+        "http://169.254.169.254/latest/meta-data",
+        "http://[::1]/hook",
+        "http://[fd00::1]/hook",
+        "http://[::ffff:127.0.0.1]/hook",
+        "ftp://93.184.216.34/hook",
+    ] {
+        let response = create(&app, target).await;
+        assert_eq!(
+            response.status(),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "{} was accepted",
+            target
+        );
+    }
+    assert!(app.state.webhooks.is_empty().await);
+
+    let response = create(&app, "http://93.184.216.34/hook").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(app.state.webhooks.all().await.len(), 1);
+}
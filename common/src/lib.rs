@@ -1,4 +1,6 @@
-use chrono::prelude::*;
+use std::collections::HashMap;
+
+use chrono::{prelude::*, Duration};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
@@ -13,6 +15,14 @@ pub struct Station {
     pub name: String,
     pub station_id: i64,
     pub stop_codes: Vec<i64>,
+    pub lat: f64,
+    pub lon: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NearbyStation {
+    pub station: Station,
+    pub distance_meters: f64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -22,6 +32,374 @@ pub struct TwoStop {
     pub end: Stop,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ServiceCalendar {
+    pub id: String,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub weekdays: Vec<Weekday>,
+    #[serde(default)]
+    pub added_dates: Vec<NaiveDate>,
+    /// Holidays and other days the service doesn't run
+    #[serde(default)]
+    pub removed_dates: Vec<NaiveDate>,
+}
+
+impl ServiceCalendar {
+    /// Whether trips on this service run on `date`, start and end dates
+    /// included.
+    pub fn runs_on(&self, date: NaiveDate) -> bool {
+        if self.removed_dates.contains(&date) {
+            return false;
+        }
+
+        self.added_dates.contains(&date)
+            || (self.start_date <= date
+                && date <= self.end_date
+                && self.weekdays.contains(&date.weekday()))
+    }
+}
+
+/// Everything needed to answer schedule questions without the backend, for
+/// use when the app is offline.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScheduleBundle {
+    pub stations: Vec<Station>,
+    pub services: Vec<ServiceCalendar>,
+    pub trips: Vec<BundleTrip>,
+    /// The agency's UTC offsets over the days the bundle covers, oldest
+    /// first, so times come out the same as the backend's wherever the
+    /// browser is
+    #[serde(default)]
+    pub utc_offsets: Vec<UtcOffset>,
+}
+
+/// The UTC offset the agency's time zone has from `from` until the next
+/// change.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct UtcOffset {
+    pub from: DateTime<Utc>,
+    pub seconds_east: i32,
+}
+
+impl UtcOffset {
+    pub fn offset(&self) -> FixedOffset {
+        FixedOffset::east(self.seconds_east)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BundleTrip {
+    pub trip_id: i64,
+    pub service_id: String,
+    #[serde(default)]
+    pub direction_id: i64,
+    #[serde(default)]
+    pub headsign: String,
+    pub stops: Vec<BundleStop>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BundleStop {
+    pub station_id: i64,
+    pub stop_sequence: i64,
+    /// Minutes after the start of the service day, which is noon minus 12
+    /// hours in the agency's time zone. That's midnight except when the clocks
+    /// change. Past 24 hours for trips running after midnight
+    pub arrival: i64,
+    pub departure: i64,
+    #[serde(default)]
+    pub pickup_type: Boarding,
+    #[serde(default)]
+    pub drop_off_type: Boarding,
+    #[serde(default)]
+    pub platform: Option<String>,
+}
+
+impl ScheduleBundle {
+    /// `time` in the agency's time zone. Stays in its own offset if the bundle
+    /// doesn't cover it.
+    pub fn localize(&self, time: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        match self.offset_at(time) {
+            Some(offset) => time.with_timezone(&offset),
+            None => time,
+        }
+    }
+
+    /// The agency's service day at `now`.
+    pub fn today(&self, now: DateTime<FixedOffset>) -> NaiveDate {
+        self.localize(now).date().naive_local()
+    }
+
+    fn offset_at(&self, time: DateTime<FixedOffset>) -> Option<FixedOffset> {
+        self.utc_offsets
+            .iter()
+            .rev()
+            .find(|o| o.from <= time)
+            .map(UtcOffset::offset)
+    }
+
+    /// When stop times on `date` are counted from: noon minus 12 hours in the
+    /// agency's time zone, the same as the backend.
+    fn day_start(&self, date: NaiveDate) -> Option<DateTime<FixedOffset>> {
+        let noon = date.and_hms(12, 0, 0);
+        let noon_at = |offset: FixedOffset| {
+            DateTime::<FixedOffset>::from_utc(
+                noon - Duration::seconds(offset.local_minus_utc().into()),
+                offset,
+            )
+        };
+
+        // The clocks never change at noon, so only one offset fits
+        let noon = self
+            .utc_offsets
+            .iter()
+            .map(|o| noon_at(o.offset()))
+            .find(|noon| self.offset_at(*noon) == Some(*noon.offset()))?;
+
+        Some(self.localize(noon - Duration::hours(12)))
+    }
+
+    /// Scheduled stops of every trip running on `date`, in the agency's time
+    /// zone. None for days before the bundle starts.
+    pub fn stops_on(&self, date: NaiveDate) -> Vec<Stop> {
+        let start = match self.day_start(date) {
+            Some(start) => start,
+            None => return Vec::new(),
+        };
+
+        let running = self
+            .services
+            .iter()
+            .filter(|s| s.runs_on(date))
+            .map(|s| s.id.as_str())
+            .collect::<Vec<_>>();
+
+        let mut stops = Vec::new();
+
+        for trip in self
+            .trips
+            .iter()
+            .filter(|t| running.contains(&t.service_id.as_str()))
+        {
+            for stop in &trip.stops {
+                let station_name = self
+                    .stations
+                    .iter()
+                    .find(|s| s.station_id == stop.station_id)
+                    .map(|s| s.name.clone())
+                    .unwrap_or_default();
+
+                stops.push(Stop {
+                    station_id: stop.station_id,
+                    trip_id: trip.trip_id,
+                    station_name,
+                    arrival: self.localize(start + Duration::minutes(stop.arrival)),
+                    departure: self.localize(start + Duration::minutes(stop.departure)),
+                    service_id: trip.service_id.clone(),
+                    stop_sequence: stop.stop_sequence,
+                    pickup_type: stop.pickup_type,
+                    drop_off_type: stop.drop_off_type,
+                    platform: stop.platform.clone(),
+                    direction_id: trip.direction_id,
+                    headsign: trip.headsign.clone(),
+                });
+            }
+        }
+
+        stops
+    }
+
+    /// Stops at `station_id` departing after `now`, soonest first. Includes
+    /// trips left over from yesterday's service that run past midnight.
+    pub fn upcoming(&self, station_id: i64, now: DateTime<FixedOffset>) -> Vec<Stop> {
+        let mut stops = self
+            .service_days(now)
+            .into_iter()
+            .flat_map(|date| self.stops_on(date))
+            .filter(|s| s.station_id == station_id && s.departure > now)
+            .collect::<Vec<_>>();
+
+        stops.sort_by_key(|s| s.departure);
+
+        stops
+    }
+
+    /// Trips from `start` to `end` running on the service days around `now`.
+    pub fn twostop_list(
+        &self,
+        start: i64,
+        end: i64,
+        now: DateTime<FixedOffset>,
+    ) -> Option<TwoStopList> {
+        let station = |id| self.stations.iter().find(|s| s.station_id == id).cloned();
+
+        let mut trips = Vec::new();
+
+        for date in self.service_days(now) {
+            let stops = self.stops_on(date);
+            let services = stops
+                .iter()
+                .map(|s| s.service_id.clone())
+                .collect::<Vec<_>>();
+
+            trips.extend(twostops(&stops, start, end, &services));
+        }
+
+        trips.sort_by_key(|t| t.start.departure);
+
+        Some(TwoStopList {
+            start: station(start)?,
+            end: station(end)?,
+            trips,
+        })
+    }
+
+    fn service_days(&self, now: DateTime<FixedOffset>) -> Vec<NaiveDate> {
+        let today = self.today(now);
+
+        vec![today - Duration::days(1), today]
+    }
+}
+
+/// Trips on one of `services` that go from `start_station` to `end_station`,
+/// sorted by departure.
+pub fn twostops(
+    stops: &[Stop],
+    start_station: i64,
+    end_station: i64,
+    services: &[String],
+) -> Vec<TwoStop> {
+    let mut trips = HashMap::new();
+
+    for stop in stops {
+        if stop.station_id != start_station && stop.station_id != end_station {
+            continue;
+        }
+
+        if !services.contains(&stop.service_id) {
+            continue;
+        }
+
+        trips
+            .entry(stop.trip_id)
+            .or_insert_with(Vec::new)
+            .push(stop.clone());
+    }
+
+    let mut stops = Vec::new();
+
+    for (trip_id, mut trip) in trips {
+        if trip.len() != 2 {
+            continue;
+        }
+
+        trip.sort_by_key(|s| s.departure);
+
+        let end = trip.pop().unwrap();
+        let start = trip.pop().unwrap();
+
+        // Trains going the other way call at both stations too
+        if start.station_id != start_station {
+            continue;
+        }
+
+        stops.push(TwoStop {
+            trip_id,
+            start,
+            end,
+        });
+    }
+
+    stops.sort_by_key(|t| t.start.departure);
+
+    stops
+}
+
+/// A browser's push subscription, in the shape of `PushSubscription.toJSON()`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PushSubscription {
+    pub endpoint: String,
+    pub keys: PushSubscriptionKeys,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PushSubscriptionKeys {
+    pub p256dh: String,
+    pub auth: String,
+}
+
+/// Ask for a push notification `minutes_before` a trip departs a station.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReminderRequest {
+    pub subscription: PushSubscription,
+    pub trip_id: i64,
+    pub station_id: i64,
+    pub minutes_before: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Reminder {
+    pub id: String,
+    pub trip_id: i64,
+    pub station_id: i64,
+    pub minutes_before: i64,
+}
+
+/// What a webhook watches for delays.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WebhookWatch {
+    /// A single train
+    Trip { trip_id: i64 },
+    /// Every train from `start` to `end`, with delays measured at `start`
+    StationPair { start: i64, end: i64 },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WebhookRequest {
+    pub watch: WebhookWatch,
+    /// Only notify once a train is at least this many minutes late
+    pub delay_threshold_minutes: i64,
+    pub target_url: String,
+    /// Key for the HMAC-SHA256 signature sent with every delivery
+    pub secret: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Webhook {
+    pub id: String,
+    pub watch: WebhookWatch,
+    pub delay_threshold_minutes: i64,
+    pub target_url: String,
+}
+
+/// Body of a webhook delivery.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DelayNotification {
+    pub webhook_id: String,
+    pub trip_id: i64,
+    pub station_id: i64,
+    pub station_name: String,
+    pub scheduled_departure: DateTime<FixedOffset>,
+    pub expected_departure: DateTime<FixedOffset>,
+    pub delay_minutes: i64,
+}
+
+/// One attempt at delivering a notification to a webhook.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WebhookDelivery {
+    pub webhook_id: String,
+    pub trip_id: i64,
+    pub delay_minutes: i64,
+    pub attempt: u32,
+    pub attempted_at: DateTime<Utc>,
+    /// HTTP status of the response, if one was received
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub success: bool,
+}
+
 pub fn time_str(minute: i64) -> String {
     let hour = minute / 60;
     let min = minute % 60;
@@ -29,10 +407,168 @@ pub fn time_str(minute: i64) -> String {
     format!("{:02}:{:02}", hour, min)
 }
 
-#[derive(Serialize, Deserialize, PartialEq)]
+/// Caltrain's `direction_id`s, and what riders call them
+pub const DIRECTIONS: [(i64, &str); 2] = [(0, "Northbound"), (1, "Southbound")];
+
+/// The kind of service a train runs, from the hundreds of its number.
+pub fn train_type(trip_id: i64) -> Option<&'static str> {
+    match trip_id / 100 {
+        1 | 4 => Some("local"),
+        2 => Some("limited"),
+        3 | 8 => Some("bullet"),
+        _ => None,
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Trip {
     pub trip_id: i64,
-    pub stops: Vec<Stop>,
+    /// In the order the train calls at them
+    pub stops: Vec<TripStop>,
+    /// Minutes late at the next station the train calls at, negative when
+    /// early. `None` when there's no live status for the trip
+    pub delay_minutes: Option<i64>,
+    pub position: TripPosition,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TripStop {
+    pub scheduled: Stop,
+    /// Expected times, for stations the train has yet to reach
+    pub live: Option<Stop>,
+    pub delay_minutes: Option<i64>,
+}
+
+impl TripStop {
+    /// When the train is expected to arrive, live if known
+    pub fn arrival(&self) -> DateTime<FixedOffset> {
+        self.live.as_ref().unwrap_or(&self.scheduled).arrival
+    }
+
+    /// When the train is expected to depart, live if known
+    pub fn departure(&self) -> DateTime<FixedOffset> {
+        self.live.as_ref().unwrap_or(&self.scheduled).departure
+    }
+
+    /// Minutes the train is expected to wait at the station
+    pub fn dwell_minutes(&self) -> i64 {
+        (self.departure() - self.arrival()).num_minutes()
+    }
+
+    /// Whether the train arrives before it departs, by the schedule or live,
+    /// so both times are worth showing
+    pub fn waits(&self) -> bool {
+        self.arrival() != self.departure() || self.scheduled.arrival != self.scheduled.departure
+    }
+}
+
+/// Where a train is along its trip, estimated from its expected times.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TripPosition {
+    /// Not yet at its first station
+    NotStarted,
+    /// Standing at `Trip::stops[stop_index]`
+    AtStop { stop_index: usize },
+    /// On its way from `Trip::stops[stop_index]` to the next stop, `progress`
+    /// between 0 and 1 of the way there
+    Between { stop_index: usize, progress: f64 },
+    /// Past its last station
+    Finished,
+}
+
+impl Trip {
+    /// `trip_id` with its scheduled `stops` and the `live` status of the
+    /// stations it has yet to reach, as of `now`.
+    pub fn new(
+        trip_id: i64,
+        mut stops: Vec<Stop>,
+        live: &[Stop],
+        now: DateTime<FixedOffset>,
+    ) -> Self {
+        stops.sort_by_key(|s| s.stop_sequence);
+
+        let last = stops.len().saturating_sub(1);
+        let stops = stops
+            .into_iter()
+            .enumerate()
+            .map(|(index, scheduled)| {
+                let live = scheduled.live_in(live).cloned();
+
+                // Trains wait for their departure time, so lateness shows up
+                // on departure everywhere except the end of the line
+                let delay_minutes = live.as_ref().map(|live| {
+                    if index == last {
+                        (live.arrival - scheduled.arrival).num_minutes()
+                    } else {
+                        (live.departure - scheduled.departure).num_minutes()
+                    }
+                });
+
+                TripStop {
+                    scheduled,
+                    live,
+                    delay_minutes,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut trip = Trip {
+            trip_id,
+            delay_minutes: stops.iter().find_map(|s| s.delay_minutes),
+            stops,
+            position: TripPosition::NotStarted,
+        };
+        trip.position = trip.position_at(now);
+
+        trip
+    }
+
+    /// The same trip with `live` status in place of what it had, as of `now`.
+    pub fn with_live(&self, live: &[Stop], now: DateTime<FixedOffset>) -> Self {
+        let stops = self.stops.iter().map(|s| s.scheduled.clone()).collect();
+
+        Self::new(self.trip_id, stops, live, now)
+    }
+
+    /// Where the train is at `now`.
+    pub fn position_at(&self, now: DateTime<FixedOffset>) -> TripPosition {
+        for (index, stop) in self.stops.iter().enumerate() {
+            if now < stop.arrival() {
+                let previous = match index.checked_sub(1) {
+                    Some(previous) => &self.stops[previous],
+                    None => return TripPosition::NotStarted,
+                };
+
+                let travelled = (now - previous.departure()).num_seconds() as f64;
+                let total = (stop.arrival() - previous.departure()).num_seconds() as f64;
+
+                return TripPosition::Between {
+                    stop_index: index - 1,
+                    progress: if total > 0.0 {
+                        (travelled / total).clamp(0.0, 1.0)
+                    } else {
+                        0.0
+                    },
+                };
+            }
+
+            if now < stop.departure() {
+                return TripPosition::AtStop { stop_index: index };
+            }
+        }
+
+        TripPosition::Finished
+    }
+
+    /// Minutes the train takes from `Trip::stops[stop_index]` to the next
+    /// stop, live if known.
+    pub fn segment_minutes(&self, stop_index: usize) -> Option<i64> {
+        let from = self.stops.get(stop_index)?;
+        let to = self.stops.get(stop_index + 1)?;
+
+        Some((to.arrival() - from.departure()).num_minutes())
+    }
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
@@ -40,7 +576,107 @@ pub struct Stop {
     pub station_id: i64,
     pub trip_id: i64,
     pub station_name: String,
+    /// In the agency's time zone, with the UTC offset it has at that time
     pub arrival: DateTime<FixedOffset>,
     pub departure: DateTime<FixedOffset>,
     pub service_id: String,
+    pub stop_sequence: i64,
+    #[serde(default)]
+    pub pickup_type: Boarding,
+    #[serde(default)]
+    pub drop_off_type: Boarding,
+    /// Where to board, from the stop the schedule uses unless the live status
+    /// says the train is using another one
+    #[serde(default)]
+    pub platform: Option<String>,
+    /// `direction_id` of the trip in trips.txt
+    #[serde(default)]
+    pub direction_id: i64,
+    /// Where the trip is going
+    #[serde(default)]
+    pub headsign: String,
+}
+
+impl Stop {
+    /// Passengers can only get off here
+    pub fn drop_off_only(&self) -> bool {
+        self.pickup_type == Boarding::None && self.drop_off_type != Boarding::None
+    }
+
+    /// Passengers can only get on here
+    pub fn pickup_only(&self) -> bool {
+        self.drop_off_type == Boarding::None && self.pickup_type != Boarding::None
+    }
+
+    /// Whether `other` is on the same trip, run for the same service.
+    pub fn same_trip(&self, other: &Stop) -> bool {
+        self.trip_id == other.trip_id && self.service_id == other.service_id
+    }
+
+    /// Whether `other` is the same trip calling at the same station, like a
+    /// scheduled stop and its live status.
+    pub fn same_call(&self, other: &Stop) -> bool {
+        self.same_trip(other) && self.station_id == other.station_id
+    }
+
+    /// The live status of this scheduled stop in `live`, if it has any.
+    pub fn live_in<'a>(&self, live: &'a [Stop]) -> Option<&'a Stop> {
+        live.iter().find(|l| self.same_call(l))
+    }
+}
+
+/// Whether passengers can get on or off at a stop, from the `pickup_type` and
+/// `drop_off_type` of stop_times.txt.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Boarding {
+    #[default]
+    Regular,
+    None,
+    /// Only by phoning the agency ahead
+    PhoneAgency,
+    /// Only by asking the conductor
+    CoordinateWithDriver,
+}
+
+/// What a station's departures board shows, pushed to kiosks as it changes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DeparturesBoard {
+    pub station: Station,
+    /// When the board was put together
+    pub updated_at: DateTime<FixedOffset>,
+    /// Trains yet to leave, soonest scheduled departure first
+    pub departures: Vec<Departure>,
+    /// Short messages about delays and changes, for the ticker
+    pub alerts: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Departure {
+    pub scheduled: Stop,
+    pub live: Option<Stop>,
+}
+
+impl Departure {
+    /// When the train is expected to leave, live if known
+    pub fn departure(&self) -> DateTime<FixedOffset> {
+        self.live.as_ref().unwrap_or(&self.scheduled).departure
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Timetable {
+    pub direction_id: i64,
+    pub date: NaiveDate,
+    /// Stations in the order trips in this direction visit them
+    pub stations: Vec<Station>,
+    pub trips: Vec<TimetableTrip>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TimetableTrip {
+    pub trip_id: i64,
+    /// One entry per station in `Timetable::stations`, `None` where the trip
+    /// does not stop
+    pub stops: Vec<Option<Stop>>,
 }
@@ -0,0 +1,136 @@
+//! Departures boards pushed by the backend as server-sent events, for kiosks
+//! left running unattended.
+
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+    time::Duration,
+};
+
+use gloo::{events::EventListener, timers::callback::Interval};
+use log::error;
+use train_schedules_common::DeparturesBoard;
+use wasm_bindgen::JsCast;
+use web_sys::{EventSource, MessageEvent};
+use yew::{use_state, use_state_eq, UseStateHandle};
+
+use crate::fetch::with_now;
+
+/// How often to check the connection is still alive
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(15);
+
+/// The backend sends a board every 30 seconds, so going this long without
+/// one means the connection is dead even if the browser hasn't noticed
+const STALE_AFTER_MILLIS: f64 = 90_000.0;
+
+/// The departures board for `station_id`, kept up to date by the backend.
+/// Reconnects by itself after network errors, however long they last.
+pub fn board_events(host: &str, station_id: i64) -> BoardEvents {
+    let url = with_now(&format!("{host}/api/stations/{station_id}/board/events"));
+
+    let board = use_state_eq(|| None);
+    let connected = use_state_eq(|| false);
+
+    let _watchdog = {
+        let board = board.clone();
+        let connected = connected.clone();
+
+        use_state(move || {
+            let last_message = Rc::new(Cell::new(js_sys::Date::now()));
+            let connection = RefCell::new(connect(&url, &board, &connected, &last_message));
+
+            Interval::new(WATCHDOG_INTERVAL.as_millis() as u32, move || {
+                let closed = connection
+                    .borrow()
+                    .as_ref()
+                    .map_or(true, |c| c.source.ready_state() == EventSource::CLOSED);
+                let stale = js_sys::Date::now() - last_message.get() > STALE_AFTER_MILLIS;
+
+                if closed || stale {
+                    connected.set(false);
+
+                    // Close the old connection before opening a new one
+                    connection.borrow_mut().take();
+                    last_message.set(js_sys::Date::now());
+                    *connection.borrow_mut() = connect(&url, &board, &connected, &last_message);
+                }
+            })
+        })
+    };
+
+    BoardEvents {
+        board,
+        connected,
+        _watchdog,
+    }
+}
+
+pub struct BoardEvents {
+    /// The latest board, `None` until the first one arrives
+    pub board: UseStateHandle<Option<DeparturesBoard>>,
+    /// Whether the latest board is current. It's kept on screen while
+    /// reconnecting
+    pub connected: UseStateHandle<bool>,
+    _watchdog: UseStateHandle<Interval>,
+}
+
+struct Connection {
+    source: EventSource,
+    _listeners: [EventListener; 2],
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.source.close();
+    }
+}
+
+fn connect(
+    url: &str,
+    board: &UseStateHandle<Option<DeparturesBoard>>,
+    connected: &UseStateHandle<bool>,
+    last_message: &Rc<Cell<f64>>,
+) -> Option<Connection> {
+    let source = match EventSource::new(url) {
+        Ok(source) => source,
+        Err(e) => {
+            error!("failed to open board events: {:?}", e);
+            return None;
+        }
+    };
+
+    let on_board = {
+        let board = board.clone();
+        let connected = connected.clone();
+        let last_message = last_message.clone();
+
+        EventListener::new(&source, "board", move |event| {
+            last_message.set(js_sys::Date::now());
+
+            let data = event
+                .dyn_ref::<MessageEvent>()
+                .and_then(|event| event.data().as_string())
+                .unwrap_or_default();
+
+            match serde_json::from_str(&data) {
+                Ok(new_board) => {
+                    board.set(Some(new_board));
+                    connected.set(true);
+                }
+                Err(e) => error!("invalid board event: {}", e),
+            }
+        })
+    };
+
+    // The browser retries by itself after most errors
+    let on_error = {
+        let connected = connected.clone();
+
+        EventListener::new(&source, "error", move |_| connected.set(false))
+    };
+
+    Some(Connection {
+        source,
+        _listeners: [on_board, on_error],
+    })
+}
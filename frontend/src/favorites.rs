@@ -0,0 +1,118 @@
+use chrono::{DateTime, FixedOffset, Timelike};
+use gloo::storage::{LocalStorage, Storage};
+use log::error;
+use serde::{Deserialize, Serialize};
+use yew::{use_state_eq, UseStateHandle};
+
+const STORAGE_KEY: &str = "favorites";
+
+/// Stations and commutes the user saved, persisted in the browser's
+/// localStorage.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Default, Debug)]
+pub struct Favorites {
+    #[serde(default)]
+    pub stations: Vec<i64>,
+
+    #[serde(default)]
+    pub commutes: Vec<Commute>,
+
+    /// Show commutes in their morning direction before noon and their
+    /// evening direction after
+    #[serde(default)]
+    pub auto_direction: bool,
+}
+
+/// A saved pair of stations. Mornings go from `home` to `work`, evenings go
+/// back.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Commute {
+    pub home: i64,
+    pub work: i64,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Direction {
+    Morning,
+    Evening,
+}
+
+impl Favorites {
+    pub fn has_station(&self, station_id: i64) -> bool {
+        self.stations.contains(&station_id)
+    }
+
+    pub fn toggle_station(&mut self, station_id: i64) {
+        if self.has_station(station_id) {
+            self.stations.retain(|id| *id != station_id);
+        } else {
+            self.stations.push(station_id);
+        }
+    }
+
+    /// Whether a commute between the two stations is saved, in either
+    /// direction.
+    pub fn has_commute(&self, a: i64, b: i64) -> bool {
+        self.commutes.iter().any(|c| c.connects(a, b))
+    }
+
+    pub fn toggle_commute(&mut self, home: i64, work: i64) {
+        if self.has_commute(home, work) {
+            self.commutes.retain(|c| !c.connects(home, work));
+        } else {
+            self.commutes.push(Commute { home, work });
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stations.is_empty() && self.commutes.is_empty()
+    }
+}
+
+impl Commute {
+    fn connects(&self, a: i64, b: i64) -> bool {
+        (self.home == a && self.work == b) || (self.home == b && self.work == a)
+    }
+
+    /// Start and end station for travelling in `direction`.
+    pub fn stations(&self, direction: Direction) -> (i64, i64) {
+        match direction {
+            Direction::Morning => (self.home, self.work),
+            Direction::Evening => (self.work, self.home),
+        }
+    }
+}
+
+impl Direction {
+    pub fn for_time(time: DateTime<FixedOffset>) -> Self {
+        if time.hour() < 12 {
+            Self::Morning
+        } else {
+            Self::Evening
+        }
+    }
+
+    pub fn flip(self) -> Self {
+        match self {
+            Self::Morning => Self::Evening,
+            Self::Evening => Self::Morning,
+        }
+    }
+}
+
+/// Load the saved favorites into component state.
+pub fn load() -> UseStateHandle<Favorites> {
+    use_state_eq(|| LocalStorage::get(STORAGE_KEY).unwrap_or_default())
+}
+
+/// Change the favorites held in `favorites` and write them back to
+/// localStorage.
+pub fn update(favorites: &UseStateHandle<Favorites>, change: impl FnOnce(&mut Favorites)) {
+    let mut updated = (**favorites).clone();
+    change(&mut updated);
+
+    if let Err(e) = LocalStorage::set(STORAGE_KEY, &updated) {
+        error!("failed to save favorites: {}", e);
+    }
+
+    favorites.set(updated);
+}
@@ -4,6 +4,8 @@ use serde::de::DeserializeOwned;
 use wasm_bindgen_futures::spawn_local;
 use yew::{use_state, UseStateHandle};
 
+use crate::{offline, time};
+
 pub fn fetch_repeating_interval<T>(
     url: String,
     container: UseStateHandle<T>,
@@ -34,22 +36,90 @@ where
 {
     spawn_local(async move {
         match fetch_inner(&url).await {
-            Ok(value) => container.set(value),
+            Ok(value) => {
+                offline::set_offline(false);
+                container.set(value);
+            }
             Err(e) => {
                 error!("failed to fetch: {}", e);
+
+                // The backend answered, it just didn't like the request
+                if !is_network_error(&e) {
+                    offline::set_offline(false);
+                    return;
+                }
+
+                if let Some(value) = offline::respond(&url) {
+                    offline::set_offline(true);
+                    container.set(value);
+                }
             }
         }
     });
 }
 
-async fn fetch_inner<T>(url: &str) -> anyhow::Result<T>
+/// Whether `e` means the backend couldn't be reached at all, rather than it
+/// answering with an error or something that doesn't parse.
+fn is_network_error(e: &anyhow::Error) -> bool {
+    match e.downcast_ref::<reqwest::Error>() {
+        Some(e) => !e.is_status(),
+        None => false,
+    }
+}
+
+pub async fn fetch_inner<T>(url: &str) -> anyhow::Result<T>
 where
     T: DeserializeOwned,
 {
-    let response = reqwest::get(url).await?.error_for_status()?;
+    let url = with_now(url);
+    let response = reqwest::Client::new()
+        .get(&url)
+        .header("traceparent", traceparent())
+        .send()
+        .await?
+        .error_for_status()?;
 
     let body = response.text().await?;
     let list = serde_json::from_str(&body)?;
 
     Ok(list)
 }
+
+/// `url` with the time the clock was moved to, if it was, so the backend
+/// answers as of the same time the UI shows.
+pub fn with_now(url: &str) -> String {
+    match time::overridden_now() {
+        Some(now) => {
+            let separator = if url.contains('?') { '&' } else { '?' };
+            let now = String::from(js_sys::encode_uri_component(&now.to_rfc3339()));
+
+            format!("{url}{separator}now={now}")
+        }
+        None => url.to_owned(),
+    }
+}
+
+/// A W3C `traceparent` header value starting a new trace, so the backend's
+/// spans for this request can be found by its trace id. It isn't marked as
+/// sampled, leaving the backend's sample ratio to decide.
+pub fn traceparent() -> String {
+    format!("00-{}-{}-00", random_hex(16), random_hex(8))
+}
+
+fn random_hex(bytes: usize) -> String {
+    let mut hex = String::with_capacity(bytes * 2);
+
+    loop {
+        hex.clear();
+
+        for _ in 0..bytes {
+            let byte = (js_sys::Math::random() * 256.0) as u8;
+            hex.push_str(&format!("{:02x}", byte));
+        }
+
+        // All zero ids are invalid
+        if hex.bytes().any(|b| b != b'0') {
+            return hex;
+        }
+    }
+}
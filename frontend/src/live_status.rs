@@ -6,28 +6,39 @@ use yew::{use_state_eq, UseStateHandle};
 
 use crate::fetch::fetch_repeating_interval;
 
-pub fn live_status(host: &str) -> LiveStatus {
-    let stops = use_state_eq(Vec::new);
+/// Live status of the trains calling at `station_id`, kept up to date.
+pub fn station_live_status(host: &str, station_id: i64) -> LiveStatus {
+    live_status(format!("{host}/api/stations/{station_id}/live"))
+}
+
+/// Live status of `trip_id` at the stations it has yet to reach, kept up to
+/// date.
+pub fn trip_live_status(host: &str, trip_id: i64) -> LiveStatus {
+    live_status(format!("{host}/api/trip/{trip_id}/live"))
+}
 
-    let _interval = fetch_repeating_interval(
-        format!("{host}/api/stations/live"),
-        stops.clone(),
-        Duration::from_secs(60),
-    );
+fn live_status(url: String) -> LiveStatus {
+    let stops = use_state_eq(|| None);
+
+    let _interval = fetch_repeating_interval(url, stops.clone(), Duration::from_secs(60));
 
     LiveStatus { stops, _interval }
 }
 
 pub struct LiveStatus {
-    stops: UseStateHandle<Vec<Stop>>,
+    /// None until the first update arrives
+    stops: UseStateHandle<Option<Vec<Stop>>>,
     _interval: UseStateHandle<Interval>,
 }
 
 impl LiveStatus {
-    pub fn get(&self, station_id: i64, trip_id: i64) -> Option<Stop> {
-        self.stops
-            .iter()
-            .find(|s| s.trip_id == trip_id && s.station_id == station_id)
-            .cloned()
+    /// The live status of the scheduled `stop`, if its trip is running.
+    pub fn get(&self, stop: &Stop) -> Option<Stop> {
+        stop.live_in(self.stops.as_deref()?).cloned()
+    }
+
+    /// Every live stop, once the first update has arrived.
+    pub fn stops(&self) -> Option<&[Stop]> {
+        self.stops.as_deref()
     }
 }
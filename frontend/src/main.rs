@@ -3,9 +3,13 @@
 use crate::context::Context;
 use log::Level;
 
+mod board_events;
 mod context;
+mod favorites;
 mod fetch;
 mod live_status;
+mod offline;
+mod push;
 mod time;
 mod timer;
 mod views;
@@ -23,8 +27,26 @@ fn main() {
         location.host().unwrap()
     );
 
-    yew::start_app_with_props_in_element::<views::router::Main>(
-        document.query_selector("#app-container").unwrap().unwrap(),
-        Context { host },
-    );
+    time::init_override(&location.search().unwrap_or_default());
+
+    register_service_worker(&window);
+    offline::refresh_bundle(&host);
+
+    // Replace the page the backend rendered, if there is one. Yew 0.19 can't
+    // hydrate it, so it's rendered again from scratch
+    let container = document.query_selector("#app-container").unwrap().unwrap();
+    container.set_inner_html("");
+
+    yew::start_app_with_props_in_element::<views::router::Main>(container, Context { host });
+}
+
+fn register_service_worker(window: &web_sys::Window) {
+    let navigator = window.navigator();
+
+    // Service workers are only available in secure contexts
+    if !js_sys::Reflect::has(&navigator, &"serviceWorker".into()).unwrap_or(false) {
+        return;
+    }
+
+    let _ = navigator.service_worker().register("/sw.js");
 }
@@ -0,0 +1,94 @@
+//! Offline fallback: keep a copy of the static schedule in localStorage and
+//! answer API requests from it when the network is unavailable.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use gloo::storage::{LocalStorage, Storage};
+use log::error;
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use train_schedules_common::{ScheduleBundle, Trip};
+use wasm_bindgen_futures::spawn_local;
+
+use crate::{fetch::fetch_inner, time};
+
+const STORAGE_KEY: &str = "schedule-bundle";
+const BUNDLE_DAYS: u32 = 7;
+
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Whether the most recent API request was answered from the offline copy
+/// of the schedule.
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+/// Download a fresh copy of the schedule in the background.
+pub fn refresh_bundle(host: &str) {
+    let url = format!("{host}/api/schedule-bundle?days={BUNDLE_DAYS}");
+
+    spawn_local(async move {
+        match fetch_inner::<ScheduleBundle>(&url).await {
+            Ok(bundle) => {
+                if let Err(e) = LocalStorage::set(STORAGE_KEY, &bundle) {
+                    error!("failed to store schedule bundle: {}", e);
+                }
+            }
+            Err(e) => error!("failed to fetch schedule bundle: {}", e),
+        }
+    });
+}
+
+/// Compute the response the backend would have given for `url` from the
+/// stored schedule. Live data is never available offline.
+pub fn respond<T>(url: &str) -> Option<T>
+where
+    T: DeserializeOwned,
+{
+    let bundle: ScheduleBundle = LocalStorage::get(STORAGE_KEY).ok()?;
+
+    let path_start = url.find("/api/")?;
+    let request = &url[path_start..];
+    let (path, query) = request.split_once('?').unwrap_or((request, ""));
+
+    let param = |name: &str| {
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .and_then(|(_, value)| value.parse::<i64>().ok())
+    };
+
+    let now = time::now();
+
+    let value = match path {
+        "/api/stations" => serde_json::to_value(&bundle.stations).ok()?,
+        // There's no live status offline
+        path if path.ends_with("/live") => json!([]),
+        "/api/upcoming-trips" => {
+            let start = param("start")?;
+
+            match param("end") {
+                Some(end) => serde_json::to_value(bundle.twostop_list(start, end, now)?).ok()?,
+                None => serde_json::to_value(bundle.upcoming(start, now)).ok()?,
+            }
+        }
+        "/api/trip" => {
+            let trip_id = param("id")?;
+            let stops = bundle
+                .stops_on(bundle.today(now))
+                .into_iter()
+                .filter(|s| s.trip_id == trip_id)
+                .collect();
+
+            serde_json::to_value(Trip::new(trip_id, stops, &[], now)).ok()?
+        }
+        _ => return None,
+    };
+
+    serde_json::from_value(value).ok()
+}
@@ -0,0 +1,80 @@
+//! Browser side of Web Push: subscribing this device to notifications sent by
+//! the backend.
+
+use anyhow::anyhow;
+use train_schedules_common::{PushSubscription, Reminder, ReminderRequest};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{PushManager, PushSubscriptionOptionsInit, ServiceWorkerRegistration};
+
+use crate::fetch::{fetch_inner, traceparent};
+
+/// Subscribe to push messages from the backend, reusing the existing
+/// subscription if there is one.
+pub async fn subscribe(host: &str) -> anyhow::Result<PushSubscription> {
+    let window = web_sys::window().ok_or_else(|| anyhow!("no window"))?;
+
+    let ready = window
+        .navigator()
+        .service_worker()
+        .ready()
+        .map_err(|e| anyhow!("service worker unavailable: {:?}", e))?;
+
+    let registration: ServiceWorkerRegistration = JsFuture::from(ready)
+        .await
+        .map_err(|e| anyhow!("service worker unavailable: {:?}", e))?
+        .unchecked_into();
+
+    let push_manager: PushManager = registration
+        .push_manager()
+        .map_err(|e| anyhow!("push messages are not supported: {:?}", e))?;
+
+    let public_key: String = fetch_inner(&format!("{host}/api/push/public-key")).await?;
+    let public_key = base64::decode_config(public_key, base64::URL_SAFE_NO_PAD)?;
+    let public_key = JsValue::from(js_sys::Uint8Array::from(&public_key[..]));
+
+    let mut options = PushSubscriptionOptionsInit::new();
+    options
+        .user_visible_only(true)
+        .application_server_key(Some(&public_key));
+
+    let subscribe = push_manager
+        .subscribe_with_options(&options)
+        .map_err(|e| anyhow!("failed to subscribe: {:?}", e))?;
+
+    let subscription = JsFuture::from(subscribe)
+        .await
+        .map_err(|_| anyhow!("notifications were not allowed"))?;
+
+    let json = js_sys::JSON::stringify(&subscription)
+        .map_err(|e| anyhow!("failed to serialize subscription: {:?}", e))?;
+
+    Ok(serde_json::from_str(&String::from(json))?)
+}
+
+/// Ask the backend to notify this device `minutes_before` the trip leaves the
+/// station.
+pub async fn remind(
+    host: &str,
+    trip_id: i64,
+    station_id: i64,
+    minutes_before: i64,
+) -> anyhow::Result<Reminder> {
+    let request = ReminderRequest {
+        subscription: subscribe(host).await?,
+        trip_id,
+        station_id,
+        minutes_before,
+    };
+
+    let response = reqwest::Client::new()
+        .post(format!("{host}/api/reminders"))
+        .header("Content-Type", "application/json")
+        .header("traceparent", traceparent())
+        .body(serde_json::to_string(&request)?)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(serde_json::from_str(&response.text().await?)?)
+}
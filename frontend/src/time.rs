@@ -1,6 +1,63 @@
-use chrono::prelude::*;
+use std::cell::Cell;
+
+use chrono::{prelude::*, Duration};
+use log::{error, info};
+
+thread_local! {
+    /// How far a `?now=` parameter moved the clock, in milliseconds
+    static OVERRIDE_OFFSET: Cell<Option<i64>> = Cell::new(None);
+}
+
+/// Move the clock to the time in the page's `?now=` parameter, if there is
+/// one, to see the app as it would look then. Time keeps passing from there.
+/// Takes RFC 3339 times, or `YYYY-MM-DDTHH:MM` in the browser's time zone.
+pub fn init_override(search: &str) {
+    let value = search
+        .trim_start_matches('?')
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == "now")
+        .map(|(_, value)| value);
+
+    let value = match value {
+        Some(value) => js_sys::decode_uri_component(value)
+            .ok()
+            .and_then(|value| value.as_string())
+            .unwrap_or_else(|| value.to_owned()),
+        None => return,
+    };
+
+    match parse_override(&value) {
+        Some(at) => {
+            info!("pretending it is {}", at);
+
+            let offset = (at - system_now()).num_milliseconds();
+            OVERRIDE_OFFSET.with(|o| o.set(Some(offset)));
+        }
+        None => error!("ignoring invalid now parameter {:?}", value),
+    }
+}
+
+fn parse_override(value: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(value).ok().or_else(|| {
+        let naive = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M").ok()?;
+
+        local_offset().from_local_datetime(&naive).single()
+    })
+}
+
+/// The current time, if the clock was moved with `?now=`.
+pub fn overridden_now() -> Option<DateTime<FixedOffset>> {
+    OVERRIDE_OFFSET.with(Cell::get).map(|_| now())
+}
 
 pub fn now() -> DateTime<FixedOffset> {
+    let offset = OVERRIDE_OFFSET.with(Cell::get).unwrap_or(0);
+
+    system_now() + Duration::milliseconds(offset)
+}
+
+fn system_now() -> DateTime<FixedOffset> {
     let date = js_sys::Date::new_0();
     let timestamp = date.get_time() as i64;
 
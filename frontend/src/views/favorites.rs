@@ -0,0 +1,171 @@
+use train_schedules_common::Station;
+use yew::prelude::*;
+
+use crate::{
+    context::host,
+    favorites::{self, Commute, Direction},
+    time,
+    views::{station_list::StationList, twostop_list::UpcomingTwostops},
+};
+
+/// Saved commutes and stations, followed by the full station list.
+#[function_component(Dashboard)]
+pub fn dashboard() -> Html {
+    let favorites = favorites::load();
+    let stations = use_state_eq::<Vec<Station>, _>(Vec::new);
+
+    let host = host();
+    crate::fetch::fetch(format!("{host}/api/stations"), stations.clone());
+
+    let saved = if favorites.is_empty() {
+        html! {}
+    } else {
+        let on_auto_direction = {
+            let favorites = favorites.clone();
+
+            Callback::from(move |_: Event| {
+                favorites::update(&favorites, |f| f.auto_direction = !f.auto_direction)
+            })
+        };
+
+        html! {
+            <div class="Dashboard">
+                <h1>{ "Favorites" }</h1>
+                { for favorites.commutes.iter().map(|commute| html! {
+                    <CommuteCard
+                        commute={commute.clone()}
+                        auto_direction={favorites.auto_direction}
+                        stations={(*stations).clone()}
+                    />
+                }) }
+                <label class="Dashboard-autoDirection">
+                    <input type="checkbox" checked={favorites.auto_direction} onchange={on_auto_direction} />
+                    { " Choose commute direction by time of day" }
+                </label>
+                <ul>
+                { for favorites.stations.iter().map(|station_id| html! {
+                    <li>
+                        <a href={format!("/c/station/{}", station_id)}>
+                            { station_name(&stations, *station_id) }
+                        </a>
+                    </li>
+                }) }
+                </ul>
+            </div>
+        }
+    };
+
+    html! {
+        <>
+            { saved }
+            <StationList start_station_id={None} />
+        </>
+    }
+}
+
+#[derive(Properties, PartialEq, Clone)]
+pub struct CommuteCardProps {
+    pub commute: Commute,
+    pub auto_direction: bool,
+    pub stations: Vec<Station>,
+}
+
+#[function_component(CommuteCard)]
+pub fn commute_card(props: &CommuteCardProps) -> Html {
+    // Set once the direction is picked by hand, overriding the default
+    let chosen = use_state_eq(|| None::<Direction>);
+
+    let direction = chosen.unwrap_or_else(|| {
+        if props.auto_direction {
+            Direction::for_time(time::now())
+        } else {
+            Direction::Morning
+        }
+    });
+
+    let (start, end) = props.commute.stations(direction);
+
+    let onclick = {
+        let chosen = chosen.clone();
+        Callback::from(move |_: MouseEvent| chosen.set(Some(direction.flip())))
+    };
+
+    let label = match direction {
+        Direction::Morning => "Morning",
+        Direction::Evening => "Evening",
+    };
+
+    html! {
+        <div class="CommuteCard">
+            <h2>
+                <a href={format!("/c/station/{}/{}", start, end)}>
+                    { station_name(&props.stations, start) }
+                    { " → " }
+                    { station_name(&props.stations, end) }
+                </a>
+                { " " }
+                <button class="CommuteCard-direction" {onclick}>{ label }</button>
+            </h2>
+            <UpcomingTwostops key={format!("{}-{}", start, end)} {start} {end} count={2} />
+        </div>
+    }
+}
+
+#[derive(Properties, PartialEq, Clone)]
+pub struct FavoriteStationButtonProps {
+    pub station_id: i64,
+}
+
+#[function_component(FavoriteStationButton)]
+pub fn favorite_station_button(props: &FavoriteStationButtonProps) -> Html {
+    let favorites = favorites::load();
+    let station_id = props.station_id;
+    let saved = favorites.has_station(station_id);
+
+    let onclick = {
+        let favorites = favorites.clone();
+        Callback::from(move |_: MouseEvent| {
+            favorites::update(&favorites, |f| f.toggle_station(station_id))
+        })
+    };
+
+    html! {
+        <button class="FavoriteButton" {onclick} title="Save station">
+            { if saved { "★" } else { "☆" } }
+        </button>
+    }
+}
+
+#[derive(Properties, PartialEq, Clone)]
+pub struct FavoriteCommuteButtonProps {
+    pub start: i64,
+    pub end: i64,
+}
+
+#[function_component(FavoriteCommuteButton)]
+pub fn favorite_commute_button(props: &FavoriteCommuteButtonProps) -> Html {
+    let favorites = favorites::load();
+    let (start, end) = (props.start, props.end);
+    let saved = favorites.has_commute(start, end);
+
+    let onclick = {
+        let favorites = favorites.clone();
+        Callback::from(move |_: MouseEvent| {
+            favorites::update(&favorites, |f| f.toggle_commute(start, end))
+        })
+    };
+
+    html! {
+        <button class="FavoriteButton" {onclick} title="Save commute">
+            { if saved { "★" } else { "☆" } }
+        </button>
+    }
+}
+
+fn station_name(stations: &[Station], station_id: i64) -> String {
+    stations
+        .iter()
+        .find(|s| s.station_id == station_id)
+        .map(|s| s.name.clone())
+        .unwrap_or_default()
+}
@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+use train_schedules_common::{train_type, Departure};
+use yew::prelude::*;
+
+use crate::{
+    board_events::board_events,
+    context::host,
+    time::{self, local},
+    views::{platform_display::PlatformDisplay, time_display::TimeDisplay, twostop::TripId},
+};
+
+/// Rows that fit on screen before the board starts scrolling
+const VISIBLE_ROWS: usize = 8;
+
+/// Seconds each row takes to scroll past
+const SECONDS_PER_ROW: usize = 4;
+
+#[derive(Properties, PartialEq, Clone)]
+pub struct KioskProps {
+    pub station_id: i64,
+}
+
+/// A full screen departures board for wall displays.
+#[function_component(Kiosk)]
+pub fn kiosk(props: &KioskProps) -> Html {
+    // Keeps the clock and the countdowns moving between boards
+    let _refresher = crate::timer::refresh_periodically(Duration::from_secs(1));
+
+    let events = board_events(&host(), props.station_id);
+    let now = time::now();
+
+    let board = match &*events.board {
+        Some(board) => board,
+        None => {
+            return html! {
+                <div class="Kiosk">
+                    <div class="Kiosk-status">{ "Connecting…" }</div>
+                </div>
+            }
+        }
+    };
+
+    let departures = board
+        .departures
+        .iter()
+        .filter(|d| d.departure() > now)
+        .collect::<Vec<_>>();
+
+    // Show the rows twice so the scroll can loop without a jump
+    let scrolling = departures.len() > VISIBLE_ROWS;
+    let rows = if scrolling {
+        departures.iter().chain(&departures).copied().collect()
+    } else {
+        departures.clone()
+    };
+    let style = format!(
+        "animation-duration: {}s",
+        departures.len() * SECONDS_PER_ROW
+    );
+
+    let status = if *events.connected {
+        html! {}
+    } else {
+        html! {
+            <div class="Kiosk-status">
+                { format!("Reconnecting… as of {}", local(board.updated_at).format("%l:%M %p")) }
+            </div>
+        }
+    };
+
+    html! {
+        <div class="Kiosk">
+            <header class="Kiosk-header">
+                <h1>{ &board.station.name }</h1>
+                <div class="Kiosk-clock">{ local(now).format("%l:%M:%S %p").to_string() }</div>
+            </header>
+            <div class="Kiosk-departures">
+                <div class={ classes!("Kiosk-rows", scrolling.then(|| "Kiosk-rows--scrolling")) } {style}>
+                    { for rows.into_iter().map(row) }
+                </div>
+            </div>
+            <div class="Kiosk-ticker">
+                <div class="Kiosk-tickerText">{ board.alerts.join(" · ") }</div>
+            </div>
+            { status }
+        </div>
+    }
+}
+
+fn row(departure: &Departure) -> Html {
+    let stop = &departure.scheduled;
+    let live = departure.live.as_ref().map(|s| s.departure);
+    let minutes = (departure.departure() - time::now()).num_minutes();
+
+    html! {
+        <div class="Kiosk-row">
+            <TripId id={ stop.trip_id } />
+            <div class="Kiosk-destination">
+                { &stop.headsign }
+                <span class="TrainType">{ train_type(stop.trip_id).unwrap_or_default() }</span>
+            </div>
+            <PlatformDisplay scheduled={ stop.clone() } live={ departure.live.clone() } />
+            <TimeDisplay scheduled={ stop.departure } {live} />
+            <div class="Kiosk-countdown">
+                { if minutes < 1 { String::from("Now") } else { format!("{} min.", minutes) } }
+            </div>
+        </div>
+    }
+}
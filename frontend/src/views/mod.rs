@@ -1,7 +1,14 @@
+pub mod favorites;
+pub mod kiosk;
+pub mod nearby_stations;
+pub mod offline_banner;
+pub mod platform_display;
+pub mod reminder;
 pub mod router;
 pub mod station_list;
 pub mod station_upcoming;
 pub mod time_display;
+pub mod timetable;
 pub mod trip_view;
 pub mod twostop;
 pub mod twostop_list;
@@ -0,0 +1,105 @@
+use train_schedules_common::NearbyStation;
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use web_sys::Position;
+use yew::prelude::*;
+
+use crate::{context::host, fetch::fetch_raw};
+
+#[function_component(NearbyStations)]
+pub fn nearby_stations() -> Html {
+    let stations = use_state_eq(Vec::<NearbyStation>::new);
+    let status = use_state_eq(|| None::<String>);
+
+    let onclick = {
+        let host = host();
+        let stations = stations.clone();
+        let status = status.clone();
+
+        Callback::from(move |_: MouseEvent| locate(host.clone(), stations.clone(), status.clone()))
+    };
+
+    html! {
+        <div class="NearbyStations">
+            <button {onclick}>{ "Use my location" }</button>
+            { for status.as_ref().map(|status| html! { <p>{ status }</p> }) }
+            <ul>
+            { for stations.iter().map(view_nearby_station) }
+            </ul>
+        </div>
+    }
+}
+
+fn view_nearby_station(nearby: &NearbyStation) -> Html {
+    let href = format!("/c/station/{}", nearby.station.station_id);
+
+    html! {
+        <li>
+            <a {href}>{ &nearby.station.name }</a>
+            <span class="NearbyStations-distance">{ format_distance(nearby.distance_meters) }</span>
+        </li>
+    }
+}
+
+fn format_distance(meters: f64) -> String {
+    if meters < 1_000.0 {
+        format!("{:.0} m", meters)
+    } else {
+        format!("{:.1} km", meters / 1_000.0)
+    }
+}
+
+/// Ask the browser for the current position and load the closest stations to
+/// it into `stations`.
+fn locate(
+    host: String,
+    stations: UseStateHandle<Vec<NearbyStation>>,
+    status: UseStateHandle<Option<String>>,
+) {
+    let geolocation = match web_sys::window().and_then(|w| w.navigator().geolocation().ok()) {
+        Some(geolocation) => geolocation,
+        None => {
+            status.set(Some(String::from(
+                "Location is not available in this browser",
+            )));
+            return;
+        }
+    };
+
+    status.set(Some(String::from("Finding your location...")));
+
+    let on_error = {
+        let status = status.clone();
+
+        Closure::once_into_js(move |_: JsValue| {
+            status.set(Some(String::from("Could not determine your location")));
+        })
+    };
+
+    let on_success = {
+        let status = status.clone();
+
+        Closure::once_into_js(move |position: Position| {
+            let coords = position.coords();
+            status.set(None);
+
+            fetch_raw(
+                format!(
+                    "{host}/api/stations/nearby?lat={}&lon={}&limit=3",
+                    coords.latitude(),
+                    coords.longitude()
+                ),
+                stations,
+            );
+        })
+    };
+
+    if geolocation
+        .get_current_position_with_error_callback(
+            on_success.unchecked_ref(),
+            Some(on_error.unchecked_ref()),
+        )
+        .is_err()
+    {
+        status.set(Some(String::from("Could not determine your location")));
+    }
+}
@@ -0,0 +1,22 @@
+use std::time::Duration;
+
+use yew::prelude::*;
+
+use crate::offline;
+
+/// Notice shown while API requests are being answered from the offline copy
+/// of the schedule.
+#[function_component(OfflineBanner)]
+pub fn offline_banner() -> Html {
+    let _refresher = crate::timer::refresh_periodically(Duration::from_secs(5));
+
+    if !offline::is_offline() {
+        return html! {};
+    }
+
+    html! {
+        <div class="OfflineBanner">
+            { "Offline: showing scheduled times only, live updates are unavailable" }
+        </div>
+    }
+}
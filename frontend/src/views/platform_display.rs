@@ -0,0 +1,34 @@
+use train_schedules_common::Stop;
+use yew::prelude::*;
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct Properties {
+    pub scheduled: Stop,
+    pub live: Option<Stop>,
+}
+
+/// The platform a train leaves from, flagged when the live status has moved
+/// it from the scheduled one.
+#[function_component(PlatformDisplay)]
+pub fn platform_display(props: &Properties) -> Html {
+    let scheduled = props.scheduled.platform.as_deref();
+    let live = props.live.as_ref().and_then(|s| s.platform.as_deref());
+
+    let platform = match live.or(scheduled) {
+        Some(platform) => platform,
+        None => return html! {},
+    };
+
+    let changed = live.is_some() && live != scheduled;
+
+    let title = match (changed, scheduled) {
+        (true, Some(scheduled)) => format!("Scheduled for platform {}", scheduled),
+        _ => String::new(),
+    };
+
+    html! {
+        <div class={ classes!("PlatformDisplay", changed.then(|| "PlatformDisplay--changed")) } { title }>
+            { format!("Platform {}", platform) }
+        </div>
+    }
+}
@@ -0,0 +1,63 @@
+use log::error;
+use wasm_bindgen_futures::spawn_local;
+use yew::prelude::*;
+
+use crate::{context::host, push};
+
+/// How long before departure the notification is sent
+const MINUTES_BEFORE: i64 = 10;
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct RemindButtonProps {
+    pub trip_id: i64,
+    pub station_id: i64,
+}
+
+#[derive(Clone, PartialEq)]
+enum Status {
+    Idle,
+    Pending,
+    Set,
+    Failed,
+}
+
+#[function_component(RemindButton)]
+pub fn remind_button(props: &RemindButtonProps) -> Html {
+    let status = use_state_eq(|| Status::Idle);
+
+    let onclick = {
+        let host = host();
+        let status = status.clone();
+        let trip_id = props.trip_id;
+        let station_id = props.station_id;
+
+        Callback::from(move |_: MouseEvent| {
+            let host = host.clone();
+            let status = status.clone();
+            status.set(Status::Pending);
+
+            spawn_local(async move {
+                match push::remind(&host, trip_id, station_id, MINUTES_BEFORE).await {
+                    Ok(_) => status.set(Status::Set),
+                    Err(e) => {
+                        error!("failed to set reminder: {}", e);
+                        status.set(Status::Failed);
+                    }
+                }
+            });
+        })
+    };
+
+    let label = match *status {
+        Status::Idle => format!("Remind me {MINUTES_BEFORE} min. before"),
+        Status::Pending => String::from("Setting reminder..."),
+        Status::Set => String::from("Reminder set"),
+        Status::Failed => String::from("Could not set reminder"),
+    };
+
+    let disabled = matches!(*status, Status::Pending | Status::Set);
+
+    html! {
+        <button class="RemindButton" {onclick} {disabled}>{ label }</button>
+    }
+}
@@ -14,6 +14,12 @@ pub enum Route {
     #[at("/c/trip/:trip_id")]
     Trip { trip_id: i64 },
 
+    #[at("/c/kiosk/:station_id")]
+    Kiosk { station_id: i64 },
+
+    #[at("/c/timetable/:direction")]
+    Timetable { direction: i64 },
+
     #[at("/c/")]
     StationListRoot,
 
@@ -33,6 +39,7 @@ pub fn main(props: &Context) -> Html {
 
     html! {
         <ContextProvider<Context> context={(*context).clone()} >
+            <offline_banner::OfflineBanner />
             <BrowserRouter>
                 <Switch<Route> render={Switch::render(switch)} />
             </BrowserRouter>
@@ -46,10 +53,14 @@ fn switch(route: &Route) -> Html {
             html! { <station_list::StationList start_station_id={*start} /> }
         }
         Route::StationListRoot => html! { <station_list::StationList start_station_id={None} /> },
-        Route::Index => html! { <station_list::StationList start_station_id={None} /> },
+        Route::Index => html! { <favorites::Dashboard /> },
         Route::Twostop { start, end } => {
             html! { <twostop_list::TwostopList start={*start} end={*end} /> }
         }
         Route::Trip { trip_id } => html! { <trip_view::TripView trip_id={*trip_id} /> },
+        Route::Kiosk { station_id } => html! { <kiosk::Kiosk station_id={*station_id} /> },
+        Route::Timetable { direction } => {
+            html! { <timetable::TimetableView direction={*direction} /> }
+        }
     }
 }
@@ -1,8 +1,15 @@
 use crate::context::host;
-use crate::views::station_upcoming::StationUpcoming;
+use crate::views::{
+    favorites::FavoriteStationButton, nearby_stations::NearbyStations,
+    station_upcoming::StationUpcoming,
+};
 use train_schedules_common::*;
+use web_sys::HtmlSelectElement;
 use yew::prelude::*;
 
+/// How many departures in each direction the station page can show
+const DEPARTURE_COUNTS: [usize; 3] = [3, 5, 10];
+
 #[derive(Properties, Clone, PartialEq, Default)]
 pub struct Properties {
     pub start_station_id: Option<i64>,
@@ -22,17 +29,42 @@ pub fn station_list(props: &Properties) -> Html {
             .map(|s| (s.name.clone(), start_station_id))
     });
 
+    let count = use_state_eq(|| DEPARTURE_COUNTS[0]);
+    let on_count_change = {
+        let count = count.clone();
+
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+
+            if let Ok(new_count) = select.value().parse() {
+                count.set(new_count);
+            }
+        })
+    };
+
     let start_station = match start_station {
         Some((name, station_id)) => html! {
             <>
-                <h1>{ name }</h1>
-                <h2>{"Next 3 departures"}</h2>
-                <StationUpcoming {station_id} count={3} />
+                <h1>{ name }{ " " }<FavoriteStationButton {station_id} /></h1>
+                <h2>
+                    {"Next "}
+                    <select onchange={on_count_change}>
+                        { for DEPARTURE_COUNTS.iter().map(|n| html! {
+                            <option value={n.to_string()} selected={*n == *count}>{ n }</option>
+                        }) }
+                    </select>
+                    {" departures"}
+                </h2>
+                <StationUpcoming {station_id} count={*count} />
                 <h2>{ "Filter by ending station" }</h2>
             </>
         },
         None => html! {
-            <h1>{ "Choose a station" }</h1>
+            <>
+                <h1>{ "Choose a station" }</h1>
+                <NearbyStations />
+                <a class="TimetableLink" href="/c/timetable/0">{ "Full timetable" }</a>
+            </>
         },
     };
 
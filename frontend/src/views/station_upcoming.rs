@@ -1,12 +1,12 @@
-use train_schedules_common::Stop;
+use train_schedules_common::{train_type, Stop, DIRECTIONS};
 use yew::prelude::*;
 
 use crate::{
     context::host,
     fetch::fetch,
-    live_status::live_status,
+    live_status::station_live_status,
     time,
-    views::{time_display::TimeDisplay, twostop::TripId},
+    views::{platform_display::PlatformDisplay, time_display::TimeDisplay, twostop::TripId},
 };
 
 #[derive(Properties, PartialEq, Clone)]
@@ -17,6 +17,7 @@ pub struct StationUpcomingProps {
 
 #[function_component(StationUpcoming)]
 pub fn departures(props: &StationUpcomingProps) -> Html {
+    let _refresher = crate::timer::refresh_periodically(std::time::Duration::from_secs(30));
     let host = host();
 
     let stops = use_state(Vec::<Stop>::new);
@@ -24,26 +25,39 @@ pub fn departures(props: &StationUpcomingProps) -> Html {
         format!("{host}/api/upcoming-trips?start={}", props.station_id),
         stops.clone(),
     );
-    let live = live_status(&host);
+    let live = station_live_status(&host, props.station_id);
 
     let now = time::now();
 
-    let stops = stops.iter().filter(|stop| {
-        let start_live = live.get(stop.station_id, stop.trip_id);
-        let time = start_live
-            .as_ref()
-            .map(|s| s.departure)
-            .unwrap_or(stop.departure);
+    let stops = stops
+        .iter()
+        .filter(|stop| {
+            let start_live = live.get(stop);
+            let time = start_live
+                .as_ref()
+                .map(|s| s.departure)
+                .unwrap_or(stop.departure);
 
-        time > now
-    });
+            time > now
+        })
+        .collect::<Vec<_>>();
 
     html! {
-        <>
-            {for stops.take(props.count).map(|s| html! {
-                <Upcoming stop={s.clone()} live={live.get(s.station_id, s.trip_id)} />
-            })}
-        </>
+        <div class="DeparturesBoard">
+            { for DIRECTIONS.iter().map(|(direction_id, label)| html! {
+                <div class="DeparturesBoard-column">
+                    <h3>{ label }</h3>
+                    { for stops
+                        .iter()
+                        .filter(|s| s.direction_id == *direction_id)
+                        .take(props.count)
+                        .map(|s| html! {
+                            <Upcoming stop={(*s).clone()} live={live.get(s)} />
+                        })
+                    }
+                </div>
+            }) }
+        </div>
     }
 }
 
@@ -68,7 +82,12 @@ pub fn upcoming(props: &UpcomingProps) -> Html {
     html! {
         <div class={ classes!("TripDisplay") }>
             <TripId id={ props.stop.trip_id } />
+            <div class="Destination">
+                { &props.stop.headsign }
+                <span class="TrainType">{ train_type(props.stop.trip_id).unwrap_or_default() }</span>
+            </div>
             <div class="MinsToDepart">{ format!("{} min.", time_to_departure) }</div>
+            <PlatformDisplay scheduled={ props.stop.clone() } live={ props.live.clone() } />
             <div class="DepartTime">{"Departing "}<TimeDisplay scheduled={ props.stop.departure } {live} /></div>
         </div>
     }
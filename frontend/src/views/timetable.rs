@@ -0,0 +1,118 @@
+use chrono::NaiveDate;
+use train_schedules_common::{Timetable, TimetableTrip};
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+use crate::{
+    context::host,
+    fetch::fetch_raw,
+    time::{self, local},
+    views::twostop::TripId,
+};
+
+#[derive(Properties, PartialEq, Clone)]
+pub struct TimetableProps {
+    pub direction: i64,
+}
+
+#[function_component(TimetableView)]
+pub fn timetable_view(props: &TimetableProps) -> Html {
+    let host = host();
+    let date = use_state_eq(|| time::now().date().naive_local());
+    let timetable = use_state_eq(|| None::<Timetable>);
+
+    {
+        let timetable = timetable.clone();
+
+        use_effect_with_deps(
+            move |(direction, date)| {
+                fetch_raw(
+                    format!("{host}/api/timetable?direction={direction}&date={date}"),
+                    timetable,
+                );
+                || ()
+            },
+            (props.direction, *date),
+        );
+    }
+
+    let onchange = {
+        let date = date.clone();
+
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+
+            if let Ok(new_date) = NaiveDate::parse_from_str(&input.value(), "%Y-%m-%d") {
+                date.set(new_date);
+            }
+        })
+    };
+
+    let onprint = Callback::from(|_: MouseEvent| {
+        if let Some(window) = web_sys::window() {
+            let _ = window.print();
+        }
+    });
+
+    let timetable = match &*timetable {
+        Some(timetable) => timetable,
+        None => return html! { <div class="Timetable"><h1>{ "Timetable" }</h1></div> },
+    };
+
+    let heading = match (timetable.stations.first(), timetable.stations.last()) {
+        (Some(first), Some(last)) => format!("{} to {}", first.name, last.name),
+        _ => String::from("No trains run on this day"),
+    };
+
+    html! {
+        <div class="Timetable">
+            <h1>{ heading }</h1>
+            <div class="Timetable-controls">
+                <input type="date" value={date.to_string()} {onchange} />
+                <a href={format!("/c/timetable/{}", 1 - props.direction)}>{ "Reverse direction" }</a>
+                <button onclick={onprint}>{ "Print" }</button>
+            </div>
+            <div class="Timetable-scroll">
+                <table>
+                    <thead>
+                        <tr>
+                            <th class="Timetable-station">{ "Station" }</th>
+                            { for timetable.trips.iter().map(|trip| html! {
+                                <th><TripId id={ trip.trip_id } /></th>
+                            }) }
+                        </tr>
+                    </thead>
+                    <tbody>
+                        { for timetable.stations.iter().enumerate().map(|(i, station)| html! {
+                            <tr>
+                                <th class="Timetable-station">
+                                    <a href={format!("/c/station/{}", station.station_id)}>{ &station.name }</a>
+                                </th>
+                                { for timetable.trips.iter().map(|trip| view_cell(trip, i)) }
+                            </tr>
+                        }) }
+                    </tbody>
+                </table>
+            </div>
+        </div>
+    }
+}
+
+fn view_cell(trip: &TimetableTrip, station_index: usize) -> Html {
+    if let Some(Some(stop)) = trip.stops.get(station_index) {
+        return html! {
+            <td>{ local(stop.departure).format("%l:%M %p").to_string() }</td>
+        };
+    }
+
+    let first = trip.stops.iter().position(Option::is_some);
+    let last = trip.stops.iter().rposition(Option::is_some);
+
+    match (first, last) {
+        // The train passes through this station without stopping
+        (Some(first), Some(last)) if first < station_index && station_index < last => html! {
+            <td class="Timetable-skip">{ "—" }</td>
+        },
+        _ => html! { <td></td> },
+    }
+}
@@ -1,11 +1,13 @@
+use std::time::Duration;
+
 use crate::{
-    live_status::live_status,
+    live_status::trip_live_status,
     time::now,
     views::{time_display::TimeDisplay, twostop::TripId},
 };
-use chrono::{DateTime, Duration, FixedOffset};
-use train_schedules_common::Trip;
-use yew::{function_component, html, use_state_eq, Properties};
+use chrono::{DateTime, FixedOffset};
+use train_schedules_common::{Stop, Trip, TripPosition, TripStop};
+use yew::{classes, function_component, html, use_state_eq, Html, Properties};
 
 use crate::context::host;
 
@@ -16,36 +18,53 @@ pub struct Props {
 
 #[function_component(TripView)]
 pub fn train_view(props: &Props) -> Html {
-    let _refresher = crate::timer::refresh_periodically(std::time::Duration::from_secs(30));
+    let _refresher = crate::timer::refresh_periodically(Duration::from_secs(30));
     let trip = use_state_eq(|| Trip {
         trip_id: props.trip_id,
         stops: Vec::new(),
+        delay_minutes: None,
+        position: TripPosition::NotStarted,
     });
     let host = host();
     let trip_id = props.trip_id;
 
-    let live = live_status(&host);
+    let live = trip_live_status(&host, trip_id);
 
+    // The schedule doesn't change, only the much smaller live status is polled
     crate::fetch::fetch(format!("{host}/api/trip?id={trip_id}"), trip.clone());
+    let trip = match live.stops() {
+        Some(live) => trip.with_live(live, now()),
+        None => (*trip).clone(),
+    };
+
+    // The train keeps moving between updates
+    let position = trip.position_at(now());
 
     html! {
         <div class="TripView">
             <h1><TripId id={ props.trip_id } /></h1>
+            <div class="TripView-delay">{ delay_text(trip.delay_minutes) }</div>
+            <a class="AddToCalendar" href={crate::fetch::with_now(&format!("{host}/api/trip/{trip_id}.ics"))} download="">
+                { "Add to calendar" }
+            </a>
 
             <ul>
-            { for trip.stops.iter().map(|s| {
-                let live = live.get(s.station_id, s.trip_id).map(|s| s.departure);
-
-                let time = live.unwrap_or(s.departure);
+            { for trip.stops.iter().enumerate().map(|(index, s)| {
+                let at_stop = position == TripPosition::AtStop { stop_index: index };
 
                 html!{
-                    <li class={ time_class(time) }>
-                        <TimeDisplay scheduled={ s.departure } {live} />
-                        <div class="TripView-box"></div>
-                        <a href={format!("/c/station/{}", s.station_id)}>
-                            { &s.station_name }
+                    <>
+                    <li class={ time_class(s.departure()) }>
+                        { times(s) }
+                        <div class={ classes!("TripView-box", at_stop.then(|| "TripView-train")) }></div>
+                        <a href={format!("/c/station/{}", s.scheduled.station_id)}>
+                            { &s.scheduled.station_name }
                         </a>
+                        { boarding(&s.scheduled) }
+                        { dwell(s.dwell_minutes()) }
                     </li>
+                    { segment(&trip, position, index) }
+                    </>
                 }
             }) }
             </ul>
@@ -53,8 +72,86 @@ pub fn train_view(props: &Props) -> Html {
     }
 }
 
+/// Departure time, with the arrival time before it if the train waits.
+fn times(stop: &TripStop) -> Html {
+    let departure = html! {
+        <TimeDisplay scheduled={ stop.scheduled.departure } live={ stop.live.as_ref().map(|l| l.departure) } />
+    };
+
+    if !stop.waits() {
+        return departure;
+    }
+
+    html! {
+        <>
+            <TimeDisplay scheduled={ stop.scheduled.arrival } live={ stop.live.as_ref().map(|l| l.arrival) } />
+            { " – " }
+            { departure }
+        </>
+    }
+}
+
+fn boarding(stop: &Stop) -> Html {
+    let text = if stop.drop_off_only() {
+        "Drop off only"
+    } else if stop.pickup_only() {
+        "Pick up only"
+    } else {
+        return html! {};
+    };
+
+    html! {
+        <span class="TripView-boarding">{ text }</span>
+    }
+}
+
+fn dwell(minutes: i64) -> Html {
+    if minutes <= 0 {
+        return html! {};
+    }
+
+    html! {
+        <span class="TripView-dwell">{ format!("{} min. stop", minutes) }</span>
+    }
+}
+
+/// The time from `Trip::stops[stop_index]` to the next stop, with the train
+/// part of the way along if that's where it is.
+fn segment(trip: &Trip, position: TripPosition, stop_index: usize) -> Html {
+    let minutes = match trip.segment_minutes(stop_index) {
+        Some(minutes) => minutes,
+        None => return html! {},
+    };
+
+    let train = match position {
+        TripPosition::Between {
+            stop_index: at,
+            progress,
+        } if at == stop_index => html! {
+            <div class="TripView-train" style={ format!("top: {:.0}%", progress * 100.0) }></div>
+        },
+        _ => html! {},
+    };
+
+    html! {
+        <li class={ classes!("TripView-segment", time_class(trip.stops[stop_index + 1].arrival())) }>
+            { train }
+            { format!("{} min.", minutes) }
+        </li>
+    }
+}
+
+fn delay_text(delay_minutes: Option<i64>) -> String {
+    match delay_minutes {
+        None => String::new(),
+        Some(0) => String::from("On time"),
+        Some(minutes) if minutes > 0 => format!("{} min. late", minutes),
+        Some(minutes) => format!("{} min. early", -minutes),
+    }
+}
+
 fn time_class(time: DateTime<FixedOffset>) -> &'static str {
-    if time.signed_duration_since(now()) > Duration::seconds(0) {
+    if time.signed_duration_since(now()) > chrono::Duration::seconds(0) {
         ""
     } else {
         "TrainView--timePast"
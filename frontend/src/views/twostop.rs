@@ -1,6 +1,10 @@
 use std::time::Duration;
 
-use crate::{time, views::time_display::TimeDisplay};
+use crate::{
+    context::host,
+    time,
+    views::{platform_display::PlatformDisplay, reminder::RemindButton, time_display::TimeDisplay},
+};
 use train_schedules_common::*;
 use yew::prelude::*;
 
@@ -28,13 +32,28 @@ pub fn view(props: &TwostopProperties) -> Html {
 
     let transit_time = (arrive - depart).num_minutes().abs();
 
+    let host = host();
+    let calendar_url = format!(
+        "{host}/api/trip/{}.ics?start={}&end={}",
+        twostop.trip_id, twostop.start.station_id, twostop.end.station_id
+    );
+    let weekly_calendar_url = crate::fetch::with_now(&format!("{calendar_url}&recurring=true"));
+    let calendar_url = crate::fetch::with_now(&calendar_url);
+
     html! {
         <div class={ classes!("TripDisplay") }>
             <TripId id={ twostop.trip_id } />
             <div class="MinsToDepart">{ format!("{} min.", time_to_departure) }</div>
+            <PlatformDisplay scheduled={ twostop.start.clone() } live={ props.start_live.clone() } />
             <div class="DepartTime">{"Departing "}<TimeDisplay scheduled={ twostop.start.departure } live={depart_live} /></div>
             <div class="ArrivalTime">{"Arriving "}<TimeDisplay scheduled={ twostop.end.arrival } live={arrival_live} /></div>
             <div class="TransitTime">{ format!("{} min. in transit", transit_time) }</div>
+            <div class="AddToCalendar">
+                <a href={calendar_url} download="">{ "Add to calendar" }</a>
+                {" · "}
+                <a href={weekly_calendar_url} download="">{ "Weekly" }</a>
+            </div>
+            <RemindButton trip_id={ twostop.trip_id } station_id={ twostop.start.station_id } />
         </div>
     }
 }
@@ -47,12 +66,7 @@ pub struct TripIdProps {
 #[function_component(TripId)]
 pub fn train_id(props: &TripIdProps) -> Html {
     let href = format!("/c/trip/{}", props.id);
-    let class = match props.id / 100 {
-        1 | 4 => "local",
-        2 => "limited",
-        3 | 8 => "bullet",
-        _ => "",
-    };
+    let class = train_type(props.id).unwrap_or("");
 
     html! {
         <a {href}><div class={ classes!("TrainID", class) }>{ props.id }</div></a>
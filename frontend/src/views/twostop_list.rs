@@ -1,7 +1,9 @@
 use crate::context::host;
-use crate::live_status::live_status;
+use crate::live_status::station_live_status;
 use crate::time;
-use crate::views::{station_list::StationFilterList, twostop::Twostop};
+use crate::views::{
+    favorites::FavoriteCommuteButton, station_list::StationFilterList, twostop::Twostop,
+};
 use serde::Serialize;
 use train_schedules_common::*;
 use yew::prelude::*;
@@ -15,15 +17,61 @@ pub struct TwostopListProps {
 
 #[function_component(TwostopList)]
 pub fn view(props: &TwostopListProps) -> Html {
-    let twostops = use_state_eq(TwoStopList::default);
     let host = host();
 
     let stations = use_state_eq::<Vec<Station>, _>(Vec::new);
 
     crate::fetch::fetch(format!("{host}/api/stations"), stations.clone());
+
+    let flipped_url = format!("/c/station/{}/{}", props.end, props.start);
+
+    let name = |id: i64| {
+        stations
+            .iter()
+            .find(|s| s.station_id == id)
+            .map(|s| s.name.clone())
+            .unwrap_or_default()
+    };
+
+    html! {
+        <div class="TripList">
+            <h1>
+                {name(props.start)}
+                {" "}
+                <a classes="DirectionFlip" href={flipped_url}>
+                    {"→"}
+                </a>
+                {" "}
+                {name(props.end)}
+                {" "}
+                <FavoriteCommuteButton start={props.start} end={props.end} />
+            </h1>
+            <h2>{ "Next 5 trips" }</h2>
+            <UpcomingTwostops start={props.start} end={props.end} count={5} />
+
+            <h2>{ "Filter by ending station" }</h2>
+            <StationFilterList start_station_id={props.start} stations={(*stations).clone()} />
+        </div>
+    }
+}
+
+#[derive(Properties, Clone, PartialEq, Debug)]
+pub struct UpcomingTwostopsProps {
+    pub start: i64,
+    pub end: i64,
+    pub count: usize,
+}
+
+/// The next `count` trips from `start` to `end` that have not departed yet.
+#[function_component(UpcomingTwostops)]
+pub fn upcoming_twostops(props: &UpcomingTwostopsProps) -> Html {
+    let twostops = use_state_eq(TwoStopList::default);
+    let host = host();
+
     let now = time::now();
 
-    let live = live_status(&host);
+    let start_live = station_live_status(&host, props.start);
+    let end_live = station_live_status(&host, props.end);
 
     crate::fetch::fetch(
         format!(
@@ -35,46 +83,29 @@ pub fn view(props: &TwostopListProps) -> Html {
 
     // TODO: hide twostops that already completed with some kind of time filtering and interval
 
-    let flipped_url = format!(
-        "/c/station/{}/{}",
-        twostops.end.station_id, twostops.start.station_id
-    );
-
     let twostops_upcoming = twostops
         .trips
         .iter()
         .filter(|twostop| {
-            let start_live = live.get(twostop.start.station_id, twostop.trip_id);
-            let time = start_live.unwrap_or_else(|| twostop.start.clone());
+            let time = start_live
+                .get(&twostop.start)
+                .unwrap_or_else(|| twostop.start.clone());
 
             time.departure > now
         })
-        .take(5);
+        .take(props.count);
 
     html! {
-        <div class="TripList">
-            <h1>
-                {twostops.start.name.clone()}
-                {" "}
-                <a classes="DirectionFlip" href={flipped_url}>
-                    {"→"}
-                </a>
-                {" "}
-                {twostops.end.name.clone()}
-            </h1>
-            <h2>{ "Next 5 trips" }</h2>
+        <>
             { for twostops_upcoming.map(|twostop| {
                 let twostop = twostop.clone();
-                let start_live = live.get(twostop.start.station_id, twostop.trip_id);
-                let end_live = live.get(twostop.end.station_id, twostop.trip_id);
+                let start_live = start_live.get(&twostop.start);
+                let end_live = end_live.get(&twostop.end);
 
                 html! {
                     <Twostop {twostop} {start_live} {end_live} />
                 }
             })}
-
-            <h2>{ "Filter by ending station" }</h2>
-            <StationFilterList start_station_id={props.start} stations={(*stations).clone()} />
-        </div>
+        </>
     }
 }
@@ -0,0 +1,122 @@
+// Service worker keeping the app shell and schedule bundle available offline.
+// The app itself answers API requests from the bundle when the network fails,
+// so the only API response cached here is the bundle.
+
+const CACHE = "trains-v1";
+const BUNDLE_PATH = "/api/schedule-bundle";
+
+// Absolute asset paths referenced from the app shell, including the hashed
+// wasm and js files trunk generates
+const ASSET_PATTERN = /["'](\/[^"'\s]+\.(?:js|wasm|css|png|ico|svg|webmanifest|xml))["']/g;
+
+self.addEventListener("install", (event) => {
+  event.waitUntil(precache().then(() => self.skipWaiting()));
+});
+
+self.addEventListener("activate", (event) => {
+  event.waitUntil(
+    caches
+      .keys()
+      .then((keys) => Promise.all(keys.filter((key) => key !== CACHE).map((key) => caches.delete(key))))
+      .then(() => self.clients.claim())
+  );
+});
+
+self.addEventListener("fetch", (event) => {
+  const request = event.request;
+  const url = new URL(request.url);
+
+  if (request.method !== "GET" || url.origin !== self.location.origin) {
+    return;
+  }
+
+  if (url.pathname.startsWith("/api/")) {
+    if (url.pathname === BUNDLE_PATH) {
+      event.respondWith(networkFirst(request));
+    }
+    return;
+  }
+
+  if (request.mode === "navigate") {
+    event.respondWith(fetch(request).catch(() => caches.match("/")));
+    return;
+  }
+
+  event.respondWith(staleWhileRevalidate(request));
+});
+
+async function precache() {
+  const cache = await caches.open(CACHE);
+  const response = await fetch("/");
+  const html = await response.clone().text();
+
+  await cache.put("/", response);
+
+  const assets = new Set(Array.from(html.matchAll(ASSET_PATTERN), (match) => match[1]));
+  await Promise.all(Array.from(assets, (asset) => cache.add(asset).catch(() => undefined)));
+}
+
+async function networkFirst(request) {
+  const cache = await caches.open(CACHE);
+
+  try {
+    const response = await fetch(request);
+    if (response.ok) {
+      await cache.put(BUNDLE_PATH, response.clone());
+    }
+    return response;
+  } catch (e) {
+    const cached = await cache.match(BUNDLE_PATH);
+    if (cached) {
+      return cached;
+    }
+    throw e;
+  }
+}
+
+async function staleWhileRevalidate(request) {
+  const cache = await caches.open(CACHE);
+  const cached = await cache.match(request);
+
+  const network = fetch(request).then((response) => {
+    if (response.ok) {
+      cache.put(request, response.clone());
+    }
+    return response;
+  });
+
+  if (cached) {
+    network.catch(() => undefined);
+    return cached;
+  }
+
+  return network;
+}
+
+self.addEventListener("push", (event) => {
+  const message = event.data ? event.data.json() : {};
+
+  event.waitUntil(
+    self.registration.showNotification(message.title || "Train reminder", {
+      body: message.body,
+      icon: "/android-chrome-192x192.png",
+      data: { url: message.url || "/" },
+    })
+  );
+});
+
+self.addEventListener("notificationclick", (event) => {
+  event.notification.close();
+
+  const url = event.notification.data.url;
+
+  event.waitUntil(
+    self.clients.matchAll({ type: "window" }).then((windows) => {
+      const existing = windows.find((w) => new URL(w.url).pathname === url);
+      if (existing) {
+        return existing.focus();
+      }
+      return self.clients.openWindow(url);
+    })
+  );
+});